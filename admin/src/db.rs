@@ -0,0 +1,63 @@
+use deadpool_redis::redis::{self, AsyncCommands as _};
+use records_lib::{migrations, redis_key::LEGACY_MAP_KEYS_PATTERN, MySqlPool, RedisPool};
+
+/// The maximum amount of keys removed in a single command.
+const DEL_BATCH_SIZE: usize = 1000;
+
+pub async fn migrate(mysql_pool: MySqlPool) -> anyhow::Result<()> {
+    tracing::info!("Applying pending migrations...");
+    migrations::migrate(&mysql_pool).await?;
+    tracing::info!("Database is up to date");
+    Ok(())
+}
+
+pub async fn status(mysql_pool: MySqlPool) -> anyhow::Result<()> {
+    let migrations = migrations::status(&mysql_pool).await?;
+
+    for migration in &migrations {
+        let status = match (migration.applied, migration.checksum_mismatch) {
+            (true, false) => "applied",
+            (true, true) => "applied (modified since)",
+            (false, _) => "pending",
+        };
+        println!(
+            "{} {:<40} {status}",
+            migration.version, migration.description
+        );
+    }
+
+    let pending = migrations.iter().filter(|m| !m.applied).count();
+    tracing::info!(
+        "{} migration(s) in total, {pending} pending",
+        migrations.len()
+    );
+
+    Ok(())
+}
+
+pub async fn clear_legacy_leaderboards(redis_pool: RedisPool) -> anyhow::Result<()> {
+    let mut redis_conn = redis_pool.get().await?;
+
+    // The keys are iterated with SCAN, so the Redis server isn't blocked like with KEYS
+    let keys: Vec<String> = {
+        let mut iter = redis_conn
+            .scan_match::<_, String>(LEGACY_MAP_KEYS_PATTERN)
+            .await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        keys
+    };
+
+    for batch in keys.chunks(DEL_BATCH_SIZE) {
+        redis::cmd("DEL")
+            .arg(batch)
+            .query_async::<_, ()>(&mut redis_conn)
+            .await?;
+    }
+
+    tracing::info!("Removed {} legacy leaderboard(s)", keys.len());
+
+    Ok(())
+}
@@ -1,22 +1,52 @@
 use clap::Parser;
 use mkenv::Env as _;
-use records_lib::{get_mysql_pool, get_redis_pool, Database, DbEnv, LibEnv};
+use records_lib::{
+    get_mysql_pool, get_redis_pool, mx::MxClient, Database, DbEnv, DbUrlEnv, LibEnv, RedisUrlEnv,
+};
 
-use self::{clear::ClearCommand, populate::PopulateCommand};
+use self::{
+    clear::ClearCommand,
+    populate::PopulateCommand,
+    scoring::{EventScoringCommand, MappackScoringCommand},
+};
 
 mod clear;
+mod db;
 mod populate;
+mod scoring;
 
 #[derive(clap::Parser)]
 enum Command {
     #[clap(subcommand)]
     Event(EventCommand),
+    #[clap(subcommand)]
+    Mappack(MappackCommand),
+    #[clap(subcommand)]
+    Db(DbCommand),
 }
 
 #[derive(clap::Subcommand)]
 enum EventCommand {
     Populate(PopulateCommand),
     Clear(ClearCommand),
+    /// Sets the scoring strategy of the event edition.
+    Scoring(EventScoringCommand),
+}
+
+#[derive(clap::Subcommand)]
+enum MappackCommand {
+    /// Sets the scoring strategy of the MX mappack.
+    Scoring(MappackScoringCommand),
+}
+
+#[derive(clap::Subcommand)]
+enum DbCommand {
+    /// Applies the pending migrations to the database.
+    Migrate,
+    /// Shows the status of each migration.
+    Status,
+    /// Removes the leaderboards saved in Redis before the scores included a tie-break.
+    ClearLegacyLeaderboards,
 }
 
 mkenv::make_env!(Env includes [DbEnv as db_env, LibEnv as lib_env]:);
@@ -28,22 +58,55 @@ async fn main() -> anyhow::Result<()> {
         .compact()
         .try_init()
         .map_err(|e| anyhow::anyhow!("unable to init tracing_subscriber: {e}"))?;
-    let env = Env::try_get()?;
-    records_lib::init_env(env.lib_env);
-
-    let db = Database {
-        mysql_pool: get_mysql_pool(env.db_env.db_url.db_url).await?,
-        redis_pool: get_redis_pool(env.db_env.redis_url.redis_url)?,
-    };
 
     let cmd = Command::parse();
 
-    let client = reqwest::Client::new();
-
     match cmd {
-        Command::Event(event) => match event {
-            EventCommand::Populate(cmd) => populate::populate(client, db, cmd).await?,
-            EventCommand::Clear(cmd) => clear::clear(db, cmd).await?,
+        Command::Event(event) => {
+            let env = Env::try_get()?;
+            records_lib::init_env(env.lib_env);
+
+            let db = Database {
+                mysql_pool: get_mysql_pool(env.db_env.db_url.db_url).await?,
+                redis_pool: get_redis_pool(env.db_env.redis_url.redis_url)?,
+            };
+
+            let mx = MxClient::from_env(reqwest::Client::new());
+
+            match event {
+                EventCommand::Populate(cmd) => populate::populate(mx, db, cmd).await?,
+                EventCommand::Clear(cmd) => clear::clear(db, cmd).await?,
+                EventCommand::Scoring(cmd) => scoring::set_event_scoring(db, cmd).await?,
+            }
+        }
+        Command::Mappack(mappack) => {
+            let env = Env::try_get()?;
+            records_lib::init_env(env.lib_env);
+
+            let db = Database {
+                mysql_pool: get_mysql_pool(env.db_env.db_url.db_url).await?,
+                redis_pool: get_redis_pool(env.db_env.redis_url.redis_url)?,
+            };
+
+            match mappack {
+                MappackCommand::Scoring(cmd) => scoring::set_mappack_scoring(db, cmd).await?,
+            }
+        }
+        Command::Db(cmd) => match cmd {
+            // These commands only need the MySQL/MariaDB database
+            DbCommand::Migrate => {
+                let env = DbUrlEnv::try_get()?;
+                db::migrate(get_mysql_pool(env.db_url).await?).await?
+            }
+            DbCommand::Status => {
+                let env = DbUrlEnv::try_get()?;
+                db::status(get_mysql_pool(env.db_url).await?).await?
+            }
+            // This one only needs the Redis database
+            DbCommand::ClearLegacyLeaderboards => {
+                let env = RedisUrlEnv::try_get()?;
+                db::clear_legacy_leaderboards(get_redis_pool(env.redis_url)?).await?
+            }
         },
     }
 
@@ -8,6 +8,7 @@ use records_lib::{
     event, map,
     mappack::{self, AnyMappackId},
     models, must,
+    mx::{self, MxClient, MxMap},
     redis_key::mappack_key,
     Database, DatabaseConnection, MySqlPool,
 };
@@ -69,21 +70,9 @@ struct Row {
     transitive_save: Option<bool>,
 }
 
-#[derive(serde::Deserialize)]
-struct MxMapItem {
-    #[serde(rename = "TrackID")]
-    mx_id: i64,
-    #[serde(rename = "AuthorLogin")]
-    author_login: String,
-    #[serde(rename = "TrackUID")]
-    map_uid: String,
-    #[serde(rename = "GbxMapName")]
-    name: String,
-}
-
 async fn insert_mx_maps(
     db: &MySqlPool,
-    mx_maps: &[MxMapItem],
+    mx_maps: &[MxMap],
 ) -> anyhow::Result<Vec<(i64, models::Map)>> {
     let mut out = Vec::with_capacity(mx_maps.len());
 
@@ -151,9 +140,9 @@ impl Iterator for MxIdIter {
     }
 }
 
-#[tracing::instrument(skip(client, pool, rows))]
+#[tracing::instrument(skip(mx, pool, rows))]
 async fn populate_mx_maps(
-    client: &reqwest::Client,
+    mx: &MxClient,
     pool: &MySqlPool,
     rows: &[(Row, u64)],
 ) -> anyhow::Result<HashMap<i64, models::Map>> {
@@ -174,22 +163,13 @@ async fn populate_mx_maps(
                 }),
             ) => MxIdIter::single(*mx_id),
         })
-        .chunks(25);
+        .chunks(mx::MAX_MAPS_INFO);
 
     let mx_ids: Vec<_> = stream::iter(&mx_ids)
-        .map(|mut chunk| async move {
-            let url = format!(
-                "https://sm.mania.exchange/api/maps/get_map_info/multi/{}",
-                chunk.join(",")
-            );
-            tracing::info!("Requesting MX ({})...", url);
-            let mx_maps = client
-                .get(url)
-                .header("User-Agent", "obstacle (discord @ahmadbky)")
-                .send()
-                .await?
-                .json::<Vec<MxMapItem>>()
-                .await?;
+        .map(|chunk| async move {
+            let chunk = chunk.collect::<Vec<_>>();
+            tracing::info!("Requesting MX ({} maps)...", chunk.len());
+            let mx_maps = mx.maps_info(&chunk).await?;
             insert_mx_maps(pool, &mx_maps).await
         })
         .buffer_unordered(rows.len())
@@ -200,7 +180,7 @@ async fn populate_mx_maps(
 }
 
 pub async fn populate(
-    client: reqwest::Client,
+    mx: MxClient,
     db: Database,
     PopulateCommand {
         event_handle,
@@ -244,24 +224,23 @@ pub async fn populate(
                 (None, None) => anyhow::bail!("no MX id provided"),
             };
 
-            let maps =
-                map::fetch_mx_mappack_maps(&client, mx_id as _, edition.mx_secret.as_deref())
-                    .await?;
+            let maps = mx
+                .mappack_maps(mx_id as _, edition.mx_secret.as_deref())
+                .await?;
 
             tracing::info!("Found {} map(s) in MX mappack with ID {mx_id}", maps.len());
 
             for map in maps {
-                let player = must::have_player(&mut conn.mysql_conn, &map.AuthorLogin).await?;
-                let map_id = match map::get_map_from_uid(&mut conn.mysql_conn, &map.TrackUID)
-                    .await?
+                let player = must::have_player(&mut conn.mysql_conn, &map.author_login).await?;
+                let map_id = match map::get_map_from_uid(&mut conn.mysql_conn, &map.map_uid).await?
                 {
                     Some(map) => map.id,
                     None => sqlx::query_scalar(
                         "insert into maps (game_id, player_id, name) values (?, ?, ?) returning id",
                     )
-                    .bind(&map.TrackUID)
+                    .bind(&map.map_uid)
                     .bind(player.id)
-                    .bind(&map.GbxMapName)
+                    .bind(&map.name)
                     .fetch_one(&mut *conn.mysql_conn)
                     .await?,
                 };
@@ -273,7 +252,7 @@ pub async fn populate(
                 .bind(event.id)
                 .bind(edition.id)
                 .bind(map_id)
-                .bind(map.MapID)
+                .bind(map.mx_id)
                 .execute(&mut *conn.mysql_conn)
                 .await?;
             }
@@ -308,7 +287,7 @@ pub async fn populate(
 
     conn.mysql_conn.close().await?;
 
-    let mut mx_maps = populate_mx_maps(&client, &db.mysql_pool, &rows).await?;
+    let mut mx_maps = populate_mx_maps(&mx, &db.mysql_pool, &rows).await?;
 
     let mut conn = DatabaseConnection {
         mysql_conn: db.mysql_pool.acquire().await?,
@@ -0,0 +1,78 @@
+use deadpool_redis::redis::AsyncCommands as _;
+use records_lib::{
+    mappack::{self, AnyMappackId},
+    must,
+    redis_key::{mappack_key, mappack_scoring_key},
+    scoring::ScoringStrategy,
+    Database,
+};
+
+#[derive(clap::Args)]
+pub struct EventScoringCommand {
+    event_handle: String,
+    event_edition: u32,
+    /// The scoring strategy, e.g. `average_rank`, `points_table`, `points_table:10,6,4,3,2,1`,
+    /// `medal_points` or `best_of:5`.
+    scoring: ScoringStrategy,
+}
+
+#[derive(clap::Args)]
+pub struct MappackScoringCommand {
+    mappack_id: String,
+    /// The scoring strategy, e.g. `average_rank`, `points_table`, `points_table:10,6,4,3,2,1`,
+    /// `medal_points` or `best_of:5`.
+    scoring: ScoringStrategy,
+}
+
+pub async fn set_event_scoring(
+    db: Database,
+    EventScoringCommand {
+        event_handle,
+        event_edition,
+        scoring,
+    }: EventScoringCommand,
+) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await?;
+
+    let (event, mut edition) =
+        must::have_event_edition(&mut conn.mysql_conn, &event_handle, event_edition).await?;
+
+    sqlx::query("update event_edition set scoring = ? where event_id = ? and id = ?")
+        .bind(scoring.to_string())
+        .bind(event.id)
+        .bind(edition.id)
+        .execute(&mut *conn.mysql_conn)
+        .await?;
+
+    edition.scoring = scoring;
+
+    tracing::info!("Updating the scores of the event edition...");
+    mappack::update_mappack(AnyMappackId::Event(&event, &edition), &mut conn).await?;
+
+    Ok(())
+}
+
+pub async fn set_mappack_scoring(
+    db: Database,
+    MappackScoringCommand {
+        mappack_id,
+        scoring,
+    }: MappackScoringCommand,
+) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await?;
+    let mappack = AnyMappackId::Id(&mappack_id);
+
+    conn.redis_conn
+        .set::<_, _, ()>(mappack_scoring_key(mappack), scoring.to_string())
+        .await?;
+
+    // The scores are calculated when the mappack is loaded, so we only update them
+    // if it is already loaded.
+    let loaded: bool = conn.redis_conn.exists(mappack_key(mappack)).await?;
+    if loaded {
+        tracing::info!("Updating the scores of the mappack...");
+        mappack::update_mappack(mappack, &mut conn).await?;
+    }
+
+    Ok(())
+}
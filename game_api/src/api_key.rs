@@ -0,0 +1,215 @@
+//! The API keys of the third-party consumers of the API.
+//!
+//! The community tools (Discord bots, stats websites, etc) can be issued an API key by an admin.
+//! They send it in the [`API_KEY_HEADER`] header of their requests, which identifies them,
+//! and restricts the data they can read to the scopes of the key.
+//!
+//! The requests without an API key are still accepted. But if a key is provided, it must be valid,
+//! and have the scopes required by the endpoint (see [`ApiKeyGuard`](crate::auth::ApiKeyGuard)).
+//! Each use of a key is counted.
+//!
+//! Only the SHA-256 hash of a key is saved in the database, so the key itself is only returned
+//! once, when it is issued.
+
+use async_graphql::Enum;
+use records_lib::{models, Database};
+use serde::Deserialize;
+use sha256::digest;
+use sqlx::MySqlConnection;
+
+use crate::{utils::generate_token, RecordsErrorKind, RecordsResult, RecordsResultExt};
+
+/// The header containing the API key of a request.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// The length of the generated API keys.
+const API_KEY_LEN: usize = 48;
+
+/// A set of [`Scope`]s, coded as a bitmask.
+pub type Scopes = u8;
+
+/// A scope of an API key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Deserialize)]
+#[graphql(name = "ApiKeyScope")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum Scope {
+    /// Read the leaderboards of the maps, mappacks and event editions.
+    ReadLeaderboards,
+    /// Read the information about the players.
+    ReadPlayers,
+    /// Read the events and their editions.
+    ReadEvents,
+}
+
+impl Scope {
+    /// All the scopes.
+    pub const ALL: [Self; 3] = [Self::ReadLeaderboards, Self::ReadPlayers, Self::ReadEvents];
+
+    /// Returns the flag of the scope in a [`Scopes`] set.
+    pub const fn flag(self) -> Scopes {
+        1 << self as Scopes
+    }
+
+    /// Returns the name of the scope, as saved in the database.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ReadLeaderboards => "read_leaderboards",
+            Self::ReadPlayers => "read_players",
+            Self::ReadEvents => "read_events",
+        }
+    }
+
+    /// Returns the scope with the provided name, as saved in the database.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+/// No scope, the key only has to be valid.
+pub const NONE: Scopes = 0;
+pub const READ_LEADERBOARDS: Scopes = Scope::ReadLeaderboards.flag();
+pub const READ_PLAYERS: Scopes = Scope::ReadPlayers.flag();
+pub const READ_EVENTS: Scopes = Scope::ReadEvents.flag();
+
+/// An API key which has been checked, with its scopes.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckedApiKey {
+    /// The ID of the API key.
+    pub id: u32,
+    /// The scopes of the API key.
+    pub scopes: Scopes,
+}
+
+/// Returns the scopes of the API key with the provided ID.
+pub async fn scopes_of(
+    mysql_conn: &mut MySqlConnection,
+    api_key_id: u32,
+) -> RecordsResult<Vec<Scope>> {
+    let names: Vec<String> =
+        sqlx::query_scalar("SELECT scope FROM api_key_scopes WHERE api_key_id = ?")
+            .bind(api_key_id)
+            .fetch_all(mysql_conn)
+            .await
+            .with_api_err()?;
+
+    Ok(names
+        .iter()
+        .filter_map(|name| Scope::from_name(name))
+        .collect())
+}
+
+/// Returns the ID of the provided API key, or `None` if it is unknown or revoked.
+///
+/// Unlike [`check_api_key`], its use isn't counted.
+pub async fn api_key_id(mysql_conn: &mut MySqlConnection, key: &str) -> RecordsResult<Option<u32>> {
+    sqlx::query_scalar("SELECT id FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL")
+        .bind(digest(key))
+        .fetch_optional(mysql_conn)
+        .await
+        .with_api_err()
+}
+
+/// Checks the provided API key, and counts its use.
+///
+/// # Returns
+///
+/// * If the key is unknown or revoked, it returns an `Unauthorized` error
+/// * If the key hasn't the required scopes, it returns a `Forbidden` error
+/// * Otherwise, it returns the checked key
+pub async fn check_api_key(
+    db: &Database,
+    key: &str,
+    required: Scopes,
+) -> RecordsResult<CheckedApiKey> {
+    let mut mysql_conn = db.mysql_pool.acquire().await.with_api_err()?;
+
+    let Some(id) = api_key_id(&mut mysql_conn, key).await? else {
+        return Err(RecordsErrorKind::Unauthorized);
+    };
+
+    let scopes = scopes_of(&mut mysql_conn, id)
+        .await?
+        .into_iter()
+        .fold(NONE, |scopes, s| scopes | s.flag());
+    if scopes & required != required {
+        return Err(RecordsErrorKind::Forbidden);
+    }
+
+    sqlx::query(
+        "UPDATE api_keys SET usage_count = usage_count + 1, last_used_at = SYSDATE()
+        WHERE id = ?",
+    )
+    .bind(id)
+    .execute(&mut *mysql_conn)
+    .await
+    .with_api_err()?;
+
+    Ok(CheckedApiKey { id, scopes })
+}
+
+/// Returns the API key with the provided ID.
+pub async fn get_api_key(
+    mysql_conn: &mut MySqlConnection,
+    api_key_id: u32,
+) -> RecordsResult<models::ApiKey> {
+    sqlx::query_as("SELECT * FROM api_keys WHERE id = ?")
+        .bind(api_key_id)
+        .fetch_optional(mysql_conn)
+        .await
+        .with_api_err()?
+        .ok_or(RecordsErrorKind::ApiKeyNotFound(api_key_id))
+}
+
+/// Issues a new API key with the provided scopes.
+///
+/// It returns the saved key, and the key itself, which can't be retrieved afterwards.
+pub async fn create_api_key(
+    mysql_conn: &mut MySqlConnection,
+    name: &str,
+    scopes: &[Scope],
+    created_by: u32,
+) -> RecordsResult<(models::ApiKey, String)> {
+    let key = generate_token(API_KEY_LEN);
+
+    let id: u32 = sqlx::query_scalar(
+        "INSERT INTO api_keys (name, key_hash, created_by, created_at)
+        VALUES (?, ?, ?, SYSDATE()) RETURNING id",
+    )
+    .bind(name)
+    .bind(digest(&*key))
+    .bind(created_by)
+    .fetch_one(&mut *mysql_conn)
+    .await
+    .with_api_err()?;
+
+    for scope in scopes {
+        sqlx::query("INSERT IGNORE INTO api_key_scopes (api_key_id, scope) VALUES (?, ?)")
+            .bind(id)
+            .bind(scope.name())
+            .execute(&mut *mysql_conn)
+            .await
+            .with_api_err()?;
+    }
+
+    let api_key = get_api_key(mysql_conn, id).await?;
+    Ok((api_key, key))
+}
+
+/// Revokes the API key with the provided ID.
+///
+/// It returns the key as it was before being revoked.
+pub async fn revoke_api_key(
+    mysql_conn: &mut MySqlConnection,
+    api_key_id: u32,
+) -> RecordsResult<models::ApiKey> {
+    let api_key = get_api_key(mysql_conn, api_key_id).await?;
+
+    sqlx::query("UPDATE api_keys SET revoked_at = COALESCE(revoked_at, SYSDATE()) WHERE id = ?")
+        .bind(api_key_id)
+        .execute(mysql_conn)
+        .await
+        .with_api_err()?;
+
+    Ok(api_key)
+}
@@ -0,0 +1,138 @@
+//! The audit log of the privileged operations.
+//!
+//! Every handler changing sensitive data (bans, roles, notes, resources, events, moderation of
+//! the records, tokens and API keys) saves an entry with the player who made the change,
+//! the target of the change, and the state of the target before and after it.
+
+use async_graphql::Enum;
+use serde::Serialize;
+use sqlx::MySqlConnection;
+use tracing_actix_web::RequestId;
+
+use crate::{RecordsResult, RecordsResultExt};
+
+/// An action saved in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum Action {
+    Ban,
+    Unban,
+    SetRole,
+    DelNote,
+    UpdateResourcesContent,
+    InvalidateRecord,
+    RestoreRecord,
+    ReviewAcReport,
+    ReviewSuspiciousRecord,
+    UpdateEventEdition,
+    RevokeTokens,
+    CreateApiKey,
+    RevokeApiKey,
+}
+
+impl Action {
+    /// Returns the name of the action, as saved in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ban => "ban",
+            Self::Unban => "unban",
+            Self::SetRole => "set_role",
+            Self::DelNote => "del_note",
+            Self::UpdateResourcesContent => "update_resources_content",
+            Self::InvalidateRecord => "invalidate_record",
+            Self::RestoreRecord => "restore_record",
+            Self::ReviewAcReport => "review_ac_report",
+            Self::ReviewSuspiciousRecord => "review_suspicious_record",
+            Self::UpdateEventEdition => "update_event_edition",
+            Self::RevokeTokens => "revoke_tokens",
+            Self::CreateApiKey => "create_api_key",
+            Self::RevokeApiKey => "revoke_api_key",
+        }
+    }
+
+    /// Returns the action with the provided name, as saved in the database.
+    pub fn from_name(s: &str) -> Option<Self> {
+        [
+            Self::Ban,
+            Self::Unban,
+            Self::SetRole,
+            Self::DelNote,
+            Self::UpdateResourcesContent,
+            Self::InvalidateRecord,
+            Self::RestoreRecord,
+            Self::ReviewAcReport,
+            Self::ReviewSuspiciousRecord,
+            Self::UpdateEventEdition,
+            Self::RevokeTokens,
+            Self::CreateApiKey,
+            Self::RevokeApiKey,
+        ]
+        .into_iter()
+        .find(|action| action.as_str() == s)
+    }
+}
+
+/// Returns the audit log target of a player.
+pub fn player_target(login: &str) -> String {
+    format!("player:{login}")
+}
+
+/// Returns the audit log target of a record.
+pub fn record_target(record_id: u32) -> String {
+    format!("record:{record_id}")
+}
+
+/// Returns the audit log target of an anticheat report.
+pub fn ac_report_target(report_id: u32) -> String {
+    format!("ac_report:{report_id}")
+}
+
+/// Returns the audit log target of an event edition.
+pub fn edition_target(event_handle: &str, edition_id: u32) -> String {
+    format!("event_edition:{event_handle}/{edition_id}")
+}
+
+/// Returns the audit log target of an API key.
+pub fn api_key_target(api_key_id: u32) -> String {
+    format!("api_key:{api_key_id}")
+}
+
+/// The audit log target of the resources content.
+pub const RESOURCES_CONTENT_TARGET: &str = "resources_content";
+
+fn to_json<T: Serialize>(state: &T) -> Option<String> {
+    let state = serde_json::to_value(state).expect("audit log state should be serializable");
+    (!state.is_null()).then(|| state.to_string())
+}
+
+/// Saves an entry in the audit log.
+///
+/// The entry must be saved in the same transaction as the change in the MySQL/MariaDB database,
+/// so a change is never saved without its entry, and conversely.
+///
+/// The states are serialized in JSON. A state serialized to `null` is saved as `NULL`.
+pub async fn log<B: Serialize, A: Serialize>(
+    mysql_conn: &mut MySqlConnection,
+    request_id: RequestId,
+    actor_id: u32,
+    action: Action,
+    target: &str,
+    before: &B,
+    after: &A,
+) -> RecordsResult<()> {
+    sqlx::query(
+        "INSERT INTO audit_log
+                (actor_id,  action, target, before_state,   after_state,    request_id, created_at)
+        VALUES  (?,         ?,      ?,      ?,              ?,              ?,          SYSDATE())",
+    )
+    .bind(actor_id)
+    .bind(action.as_str())
+    .bind(target)
+    .bind(to_json(before))
+    .bind(to_json(after))
+    .bind(request_id.to_string())
+    .execute(mysql_conn)
+    .await
+    .with_api_err()?;
+
+    Ok(())
+}
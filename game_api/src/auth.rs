@@ -14,8 +14,8 @@
 //! This goes for the Obstacle gamemode, but for the website, it will be a session id cookie
 //! sent by the browser.
 //!
-//! For each case, the player's role (Player, Mod, Admin) is also checked to correspond
-//! to the required role.
+//! For each case, the permissions of the player are also checked to contain the required ones
+//! (see the [`permission`] module).
 //!
 //! The procedure to initialize the tokens uses the OAuth 2.0 protocol provided by ManiaPlanet.
 //! It has only access to the `basic` scope, meaning the name of the player, his login,
@@ -40,7 +40,12 @@
 //!
 //! The generated tokens have a time-to-live of 6 months. Passed this time, the authentication system
 //! will return an `Unauthorized` error. The gamemode script will have to execute the procedure
-//! from above.
+//! from above, or renew its token before with the `/player/rotate_token` endpoint.
+//!
+//! A player may have several ManiaPlanet tokens at the same time (see [`MAX_MP_TOKENS`]),
+//! one for each game server they logged in with, but only one website token. The website session
+//! can be closed with the `/player/logout` endpoint, and the admins can revoke all the tokens
+//! of a player, e.g. when one of them is compromised.
 //!
 //! So in the documentation, the `/player/give_token` endpoint represents the POST request sent by
 //! the browser ; while `/player/get_token` is the one sent by Obstacle gamemode.
@@ -55,32 +60,112 @@ use std::{collections::HashMap, time::Duration};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
 use chrono::{DateTime, Utc};
-use deadpool_redis::redis::{AsyncCommands, ToRedisArgs};
+use deadpool_redis::redis::{self, aio::MultiplexedConnection, AsyncCommands, ToRedisArgs};
 use futures::Future;
 use records_lib::models::ApiStatusKind;
-use records_lib::redis_key::{mp_token_key, web_token_key};
-use records_lib::Database;
+use records_lib::redis_key::{
+    mp_token_key, mp_tokens_key, oauth_code_key, oauth_result_key, oauth_state_key, web_token_key,
+};
+use records_lib::{Database, RedisConnection};
 use serde::{Deserialize, Serialize};
 use sha256::digest;
-use tokio::sync::oneshot::{self, Receiver, Sender};
-use tokio::sync::Mutex;
-use tokio::time::timeout;
 use tracing::Level;
 use tracing_actix_web::RequestId;
 
+use crate::api_key::{self, CheckedApiKey};
 use crate::utils::{generate_token, get_api_status, ApiStatus};
 use crate::{http::player, RecordsErrorKind, RecordsResult};
 use crate::{
     must, AccessTokenErr, FitRequestId, RecordsError, RecordsResponse, RecordsResultExt, Res,
 };
 
-#[allow(unused)]
-pub mod privilege {
-    pub type Flags = u8;
+/// The permissions of the players.
+///
+/// The permissions are granted to the players by their role, and saved in the `role_permissions`
+/// table by their name. The admins of an event edition are also granted some permissions,
+/// for this edition only (see [`EDITION_ADMIN`]).
+///
+/// The endpoints require a set of permissions, coded as a bitmask, for example with
+/// `MPAuthGuard<{ permission::MANAGE_BANS }>`.
+pub mod permission {
+    use async_graphql::Enum;
+
+    /// A set of [`Permission`]s, coded as a bitmask.
+    pub type Flags = u16;
+
+    /// A permission granted to the players.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+    pub enum Permission {
+        /// Ban and unban the players, and manage their notes.
+        ManageBans,
+        /// Change the role of the players.
+        ManageRoles,
+        /// Edit the content of the "Resources" page of the website.
+        EditResources,
+        /// Edit the event editions.
+        ManageEvents,
+        /// Review the anticheat reports and the suspicious records, and invalidate or restore
+        /// the records.
+        ModerateRecords,
+        /// Read and reset the ratings of the maps of the other players.
+        ManageMaps,
+        /// Read the audit log.
+        ViewAuditLog,
+        /// Issue and revoke the API keys of the third-party consumers of the API.
+        ManageApiKeys,
+    }
+
+    impl Permission {
+        /// All the permissions.
+        pub const ALL: [Self; 8] = [
+            Self::ManageBans,
+            Self::ManageRoles,
+            Self::EditResources,
+            Self::ManageEvents,
+            Self::ModerateRecords,
+            Self::ManageMaps,
+            Self::ViewAuditLog,
+            Self::ManageApiKeys,
+        ];
+
+        /// Returns the flag of the permission in a [`Flags`] set.
+        pub const fn flag(self) -> Flags {
+            1 << self as Flags
+        }
+
+        /// Returns the name of the permission, as saved in the database.
+        pub fn name(self) -> &'static str {
+            match self {
+                Self::ManageBans => "manage_bans",
+                Self::ManageRoles => "manage_roles",
+                Self::EditResources => "edit_resources",
+                Self::ManageEvents => "manage_events",
+                Self::ModerateRecords => "moderate_records",
+                Self::ManageMaps => "manage_maps",
+                Self::ViewAuditLog => "view_audit_log",
+                Self::ManageApiKeys => "manage_api_keys",
+            }
+        }
+
+        /// Returns the permission with the provided name, as saved in the database.
+        pub fn from_name(name: &str) -> Option<Self> {
+            Self::ALL.into_iter().find(|p| p.name() == name)
+        }
+    }
+
+    /// No permission, the player only has to be authenticated.
+    pub const NONE: Flags = 0;
+    pub const MANAGE_BANS: Flags = Permission::ManageBans.flag();
+    pub const MANAGE_ROLES: Flags = Permission::ManageRoles.flag();
+    pub const EDIT_RESOURCES: Flags = Permission::EditResources.flag();
+    pub const MANAGE_EVENTS: Flags = Permission::ManageEvents.flag();
+    pub const MODERATE_RECORDS: Flags = Permission::ModerateRecords.flag();
+    pub const MANAGE_MAPS: Flags = Permission::ManageMaps.flag();
+    pub const VIEW_AUDIT_LOG: Flags = Permission::ViewAuditLog.flag();
+    pub const MANAGE_API_KEYS: Flags = Permission::ManageApiKeys.flag();
 
-    pub const PLAYER: Flags = 0b0001;
-    pub const MOD: Flags = 0b0011;
-    pub const ADMIN: Flags = 0b1111;
+    /// The permissions granted to the admins of an event edition, for this edition only.
+    pub const EDITION_ADMIN: Flags = MANAGE_EVENTS;
 }
 
 pub const WEB_TOKEN_SESS_KEY: &str = "__obs_web_token";
@@ -92,33 +177,9 @@ pub const WEB_TOKEN_SESS_KEY: &str = "__obs_web_token";
 /// POST /player/give_token request with the same state string.
 pub const TIMEOUT: Duration = Duration::from_secs(60 * 5);
 
-/// Represents the current state of a communication between the /player/get_token and
-/// /player/give_token endpoints.
-#[derive(Debug)]
-struct TokenState {
-    /// The sender of the /player/get_token endpoint.
-    tx: Sender<Message>,
-    /// The receiver of the /player/get_token endpoint.
-    rx: Receiver<Message>,
-    /// The instant where this state has been initialized. This is used to prevent
-    /// endpoints to communicate with a `state` string that has already been used for
-    /// other endpoints.
-    instant: DateTime<Utc>,
-}
-
-impl TokenState {
-    fn new(tx: Sender<Message>, rx: Receiver<Message>) -> Self {
-        Self {
-            tx,
-            rx,
-            instant: Utc::now(),
-        }
-    }
-}
-
 /// Represents the messages that are exchanged between the /player/get_token and /player/give_token
 /// endpoints.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum Message {
     /// The /player/give_token gives the code provided by the ManiaPlanet OAuth system
@@ -135,30 +196,125 @@ pub enum Message {
     Ok(WebToken),
 }
 
+fn message_json(message: &Message) -> String {
+    serde_json::to_string(message).expect("auth message should be serializable")
+}
+
+/// Waits for a message in the provided Redis list, for at most [`TIMEOUT`].
+///
+/// It returns `None` if no message has been received in time.
+async fn recv_message(
+    conn: &mut MultiplexedConnection,
+    key: impl ToRedisArgs + Send + Sync,
+) -> RecordsResult<Option<Message>> {
+    let popped: Option<(String, String)> = conn
+        .blpop(key, TIMEOUT.as_secs_f64())
+        .await
+        .with_api_err()?;
+
+    popped
+        .map(|(_, message)| {
+            serde_json::from_str(&message)
+                .map_err(|e| RecordsErrorKind::Unknown(format!("invalid auth message: {e}")))
+        })
+        .transpose()
+}
+
+/// Pushes a message in the provided Redis list, which expires after [`TIMEOUT`]
+/// if it isn't received.
+async fn send_message(
+    conn: &mut MultiplexedConnection,
+    key: impl ToRedisArgs + Send + Sync,
+    message: &Message,
+) -> RecordsResult<()> {
+    redis::pipe()
+        .atomic()
+        .rpush(&key, message_json(message))
+        .ignore()
+        .expire(&key, TIMEOUT.as_secs() as _)
+        .ignore()
+        .query_async(conn)
+        .await
+        .with_api_err()
+}
+
 /// Holds the authentication system state between the endpoints.
 ///
-/// With its internal hash-map, it allows the /player/get_token and /player_give_token endpoints
-/// to communicate with each other, by providing the same `state` string.
-#[derive(Debug, Default)]
+/// It allows the /player/get_token and /player/give_token endpoints to communicate with each other,
+/// by providing the same `state` string. The communication goes through the Redis database,
+/// so both endpoints may be handled by different instances of the API.
+///
+/// The state string is saved in the [`oauth_state_key`] Redis key, which expires after [`TIMEOUT`].
+/// Then, the messages are exchanged through 2 Redis lists, one for each direction
+/// (see [`oauth_code_key`] and [`oauth_result_key`]).
+///
+/// The waiting of a message blocks its Redis connection, so the state holds its own client
+/// rather than using the pool of the database.
+#[derive(Debug)]
 pub struct AuthState {
-    states_map: Mutex<HashMap<String, TokenState>>,
+    client: redis::Client,
+}
+
+/// The connection of the /player/get_token endpoint with the /player/give_token endpoint,
+/// for a state string.
+///
+/// It is returned by the [`AuthState::connect_with_browser`] method.
+pub struct BrowserConnection {
+    conn: MultiplexedConnection,
+    state: String,
+}
+
+impl BrowserConnection {
+    /// Waits for the message of the /player/give_token endpoint, for at most [`TIMEOUT`].
+    ///
+    /// It returns `None` if no message has been received in time.
+    pub async fn recv(&mut self) -> RecordsResult<Option<Message>> {
+        recv_message(&mut self.conn, oauth_code_key(&self.state)).await
+    }
+
+    /// Sends a message to the /player/give_token endpoint.
+    pub async fn send(&mut self, message: Message) -> RecordsResult<()> {
+        send_message(&mut self.conn, oauth_result_key(&self.state), &message).await
+    }
 }
 
 impl AuthState {
-    /// Removes a state from the state map.
+    /// Creates the authentication system state, with the URL of the Redis database.
+    pub fn new(redis_url: &str) -> RecordsResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(redis_url).with_api_err()?,
+        })
+    }
+
+    async fn get_conn(&self) -> RecordsResult<MultiplexedConnection> {
+        self.client
+            .get_multiplexed_async_connection()
+            .await
+            .with_api_err()
+    }
+
+    /// Removes a state from the Redis database.
     pub async fn remove_state(&self, state: String) {
-        let mut state_map = self.states_map.lock().await;
-        state_map.remove(&state);
-        tracing::event!(
-            Level::INFO,
-            "Removed state `{}` from the state_map, {} state(s) in total",
-            state,
-            state_map.len()
-        );
+        let res = match self.get_conn().await {
+            Ok(mut conn) => conn
+                .del::<_, ()>(&[
+                    oauth_state_key(&state).to_string(),
+                    oauth_code_key(&state).to_string(),
+                    oauth_result_key(&state).to_string(),
+                ])
+                .await
+                .with_api_err(),
+            Err(e) => Err(e),
+        };
+
+        match res {
+            Ok(_) => tracing::event!(Level::INFO, "Removed state `{}`", state),
+            Err(e) => tracing::event!(Level::WARN, "Couldn't remove state `{}`: {}", state, e),
+        }
     }
 
     /// Called by the `/player/get_token` endpoint, this method is used to retrieve
-    /// the crossed channel used to communicate with the `/player/give_token` endpoint.
+    /// the connection used to communicate with the `/player/give_token` endpoint.
     ///
     /// This method should be called before the `/player/give_token` endpoint calls the
     /// [`Self::browser_connected_for`] method.
@@ -167,31 +323,30 @@ impl AuthState {
     ///
     /// * `state`, the state string, that is the same as the one retrieved by the `/player/give_token`
     ///    endpoint.
-    pub async fn connect_with_browser(
-        &self,
-        state: String,
-    ) -> RecordsResult<(Sender<Message>, Receiver<Message>)> {
-        // cross channels
-        let (tx1, rx1) = oneshot::channel();
-        let (tx2, rx2) = oneshot::channel();
-
-        // store channel in the state_map
-        {
-            let mut state_map = self.states_map.lock().await;
-            let state_info = state.clone();
-            if let Some(TokenState { instant, .. }) = state_map.get(&state) {
-                return Err(RecordsErrorKind::StateAlreadyReceived(*instant));
-            }
-            state_map.insert(state.clone(), TokenState::new(tx2, rx1));
-            tracing::event!(
-                Level::INFO,
-                "Inserted state `{}` into the token_state_map, {} state(s) in total",
-                state_info,
-                state_map.len()
-            );
+    pub async fn connect_with_browser(&self, state: String) -> RecordsResult<BrowserConnection> {
+        let mut conn = self.get_conn().await?;
+
+        let inserted: Option<String> = redis::cmd("SET")
+            .arg(oauth_state_key(&state))
+            .arg(Utc::now().to_rfc3339())
+            .arg("NX")
+            .arg("EX")
+            .arg(TIMEOUT.as_secs())
+            .query_async(&mut conn)
+            .await
+            .with_api_err()?;
+
+        if inserted.is_none() {
+            let instant: Option<String> = conn.get(oauth_state_key(&state)).await.with_api_err()?;
+            let instant = instant
+                .and_then(|instant| DateTime::parse_from_rfc3339(&instant).ok())
+                .map_or_else(Utc::now, |instant| instant.with_timezone(&Utc));
+            return Err(RecordsErrorKind::StateAlreadyReceived(instant));
         }
 
-        Ok((tx1, rx2))
+        tracing::event!(Level::INFO, "Inserted state `{}`", state);
+
+        Ok(BrowserConnection { conn, state })
     }
 
     /// Called by the `/player/give_token` endpoint, this method is used to send the state
@@ -207,82 +362,445 @@ impl AuthState {
         state: String,
         code: String,
     ) -> RecordsResult<WebToken> {
-        let mut state_map = self.states_map.lock().await;
-
-        let web_token = if let Some(TokenState { tx, rx, .. }) = state_map.remove(&state) {
-            tx.send(Message::MPCode(code))
-                .expect("/player/get_token rx should not be dropped at this point");
-
-            match timeout(TIMEOUT, rx).await {
-                Ok(Ok(res)) => match res {
-                    Message::Ok(web_token) => web_token,
-                    Message::InvalidMPCode => return Err(RecordsErrorKind::InvalidMPCode),
-                    Message::AccessTokenErr(err) => {
-                        return Err(RecordsErrorKind::AccessTokenErr(err))
-                    }
-                    _ => unreachable!(),
-                },
-                _ => {
-                    tracing::event!(Level::WARN, "Token state `{}` timed out", state);
-                    return Err(RecordsErrorKind::Timeout);
-                }
-            }
-        } else {
+        let mut conn = self.get_conn().await?;
+
+        // The state is consumed, so it can't be used by another request
+        let removed: u32 = conn.del(oauth_state_key(&state)).await.with_api_err()?;
+        if removed == 0 {
             return Err(RecordsErrorKind::MissingGetTokenReq);
+        }
+
+        send_message(&mut conn, oauth_code_key(&state), &Message::MPCode(code)).await?;
+
+        let web_token = match recv_message(&mut conn, oauth_result_key(&state)).await? {
+            Some(Message::Ok(web_token)) => web_token,
+            Some(Message::InvalidMPCode) => return Err(RecordsErrorKind::InvalidMPCode),
+            Some(Message::AccessTokenErr(err)) => {
+                return Err(RecordsErrorKind::AccessTokenErr(err))
+            }
+            Some(_) => unreachable!(),
+            None => {
+                tracing::event!(Level::WARN, "Token state `{}` timed out", state);
+                return Err(RecordsErrorKind::Timeout);
+            }
         };
 
-        tracing::event!(
-            Level::INFO,
-            "Removed state `{}` from the token_state_map, {} state(s) in total",
-            state,
-            state_map.len()
-        );
+        tracing::event!(Level::INFO, "Removed state `{}`", state);
 
         Ok(web_token)
     }
 }
 
+/// The maximum number of ManiaPlanet tokens a player can have at the same time.
+///
+/// When a new token is generated beyond this limit, the least recently used one is revoked.
+pub const MAX_MP_TOKENS: usize = 16;
+
+/// The metadata of a ManiaPlanet token of a player.
+///
+/// It is stored in the [`mp_tokens_key`] Redis hash of the player, along with the hash
+/// of the token.
+#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
+pub struct MPTokenInfo {
+    /// The ID of the token, made of the beginning of its hash.
+    pub id: String,
+    /// The date when the token was generated.
+    pub created_at: DateTime<Utc>,
+    /// The date when the token was last used to authenticate the player.
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// The user agent of the game server which requested the token.
+    pub user_agent: Option<String>,
+    /// The date when the token expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl MPTokenInfo {
+    fn last_activity(&self) -> DateTime<Utc> {
+        self.last_used_at.unwrap_or(self.created_at)
+    }
+}
+
+/// Replaces the metadata of a token, only if it is still the one read before.
+///
+/// This way, a token revoked in the meantime isn't stored again. It returns 0 if the token
+/// doesn't exist anymore, otherwise 1, even if its metadata was updated concurrently.
+const TOUCH_MP_TOKEN_SCRIPT: &str = r"
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if not current then
+    return 0
+end
+if current == ARGV[2] then
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+end
+return 1
+";
+
+/// Removes a token and returns its metadata, so that it is consumed only once.
+const TAKE_MP_TOKEN_SCRIPT: &str = r"
+local info = redis.call('HGET', KEYS[1], ARGV[1])
+if info then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+end
+return info
+";
+
+fn mp_token_info_json(info: &MPTokenInfo) -> String {
+    serde_json::to_string(info).expect("MP token info should be serializable")
+}
+
+/// Returns the ManiaPlanet tokens of the player, as couples of (token hash ; metadata).
+///
+/// The expired tokens are removed on the fly.
+async fn stored_mp_tokens(
+    redis_conn: &mut RedisConnection,
+    login: &str,
+) -> RecordsResult<Vec<(String, MPTokenInfo)>> {
+    let key = mp_tokens_key(login);
+    let stored: HashMap<String, String> = redis_conn.hgetall(&key).await.with_api_err()?;
+
+    let now = Utc::now();
+    let mut tokens = Vec::with_capacity(stored.len());
+    let mut expired = Vec::new();
+
+    for (hash, info) in stored {
+        match serde_json::from_str::<MPTokenInfo>(&info) {
+            Ok(info) if info.expires_at > now => tokens.push((hash, info)),
+            _ => expired.push(hash),
+        }
+    }
+
+    if !expired.is_empty() {
+        redis_conn
+            .hdel::<_, _, ()>(&key, expired)
+            .await
+            .with_api_err()?;
+    }
+
+    tokens.sort_by_key(|(_, info)| info.created_at);
+    Ok(tokens)
+}
+
+/// Generates a new ManiaPlanet token for the player and stores it, with its metadata.
+///
+/// If the player already has [`MAX_MP_TOKENS`] tokens, the least recently used ones are revoked.
+async fn add_mp_token(
+    redis_conn: &mut RedisConnection,
+    login: &str,
+    user_agent: Option<String>,
+) -> RecordsResult<String> {
+    let mp_token = generate_token(256);
+    let mp_token_hash = digest(&*mp_token);
+
+    let ttl = crate::env().auth_token_ttl;
+    let now = Utc::now();
+    let info = MPTokenInfo {
+        id: mp_token_hash[..16].to_owned(),
+        created_at: now,
+        last_used_at: None,
+        user_agent,
+        expires_at: now + chrono::Duration::seconds(ttl as _),
+    };
+
+    let key = mp_tokens_key(login);
+
+    let mut tokens = stored_mp_tokens(redis_conn, login).await?;
+    let excess = (tokens.len() + 1).saturating_sub(MAX_MP_TOKENS);
+    if excess > 0 {
+        tokens.sort_by_key(|(_, info)| info.last_activity());
+        let evicted = tokens
+            .drain(..excess)
+            .map(|(hash, _)| hash)
+            .collect::<Vec<_>>();
+        redis_conn
+            .hdel::<_, _, ()>(&key, evicted)
+            .await
+            .with_api_err()?;
+    }
+
+    // The hash expires with its most recent token
+    redis::pipe()
+        .atomic()
+        .hset(&key, &mp_token_hash, mp_token_info_json(&info))
+        .ignore()
+        .expire(&key, ttl as _)
+        .ignore()
+        .query_async::<_, ()>(&mut **redis_conn)
+        .await
+        .with_api_err()?;
+
+    Ok(mp_token)
+}
+
+/// Checks the ManiaPlanet token of the player, and updates the date of its last use.
+///
+/// The token generated before a player could have several of them is still accepted
+/// until it expires.
+async fn use_mp_token(
+    redis_conn: &mut RedisConnection,
+    login: &str,
+    token: &str,
+) -> RecordsResult<bool> {
+    let key = mp_tokens_key(login);
+    let token_hash = digest(token);
+
+    let stored: Option<String> = redis_conn.hget(&key, &token_hash).await.with_api_err()?;
+    let Some(stored) = stored else {
+        let legacy: Option<String> = redis_conn.get(mp_token_key(login)).await.with_api_err()?;
+        return Ok(legacy.is_some_and(|t| t == token_hash));
+    };
+
+    match serde_json::from_str::<MPTokenInfo>(&stored) {
+        Ok(mut info) if info.expires_at > Utc::now() => {
+            info.last_used_at = Some(Utc::now());
+            let exists: bool = redis::cmd("EVAL")
+                .arg(TOUCH_MP_TOKEN_SCRIPT)
+                .arg(1)
+                .arg(&key)
+                .arg(&token_hash)
+                .arg(&stored)
+                .arg(mp_token_info_json(&info))
+                .query_async(&mut **redis_conn)
+                .await
+                .with_api_err()?;
+            Ok(exists)
+        }
+        _ => {
+            redis_conn
+                .hdel::<_, _, ()>(&key, &token_hash)
+                .await
+                .with_api_err()?;
+            Ok(false)
+        }
+    }
+}
+
+/// Returns whether the ManiaPlanet token of the player is valid.
+///
+/// Unlike [`check_auth_for`], the date of its last use isn't updated, and the player
+/// isn't checked.
+pub async fn is_valid_mp_token(db: &Database, login: &str, token: &str) -> RecordsResult<bool> {
+    let mut connection = db.redis_pool.get().await.with_api_err()?;
+    let token_hash = digest(token);
+
+    let info: Option<String> = connection
+        .hget(mp_tokens_key(login), &token_hash)
+        .await
+        .with_api_err()?;
+    match info {
+        Some(info) => Ok(serde_json::from_str::<MPTokenInfo>(&info)
+            .is_ok_and(|info| info.expires_at > Utc::now())),
+        None => {
+            let legacy: Option<String> =
+                connection.get(mp_token_key(login)).await.with_api_err()?;
+            Ok(legacy.is_some_and(|t| t == token_hash))
+        }
+    }
+}
+
 /// Generates a ManiaPlanet and Website token for the player with the provided login.
 ///
 /// The player might not yet exist in the database.
 /// It returns a couple of (ManiaPlanet token ; Website token).
 ///
+/// The new ManiaPlanet token is added to the ones of the player, with the provided user agent
+/// of the game server, while the Website token replaces the previous one.
+///
 /// The tokens are stored in the Redis database.
-pub async fn gen_token_for(db: &Database, login: &str) -> RecordsResult<(String, String)> {
-    let mp_token = generate_token(256);
+pub async fn gen_token_for(
+    db: &Database,
+    login: &str,
+    user_agent: Option<String>,
+) -> RecordsResult<(String, String)> {
     let web_token = generate_token(32);
 
     let mut connection = db.redis_pool.get().await.with_api_err()?;
-    let mp_key = mp_token_key(login);
     let web_key = web_token_key(login);
 
     let ex = crate::env().auth_token_ttl as _;
 
-    let mp_token_hash = digest(&*mp_token);
     let web_token_hash = digest(&*web_token);
 
+    let mp_token = add_mp_token(&mut connection, login, user_agent).await?;
     connection
-        .set_ex(mp_key, mp_token_hash, ex)
+        .set_ex::<_, _, ()>(web_key, web_token_hash, ex)
         .await
         .with_api_err()?;
+    Ok((mp_token, web_token))
+}
+
+/// Replaces the provided ManiaPlanet token of the player with a new one, and returns it.
+///
+/// This is used by the game servers to renew the token of a player before it expires.
+/// The new token keeps the user agent of the previous one.
+///
+/// The provided token must have already been checked (see [`check_auth_for`]).
+/// The previous token is consumed atomically, so if it was revoked or rotated in the meantime,
+/// this returns an `Unauthorized` error instead of issuing a new token.
+pub async fn rotate_mp_token(db: &Database, login: &str, token: &str) -> RecordsResult<String> {
+    let mut connection = db.redis_pool.get().await.with_api_err()?;
+    let key = mp_tokens_key(login);
+    let token_hash = digest(token);
+
+    let info: Option<String> = redis::cmd("EVAL")
+        .arg(TAKE_MP_TOKEN_SCRIPT)
+        .arg(1)
+        .arg(&key)
+        .arg(&token_hash)
+        .query_async(&mut *connection)
+        .await
+        .with_api_err()?;
+    let user_agent = match info {
+        Some(info) => serde_json::from_str::<MPTokenInfo>(&info)
+            .ok()
+            .and_then(|info| info.user_agent),
+        None => {
+            // The token generated before a player could have several of them
+            let legacy_key = mp_token_key(login);
+            let legacy: Option<String> = connection.get_del(&legacy_key).await.with_api_err()?;
+            if legacy.as_deref() != Some(&*token_hash) {
+                return Err(RecordsErrorKind::Unauthorized);
+            }
+            None
+        }
+    };
+
+    add_mp_token(&mut connection, login, user_agent).await
+}
+
+/// Returns the metadata of the valid ManiaPlanet tokens of the player, from the oldest
+/// to the newest.
+pub async fn mp_tokens_of(db: &Database, login: &str) -> RecordsResult<Vec<MPTokenInfo>> {
+    let mut connection = db.redis_pool.get().await.with_api_err()?;
+    let tokens = stored_mp_tokens(&mut connection, login).await?;
+    Ok(tokens.into_iter().map(|(_, info)| info).collect())
+}
+
+/// Revokes the ManiaPlanet token of the player with the provided ID.
+///
+/// It returns `false` if the player has no token with this ID.
+pub async fn revoke_mp_token(db: &Database, login: &str, token_id: &str) -> RecordsResult<bool> {
+    let mut connection = db.redis_pool.get().await.with_api_err()?;
+    let tokens = stored_mp_tokens(&mut connection, login).await?;
+
+    let Some((hash, _)) = tokens.into_iter().find(|(_, info)| info.id == token_id) else {
+        return Ok(false);
+    };
+
     connection
-        .set_ex(web_key, web_token_hash, ex)
+        .hdel::<_, _, ()>(mp_tokens_key(login), hash)
         .await
         .with_api_err()?;
-    Ok((mp_token, web_token))
+    Ok(true)
+}
+
+/// Logs out the website session of the player, by deleting their Website token.
+///
+/// It returns `false` if the provided token isn't the current Website token of the player.
+pub async fn logout(db: &Database, WebToken { login, token }: &WebToken) -> RecordsResult<bool> {
+    let mut connection = db.redis_pool.get().await.with_api_err()?;
+    let key = web_token_key(login);
+
+    let stored_token: Option<String> = connection.get(&key).await.with_api_err()?;
+    if !matches!(stored_token, Some(t) if t == digest(&**token)) {
+        return Ok(false);
+    }
+
+    connection.del::<_, ()>(&key).await.with_api_err()?;
+    Ok(true)
+}
+
+/// Revokes all the tokens of the player, for the website and the game servers.
+///
+/// It returns the metadata of the revoked ManiaPlanet tokens.
+pub async fn revoke_tokens_of(db: &Database, login: &str) -> RecordsResult<Vec<MPTokenInfo>> {
+    let mut connection = db.redis_pool.get().await.with_api_err()?;
+    let tokens = stored_mp_tokens(&mut connection, login).await?;
+
+    connection
+        .del::<_, ()>(&[
+            web_token_key(login).to_string(),
+            mp_token_key(login).to_string(),
+            mp_tokens_key(login).to_string(),
+        ])
+        .await
+        .with_api_err()?;
+
+    Ok(tokens.into_iter().map(|(_, info)| info).collect())
+}
+
+/// Returns the permissions of the player, granted by their role.
+///
+/// If an event edition is provided, it also contains the permissions granted to the player
+/// if they're one of its admins (see [`permission::EDITION_ADMIN`]).
+pub async fn permissions_of(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    player_id: u32,
+    edition: Option<(u32, u32)>,
+) -> RecordsResult<permission::Flags> {
+    let names: Vec<String> = sqlx::query_scalar(
+        "SELECT rp.permission
+        FROM players p
+        INNER JOIN role_permissions rp ON rp.role_id = p.role
+        WHERE p.id = ?",
+    )
+    .bind(player_id)
+    .fetch_all(&mut *mysql_conn)
+    .await
+    .with_api_err()?;
+
+    let mut flags = names
+        .iter()
+        .filter_map(|name| permission::Permission::from_name(name))
+        .fold(permission::NONE, |flags, p| flags | p.flag());
+
+    if let Some((event_id, edition_id)) = edition {
+        let is_edition_admin: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM event_edition_admins
+                WHERE event_id = ? AND edition_id = ? AND player_id = ?)",
+        )
+        .bind(event_id)
+        .bind(edition_id)
+        .bind(player_id)
+        .fetch_one(&mut *mysql_conn)
+        .await
+        .with_api_err()?;
+
+        if is_edition_admin {
+            flags |= permission::EDITION_ADMIN;
+        }
+    }
+
+    Ok(flags)
+}
+
+/// The kind of token used to authenticate a player.
+#[derive(Clone, Copy)]
+enum TokenKind {
+    /// The Website token.
+    Web,
+    /// One of the ManiaPlanet tokens.
+    MP,
 }
 
 async fn inner_check_auth_for(
     db: &Database,
     login: &str,
     token: &str,
-    required: privilege::Flags,
-    key: impl ToRedisArgs + std::marker::Sync + std::marker::Sync,
+    required: permission::Flags,
+    edition: Option<(u32, u32)>,
+    kind: TokenKind,
 ) -> RecordsResult<u32> {
     let mut mysql_conn = db.mysql_pool.acquire().await.with_api_err()?;
     let mut connection = db.redis_pool.get().await.with_api_err()?;
-    let stored_token: Option<String> = connection.get(&key).await.with_api_err()?;
-    if !matches!(stored_token, Some(t) if t == digest(token)) {
+    let is_valid = match kind {
+        TokenKind::Web => {
+            let stored_token: Option<String> =
+                connection.get(web_token_key(login)).await.with_api_err()?;
+            matches!(stored_token, Some(t) if t == digest(token))
+        }
+        TokenKind::MP => use_mp_token(&mut connection, login, token).await?,
+    };
+    if !is_valid {
         return Err(RecordsErrorKind::Unauthorized);
     }
 
@@ -292,19 +810,11 @@ async fn inner_check_auth_for(
         return Err(RecordsErrorKind::BannedPlayer(ban));
     };
 
-    let role: privilege::Flags = sqlx::query_scalar(
-        "SELECT r.privileges
-        FROM players p
-        INNER JOIN role r ON r.id = p.role
-        WHERE p.id = ?",
-    )
-    .bind(player.id)
-    .fetch_one(&db.mysql_pool)
-    .await
-    .with_api_err()?;
-
-    if role & required != required {
-        return Err(RecordsErrorKind::Forbidden);
+    if required != permission::NONE {
+        let permissions = permissions_of(&mut mysql_conn, player.id, edition).await?;
+        if permissions & required != required {
+            return Err(RecordsErrorKind::Forbidden);
+        }
     }
 
     Ok(player.id)
@@ -318,10 +828,32 @@ pub async fn website_check_auth_for(
     db: &Database,
     login: &str,
     token: &str,
-    required: privilege::Flags,
+    required: permission::Flags,
 ) -> RecordsResult<u32> {
-    let key = web_token_key(login);
-    inner_check_auth_for(db, login, token, required, key).await
+    inner_check_auth_for(db, login, token, required, None, TokenKind::Web).await
+}
+
+/// Checks for a successful authentication for the player with its login and Website token,
+/// on the provided event edition.
+///
+/// Unlike [`website_check_auth_for`], the required permissions may also be granted to the player
+/// if they're one of the admins of the edition (see [`permission::EDITION_ADMIN`]).
+pub async fn website_check_auth_for_edition(
+    db: &Database,
+    login: &str,
+    token: &str,
+    required: permission::Flags,
+    (event_id, edition_id): (u32, u32),
+) -> RecordsResult<u32> {
+    inner_check_auth_for(
+        db,
+        login,
+        token,
+        required,
+        Some((event_id, edition_id)),
+        TokenKind::Web,
+    )
+    .await
 }
 
 /// Checks for a successful authentication for the player with its login and ManiaPlanet token.
@@ -329,22 +861,22 @@ pub async fn website_check_auth_for(
 /// # Arguments
 ///
 /// * `_: AuthHeader`: the authentication headers retrieved from the HTTP request.
-/// * `required: Role` the required role to be authorized.
+/// * `required: permission::Flags` the required permissions to be authorized.
 ///
 /// # Returns
 ///
 /// * If the token is invalid, it returns an `Unauthorized` error
 /// * If it is valid, but the player doesn't exist in the database, it returns a `PlayerNotFound` error
-/// * If the player hasn't the required role, or is banned, it returns a `Forbidden` error
+/// * If the player hasn't the required permissions, it returns a `Forbidden` error
+/// * If the player is banned, it returns a `BannedPlayer` error
 /// * Otherwise, it returns Ok(())
 pub async fn check_auth_for(
     db: &Database,
     login: &str,
     token: &str,
-    required: privilege::Flags,
+    required: permission::Flags,
 ) -> RecordsResult<u32> {
-    let key = mp_token_key(login);
-    inner_check_auth_for(db, login, token, required, key).await
+    inner_check_auth_for(db, login, token, required, None, TokenKind::MP).await
 }
 
 struct ExtAuthHeaders {
@@ -365,22 +897,22 @@ fn ext_auth_headers(req: &HttpRequest) -> ExtAuthHeaders {
     }
 }
 
-pub struct MPAuthGuard<const ROLE: privilege::Flags = { privilege::PLAYER }> {
+pub struct MPAuthGuard<const REQUIRED: permission::Flags = { permission::NONE }> {
     pub login: String,
 }
 
-impl<const MIN_ROLE: privilege::Flags> FromRequest for MPAuthGuard<MIN_ROLE> {
+impl<const REQUIRED: permission::Flags> FromRequest for MPAuthGuard<REQUIRED> {
     type Error = RecordsError;
 
     type Future = Pin<Box<dyn Future<Output = RecordsResponse<Self>>>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        async fn check<const ROLE: privilege::Flags>(
+        async fn check<const REQUIRED: permission::Flags>(
             request_id: RequestId,
             db: Res<Database>,
             login: Option<String>,
             token: Option<String>,
-        ) -> RecordsResponse<MPAuthGuard<ROLE>> {
+        ) -> RecordsResponse<MPAuthGuard<REQUIRED>> {
             let (Some(login), Some(token)) = (login, token) else {
                 return Err(RecordsError {
                     request_id,
@@ -388,7 +920,7 @@ impl<const MIN_ROLE: privilege::Flags> FromRequest for MPAuthGuard<MIN_ROLE> {
                 });
             };
 
-            check_auth_for(&db, &login, &token, ROLE)
+            check_auth_for(&db, &login, &token, REQUIRED)
                 .await
                 .fit(request_id)?;
 
@@ -437,6 +969,52 @@ impl FromRequest for AuthHeader {
     }
 }
 
+/// A guard that checks the API key sent in the [`API_KEY_HEADER`](api_key::API_KEY_HEADER) header
+/// of the request, if any.
+///
+/// The requests without an API key are accepted, but if one is provided, it must be valid
+/// and have the required scopes, for example with `ApiKeyGuard<{ api_key::READ_PLAYERS }>`.
+pub struct ApiKeyGuard<const REQUIRED: api_key::Scopes = { api_key::NONE }> {
+    pub api_key: Option<CheckedApiKey>,
+}
+
+impl<const REQUIRED: api_key::Scopes> FromRequest for ApiKeyGuard<REQUIRED> {
+    type Error = RecordsError;
+
+    type Future = Pin<Box<dyn Future<Output = RecordsResponse<Self>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        async fn check<const REQUIRED: api_key::Scopes>(
+            request_id: RequestId,
+            db: Res<Database>,
+            key: Option<String>,
+        ) -> RecordsResponse<ApiKeyGuard<REQUIRED>> {
+            let api_key = match key {
+                Some(key) => {
+                    let api_key = api_key::check_api_key(&db, &key, REQUIRED)
+                        .await
+                        .fit(request_id)?;
+                    tracing::event!(Level::DEBUG, "Request made with the API key {}", api_key.id);
+                    Some(api_key)
+                }
+                None => None,
+            };
+
+            Ok(ApiKeyGuard { api_key })
+        }
+
+        let key = req
+            .headers()
+            .get(api_key::API_KEY_HEADER)
+            .and_then(|h| h.to_str().map(str::to_owned).ok());
+
+        let req_id = must::have_request_id(req);
+        let db = must::have_db(req);
+
+        Box::pin(check(req_id, db, key))
+    }
+}
+
 /// A guard that checks that the API isn't currently under maintenance.
 pub struct ApiAvailable;
 
@@ -0,0 +1,286 @@
+use async_graphql::{connection, dataloader::DataLoader, Context, Enum, ID};
+use records_lib::{models, moderation, must, Database};
+use sqlx::{mysql, Connection as _, FromRow as _, Row as _};
+
+use crate::{
+    audit::{self, Action},
+    auth::permission,
+};
+
+use super::{
+    map::{Map, MapLoader},
+    player::{Player, PlayerLoader},
+    utils::{
+        check_auth_for, connections_append_query_string, connections_bind_query_parameters,
+        connections_pages_info, decode_id, request_id,
+    },
+};
+
+#[derive(Copy, Clone, Eq, PartialEq, Enum)]
+pub(super) enum AcReportVerdict {
+    Legit,
+    Cheat,
+}
+
+pub struct AcReport {
+    inner: models::AcReport,
+}
+
+impl From<models::AcReport> for AcReport {
+    fn from(inner: models::AcReport) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_graphql::Object]
+impl AcReport {
+    async fn id(&self) -> ID {
+        ID(format!("v0:AcReport:{}", self.inner.id))
+    }
+
+    async fn player(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Player>> {
+        match self.inner.player_id {
+            Some(player_id) => Ok(ctx
+                .data_unchecked::<DataLoader<PlayerLoader>>()
+                .load_one(player_id)
+                .await?),
+            None => Ok(None),
+        }
+    }
+
+    async fn map(&self, ctx: &Context<'_>) -> async_graphql::Result<Map> {
+        ctx.data_unchecked::<DataLoader<MapLoader>>()
+            .load_one(self.inner.map_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Map not found."))
+    }
+
+    async fn record_id(&self) -> Option<u32> {
+        self.inner.record_id
+    }
+
+    async fn report_date(&self) -> chrono::NaiveDateTime {
+        self.inner.report_date
+    }
+
+    async fn run_time(&self) -> &str {
+        &self.inner.run_time
+    }
+
+    async fn time(&self) -> Option<i32> {
+        self.inner.time
+    }
+
+    async fn cp_times(&self) -> &str {
+        &self.inner.cp_times
+    }
+
+    async fn player_field(&self) -> &str {
+        &self.inner.player_field
+    }
+
+    async fn server_text(&self) -> &str {
+        &self.inner.server_text
+    }
+
+    async fn irl_time_passed(&self) -> &str {
+        &self.inner.irl_time_passed
+    }
+
+    async fn discrepancy(&self) -> &str {
+        &self.inner.discrepancy
+    }
+
+    async fn discrepancy_ratio(&self) -> &str {
+        &self.inner.discrepancy_ratio
+    }
+
+    async fn ac_version(&self) -> &str {
+        &self.inner.ac_version
+    }
+
+    async fn reviewed_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.inner.reviewed_at
+    }
+
+    async fn reviewed_by(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Player>> {
+        match self.inner.reviewed_by {
+            Some(reviewed_by) => Ok(ctx
+                .data_unchecked::<DataLoader<PlayerLoader>>()
+                .load_one(reviewed_by)
+                .await?),
+            None => Ok(None),
+        }
+    }
+
+    async fn verdict(&self) -> Option<AcReportVerdict> {
+        self.inner.is_cheat.map(|is_cheat| {
+            if is_cheat {
+                AcReportVerdict::Cheat
+            } else {
+                AcReportVerdict::Legit
+            }
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn ac_reports(
+    ctx: &Context<'_>,
+    reviewed: Option<bool>,
+    player_login: Option<String>,
+    map_uid: Option<String>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> async_graphql::Result<connection::Connection<ID, AcReport>> {
+    check_auth_for(ctx, permission::MODERATE_RECORDS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut mysql_conn = db.mysql_pool.acquire().await?;
+
+    let player_id = match player_login {
+        Some(login) => Some(must::have_player(&mut mysql_conn, &login).await?.id),
+        None => None,
+    };
+    let map_id = match map_uid {
+        Some(map_uid) => Some(must::have_map(&mut mysql_conn, &map_uid).await?.id),
+        None => None,
+    };
+
+    connection::query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<ID>, before: Option<ID>, first: Option<usize>, last: Option<usize>| async move {
+            let after = decode_id(after.as_ref());
+            let before = decode_id(before.as_ref());
+
+            // Build the query string
+            let mut conditions = Vec::new();
+            match reviewed {
+                Some(true) => conditions.push("reviewed_at IS NOT NULL "),
+                Some(false) => conditions.push("reviewed_at IS NULL "),
+                None => (),
+            }
+            if player_id.is_some() {
+                conditions.push("player_id = ? ");
+            }
+            if map_id.is_some() {
+                conditions.push("map_id = ? ");
+            }
+
+            let mut query = String::from("SELECT * FROM ac_reports ");
+            if !conditions.is_empty() {
+                query.push_str("WHERE ");
+                query.push_str(&conditions.join("AND "));
+            }
+            connections_append_query_string(
+                &mut query,
+                !conditions.is_empty(),
+                after,
+                before,
+                first,
+                last,
+            );
+            let reversed = first.is_none() && last.is_some();
+
+            // Bind the parameters
+            let mut query = sqlx::query(&query);
+            if let Some(player_id) = player_id {
+                query = query.bind(player_id);
+            }
+            if let Some(map_id) = map_id {
+                query = query.bind(map_id);
+            }
+            query = connections_bind_query_parameters(query, after, before, first, last);
+
+            // Execute the query
+            let mut reports = query
+                .map(|x: mysql::MySqlRow| {
+                    let cursor = ID(format!("v0:AcReport:{}", x.get::<u32, _>("id")));
+                    connection::Edge::new(
+                        cursor,
+                        AcReport::from(models::AcReport::from_row(&x).unwrap()),
+                    )
+                })
+                .fetch_all(&mut *mysql_conn)
+                .await?;
+            if reversed {
+                reports.reverse();
+            }
+
+            let (has_previous_page, has_next_page) =
+                connections_pages_info(reports.len(), first, last);
+            let mut connection = connection::Connection::new(has_previous_page, has_next_page);
+            connection.edges.extend(reports);
+
+            Ok::<_, sqlx::Error>(connection)
+        },
+    )
+    .await
+}
+
+pub(super) async fn review_ac_report(
+    ctx: &Context<'_>,
+    report_id: u32,
+    verdict: AcReportVerdict,
+    invalidate_record: bool,
+) -> async_graphql::Result<AcReport> {
+    let reviewer_id = check_auth_for(ctx, permission::MODERATE_RECORDS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut conn = db.acquire().await?;
+
+    let mut txn = conn.mysql_conn.begin().await?;
+
+    let report: models::AcReport = sqlx::query_as("SELECT * FROM ac_reports WHERE id = ?")
+        .bind(report_id)
+        .fetch_optional(&mut *txn)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Report not found."))?;
+
+    let is_cheat = verdict == AcReportVerdict::Cheat;
+
+    sqlx::query(
+        "UPDATE ac_reports SET reviewed_at = SYSDATE(), reviewed_by = ?, is_cheat = ?
+        WHERE id = ?",
+    )
+    .bind(reviewer_id)
+    .bind(is_cheat)
+    .bind(report.id)
+    .execute(&mut *txn)
+    .await?;
+
+    let invalidated = match (is_cheat, invalidate_record, report.record_id) {
+        (true, true, Some(record_id)) => {
+            Some(moderation::invalidate_record(&mut txn, record_id).await?)
+        }
+        _ => None,
+    };
+
+    let reviewed: models::AcReport = sqlx::query_as("SELECT * FROM ac_reports WHERE id = ?")
+        .bind(report.id)
+        .fetch_one(&mut *txn)
+        .await?;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        reviewer_id,
+        Action::ReviewAcReport,
+        &audit::ac_report_target(report.id),
+        &serde_json::json!({ "is_cheat": report.is_cheat }),
+        &serde_json::json!({ "is_cheat": is_cheat, "invalidated_records": invalidated }),
+    )
+    .await?;
+
+    txn.commit().await?;
+    if let Some(record_ids) = &invalidated {
+        moderation::recompute(&mut conn, record_ids).await?;
+    }
+
+    Ok(AcReport { inner: reviewed })
+}
@@ -0,0 +1,141 @@
+use async_graphql::{dataloader::DataLoader, Context, SimpleObject};
+use records_lib::{models, Database};
+
+use crate::{
+    api_key::{self, Scope},
+    audit::{self, Action},
+    auth::permission,
+};
+
+use super::{
+    player::{Player, PlayerLoader},
+    utils::{check_auth_for, request_id},
+};
+
+pub struct ApiKey {
+    inner: models::ApiKey,
+}
+
+impl From<models::ApiKey> for ApiKey {
+    fn from(inner: models::ApiKey) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_graphql::Object]
+impl ApiKey {
+    async fn id(&self) -> u32 {
+        self.inner.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    async fn created_by(&self, ctx: &Context<'_>) -> async_graphql::Result<Player> {
+        ctx.data_unchecked::<DataLoader<PlayerLoader>>()
+            .load_one(self.inner.created_by)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Player not found."))
+    }
+
+    async fn created_at(&self) -> chrono::NaiveDateTime {
+        self.inner.created_at
+    }
+
+    async fn revoked_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.inner.revoked_at
+    }
+
+    async fn last_used_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.inner.last_used_at
+    }
+
+    async fn usage_count(&self) -> u64 {
+        self.inner.usage_count
+    }
+
+    async fn scopes(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Scope>> {
+        let db = ctx.data_unchecked::<Database>();
+        let mut mysql_conn = db.mysql_pool.acquire().await?;
+        Ok(api_key::scopes_of(&mut mysql_conn, self.inner.id).await?)
+    }
+}
+
+/// A newly issued API key.
+#[derive(SimpleObject)]
+pub struct CreatedApiKey {
+    api_key: ApiKey,
+    /// The key to send in the `X-Api-Key` header. It can't be retrieved afterwards.
+    key: String,
+}
+
+pub(super) async fn api_keys(ctx: &Context<'_>) -> async_graphql::Result<Vec<ApiKey>> {
+    check_auth_for(ctx, permission::MANAGE_API_KEYS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let api_keys = sqlx::query_as::<_, models::ApiKey>("SELECT * FROM api_keys ORDER BY id DESC")
+        .fetch_all(&db.mysql_pool)
+        .await?;
+
+    Ok(api_keys.into_iter().map(Into::into).collect())
+}
+
+pub(super) async fn create_api_key(
+    ctx: &Context<'_>,
+    name: String,
+    scopes: Vec<Scope>,
+) -> async_graphql::Result<CreatedApiKey> {
+    let admin_id = check_auth_for(ctx, permission::MANAGE_API_KEYS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut txn = db.mysql_pool.begin().await?;
+
+    let (api_key, key) = api_key::create_api_key(&mut txn, &name, &scopes, admin_id).await?;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        admin_id,
+        Action::CreateApiKey,
+        &audit::api_key_target(api_key.id),
+        &(),
+        &api_key,
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(CreatedApiKey {
+        api_key: api_key.into(),
+        key,
+    })
+}
+
+pub(super) async fn revoke_api_key(
+    ctx: &Context<'_>,
+    api_key_id: u32,
+) -> async_graphql::Result<ApiKey> {
+    let admin_id = check_auth_for(ctx, permission::MANAGE_API_KEYS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut txn = db.mysql_pool.begin().await?;
+
+    let before = api_key::revoke_api_key(&mut txn, api_key_id).await?;
+    let after = api_key::get_api_key(&mut txn, api_key_id).await?;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        admin_id,
+        Action::RevokeApiKey,
+        &audit::api_key_target(api_key_id),
+        &before,
+        &after,
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(after.into())
+}
@@ -0,0 +1,159 @@
+use async_graphql::{connection, dataloader::DataLoader, Context, ID};
+use records_lib::{models, must, Database};
+use sqlx::{mysql, FromRow as _, Row as _};
+
+use crate::{audit::Action, auth::permission};
+
+use super::{
+    player::{Player, PlayerLoader},
+    utils::{
+        check_auth_for, connections_append_query_string, connections_bind_query_parameters,
+        connections_pages_info, decode_id,
+    },
+};
+
+pub struct AuditLogEntry {
+    inner: models::AuditLogEntry,
+}
+
+impl From<models::AuditLogEntry> for AuditLogEntry {
+    fn from(inner: models::AuditLogEntry) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_graphql::Object]
+impl AuditLogEntry {
+    async fn id(&self) -> ID {
+        ID(format!("v0:AuditLogEntry:{}", self.inner.id))
+    }
+
+    async fn actor(&self, ctx: &Context<'_>) -> async_graphql::Result<Player> {
+        ctx.data_unchecked::<DataLoader<PlayerLoader>>()
+            .load_one(self.inner.actor_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Player not found."))
+    }
+
+    async fn action(&self) -> async_graphql::Result<Action> {
+        Action::from_name(&self.inner.action).ok_or_else(|| {
+            async_graphql::Error::new(format!("Unknown action `{}`.", self.inner.action))
+        })
+    }
+
+    async fn target(&self) -> &str {
+        &self.inner.target
+    }
+
+    async fn before_state(&self) -> Option<&str> {
+        self.inner.before_state.as_deref()
+    }
+
+    async fn after_state(&self) -> Option<&str> {
+        self.inner.after_state.as_deref()
+    }
+
+    async fn request_id(&self) -> &str {
+        &self.inner.request_id
+    }
+
+    async fn created_at(&self) -> chrono::NaiveDateTime {
+        self.inner.created_at
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn audit_log(
+    ctx: &Context<'_>,
+    actor_login: Option<String>,
+    target: Option<String>,
+    action: Option<Action>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> async_graphql::Result<connection::Connection<ID, AuditLogEntry>> {
+    check_auth_for(ctx, permission::VIEW_AUDIT_LOG).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut mysql_conn = db.mysql_pool.acquire().await?;
+
+    let actor_id = match actor_login {
+        Some(login) => Some(must::have_player(&mut mysql_conn, &login).await?.id),
+        None => None,
+    };
+
+    connection::query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<ID>, before: Option<ID>, first: Option<usize>, last: Option<usize>| async move {
+            let after = decode_id(after.as_ref());
+            let before = decode_id(before.as_ref());
+
+            // Build the query string
+            let mut conditions = Vec::new();
+            if actor_id.is_some() {
+                conditions.push("actor_id = ? ");
+            }
+            if target.is_some() {
+                conditions.push("target = ? ");
+            }
+            if action.is_some() {
+                conditions.push("action = ? ");
+            }
+
+            let mut query = String::from("SELECT * FROM audit_log ");
+            if !conditions.is_empty() {
+                query.push_str("WHERE ");
+                query.push_str(&conditions.join("AND "));
+            }
+            connections_append_query_string(
+                &mut query,
+                !conditions.is_empty(),
+                after,
+                before,
+                first,
+                last,
+            );
+            let reversed = first.is_none() && last.is_some();
+
+            // Bind the parameters
+            let mut query = sqlx::query(&query);
+            if let Some(actor_id) = actor_id {
+                query = query.bind(actor_id);
+            }
+            if let Some(target) = &target {
+                query = query.bind(target);
+            }
+            if let Some(action) = action {
+                query = query.bind(action.as_str());
+            }
+            query = connections_bind_query_parameters(query, after, before, first, last);
+
+            // Execute the query
+            let mut entries = query
+                .map(|x: mysql::MySqlRow| {
+                    let cursor = ID(format!("v0:AuditLogEntry:{}", x.get::<u32, _>("id")));
+                    connection::Edge::new(
+                        cursor,
+                        AuditLogEntry::from(models::AuditLogEntry::from_row(&x).unwrap()),
+                    )
+                })
+                .fetch_all(&mut *mysql_conn)
+                .await?;
+            if reversed {
+                entries.reverse();
+            }
+
+            let (has_previous_page, has_next_page) =
+                connections_pages_info(entries.len(), first, last);
+            let mut connection = connection::Connection::new(has_previous_page, has_next_page);
+            connection.edges.extend(entries);
+
+            Ok::<_, sqlx::Error>(connection)
+        },
+    )
+    .await
+}
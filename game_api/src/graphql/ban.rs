@@ -34,6 +34,10 @@ impl Banishment {
         self.inner.was_reprieved
     }
 
+    async fn shadow(&self) -> bool {
+        self.inner.shadow
+    }
+
     async fn reason(&self) -> &str {
         &self.inner.reason
     }
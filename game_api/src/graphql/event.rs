@@ -14,16 +14,22 @@ use records_lib::{
     models::{self, EventCategory},
     must,
     redis_key::{mappack_map_last_rank, mappack_player_ranks_key},
-    RedisPool,
+    Database, RedisPool,
 };
 
-use crate::{RecordsResult, RecordsResultExt};
+use crate::{
+    api_key as scope,
+    audit::{self, Action},
+    auth::permission,
+    RecordsResult, RecordsResultExt,
+};
 
 use super::{
     map::{Map, MapLoader},
     mappack::{self, Mappack},
     player::Player,
     record::RankedRecord,
+    utils::{check_auth_for_edition, request_id, ApiKeyScopeGuard},
     SortState,
 };
 
@@ -39,7 +45,7 @@ impl From<models::Event> for Event {
     }
 }
 
-#[async_graphql::Object]
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_EVENTS)")]
 impl Event {
     async fn handle(&self) -> &str {
         &self.inner.handle
@@ -426,6 +432,15 @@ impl EventEditionPlayer<'_> {
         .await
     }
 
+    async fn points(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<f64>> {
+        mappack::player_points(
+            ctx,
+            AnyMappackId::Event(&self.edition.event.inner, &self.edition.inner),
+            self.player.id,
+        )
+        .await
+    }
+
     async fn map_finished(&self, ctx: &Context<'_>) -> async_graphql::Result<usize> {
         mappack::player_map_finished(
             ctx,
@@ -444,6 +459,18 @@ impl EventEditionPlayer<'_> {
         .await
     }
 
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<mappack::MappackPlayerSnapshot>> {
+        mappack::player_history(
+            ctx,
+            AnyMappackId::Event(&self.edition.event.inner, &self.edition.inner),
+            self.player.id,
+        )
+        .await
+    }
+
     async fn categorized_ranks(
         &self,
         ctx: &Context<'_>,
@@ -484,7 +511,7 @@ impl EventEditionPlayer<'_> {
         let mut unfinished_maps = sqlx::query_as(
             "select m.* from maps m
             inner join event_edition_maps eem on m.id = eem.map_id
-            left join records gr on m.id = gr.map_id and gr.record_player_id = ?
+            left join valid_records gr on m.id = gr.map_id and gr.record_player_id = ?
             left join event_edition_records eer on gr.record_id = eer.record_id
             where eem.event_id = ? and eem.edition_id = ? and gr.record_id is null",
         )
@@ -558,7 +585,7 @@ impl EventEditionMap<'_> {
     }
 }
 
-#[async_graphql::Object]
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_EVENTS)")]
 impl EventEdition<'_> {
     async fn id(&self) -> u32 {
         self.inner.id
@@ -659,3 +686,66 @@ impl EventEdition<'_> {
         Ok(q)
     }
 }
+
+/// The new values of the fields of an event edition. The missing fields are left unchanged.
+#[derive(async_graphql::InputObject)]
+pub(super) struct EventEditionInput {
+    name: Option<String>,
+    subtitle: Option<String>,
+    banner_img_url: Option<String>,
+    banner2_img_url: Option<String>,
+}
+
+pub(super) async fn update_event_edition(
+    ctx: &Context<'_>,
+    event_handle: String,
+    edition_id: u32,
+    input: EventEditionInput,
+) -> async_graphql::Result<EventEdition<'static>> {
+    let db = ctx.data_unchecked::<Database>();
+    let mut txn = db.mysql_pool.begin().await?;
+
+    let (event, before) = must::have_event_edition(&mut txn, &event_handle, edition_id).await?;
+
+    let admin_id =
+        check_auth_for_edition(ctx, permission::MANAGE_EVENTS, event.id, before.id).await?;
+
+    sqlx::query(
+        "UPDATE event_edition SET
+            name = COALESCE(?, name),
+            subtitle = COALESCE(?, subtitle),
+            banner_img_url = COALESCE(?, banner_img_url),
+            banner2_img_url = COALESCE(?, banner2_img_url)
+        WHERE event_id = ? AND id = ?",
+    )
+    .bind(input.name)
+    .bind(input.subtitle)
+    .bind(input.banner_img_url)
+    .bind(input.banner2_img_url)
+    .bind(event.id)
+    .bind(before.id)
+    .execute(&mut *txn)
+    .await?;
+
+    let after = must::have_event_edition(&mut txn, &event_handle, edition_id)
+        .await?
+        .1;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        admin_id,
+        Action::UpdateEventEdition,
+        &audit::edition_target(&event_handle, edition_id),
+        &before,
+        &after,
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(EventEdition {
+        event: Cow::Owned(event.into()),
+        inner: after,
+    })
+}
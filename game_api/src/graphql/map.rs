@@ -4,24 +4,25 @@ use async_graphql::{
     dataloader::{DataLoader, Loader},
     ID,
 };
-use deadpool_redis::redis::AsyncCommands;
 use futures::StreamExt;
 use records_lib::{
     event::OptEvent,
+    leaderboard::{leaderboard, Leaderboard as _},
     models::{self, Record},
-    redis_key::alone_map_key,
     update_ranks::{get_rank, update_leaderboard},
     Database, DatabaseConnection,
 };
 use sqlx::{mysql, FromRow, MySqlPool};
 
-use crate::auth::{self, privilege, WebToken};
+use crate::api_key as scope;
+use crate::auth::{self, permission, WebToken};
 
 use super::{
     event::EventEdition,
     player::{Player, PlayerLoader},
     rating::{PlayerRating, Rating},
     record::RankedRecord,
+    utils::ApiKeyScopeGuard,
     SortState,
 };
 
@@ -48,17 +49,12 @@ impl Map {
         let db = ctx.data_unchecked::<Database>();
         let mut conn = db.acquire().await?;
 
-        let key = alone_map_key(self.inner.id);
-
         update_leaderboard(&mut conn, self.inner.id, event).await?;
 
         let to_reverse = matches!(rank_sort_by, Some(SortState::Reverse));
-        let record_ids: Vec<i32> = if to_reverse {
-            conn.redis_conn.zrevrange(&key, 0, 99)
-        } else {
-            conn.redis_conn.zrange(&key, 0, 99)
-        }
-        .await?;
+        let record_ids = leaderboard(&mut conn)
+            .range(self.inner.id, event, 0, 100, to_reverse)
+            .await?;
         if record_ids.is_empty() {
             return Ok(Vec::new());
         }
@@ -149,7 +145,7 @@ struct RawRelatedEdition {
     edition: models::EventEdition,
 }
 
-#[async_graphql::Object]
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_LEADERBOARDS)")]
 impl Map {
     pub async fn id(&self) -> ID {
         ID(format!("v0:Map:{}", self.inner.id))
@@ -231,9 +227,9 @@ impl Map {
             .await?;
 
         let role = if author_login != *login {
-            privilege::ADMIN
+            permission::MANAGE_MAPS
         } else {
-            privilege::PLAYER
+            permission::NONE
         };
 
         auth::website_check_auth_for(db, login, token, role).await?;
@@ -1,65 +1,46 @@
 use std::time::SystemTime;
 
-use async_graphql::SimpleObject;
+use async_graphql::{dataloader::DataLoader, SimpleObject};
 use deadpool_redis::redis::AsyncCommands;
 use records_lib::{
-    map,
-    mappack::{update_mappack, AnyMappackId},
-    must, player,
+    mappack::{self, update_mappack, AnyMappackId, MappackStanding},
+    must,
+    mx::MxClient,
+    player,
     redis_key::{
         mappack_key, mappack_lb_key, mappack_map_last_rank, mappack_mx_created_key,
         mappack_mx_name_key, mappack_mx_username_key, mappack_nb_map_key,
-        mappack_player_map_finished_key, mappack_player_rank_avg_key, mappack_player_ranks_key,
-        mappack_player_worst_rank_key, mappack_time_key,
+        mappack_player_map_finished_key, mappack_player_points_key, mappack_player_rank_avg_key,
+        mappack_player_ranks_key, mappack_player_worst_rank_key, mappack_time_key,
     },
     Database, DatabaseConnection, MySqlPool, RedisPool,
 };
-use reqwest::Client;
-use serde::Deserialize;
 
-use crate::{RecordsErrorKind, RecordsResult, RecordsResultExt};
+use crate::{api_key as scope, RecordsErrorKind, RecordsResult, RecordsResultExt};
 
-use super::{map::Map, player::Player};
-
-#[derive(Deserialize)]
-#[allow(non_snake_case)]
-struct MXMappackInfoResponse {
-    Username: String,
-    Name: String,
-    Created: String,
-}
+use super::{
+    map::Map,
+    player::{Player, PlayerLoader},
+    utils::ApiKeyScopeGuard,
+};
 
 async fn fill_mappack(
-    client: &Client,
+    mx: &MxClient,
     conn: &mut DatabaseConnection,
     mappack: AnyMappackId<'_>,
     mappack_id: u32,
 ) -> RecordsResult<()> {
-    let maps = map::fetch_mx_mappack_maps(client, mappack_id, None);
-
-    let info = async {
-        let res: MXMappackInfoResponse = client
-            .get(format!(
-                "https://sm.mania.exchange/api/mappack/get_info/{mappack_id}"
-            ))
-            .header("User-Agent", "obstacle (discord @ahmadbky)")
-            .send()
-            .await
-            .with_api_err()?
-            .json()
-            .await
-            .with_api_err()?;
-        RecordsResult::Ok(res)
-    };
+    let maps = mx.mappack_maps(mappack_id, None);
+    let info = mx.mappack_info(mappack_id);
 
     let (maps, info) = tokio::join!(maps, info);
     let (maps, info) = (maps?, info?);
 
     for mx_map in maps {
         // We check that the map exists in our database
-        let _ = must::have_map(&mut conn.mysql_conn, &mx_map.TrackUID).await?;
+        let _ = must::have_map(&mut conn.mysql_conn, &mx_map.map_uid).await?;
         conn.redis_conn
-            .sadd(mappack_key(mappack), mx_map.TrackUID)
+            .sadd::<_, _, ()>(mappack_key(mappack), mx_map.map_uid)
             .await
             .with_api_err()?;
     }
@@ -69,17 +50,17 @@ async fn fill_mappack(
     // to an event edition, so these info would be retrieved from our information system.
 
     conn.redis_conn
-        .set(mappack_mx_username_key(mappack), info.Username)
+        .set::<_, _, ()>(mappack_mx_username_key(mappack), info.username)
         .await
         .with_api_err()?;
 
     conn.redis_conn
-        .set(mappack_mx_name_key(mappack), info.Name)
+        .set::<_, _, ()>(mappack_mx_name_key(mappack), info.name)
         .await
         .with_api_err()?;
 
     conn.redis_conn
-        .set(mappack_mx_created_key(mappack), info.Created)
+        .set::<_, _, ()>(mappack_mx_created_key(mappack), info.created)
         .await
         .with_api_err()?;
 
@@ -107,6 +88,34 @@ struct MappackMap {
     map: Map,
 }
 
+#[derive(SimpleObject)]
+pub(super) struct MappackPlayerSnapshot {
+    date: chrono::NaiveDateTime,
+    rank: u32,
+    rank_avg: f64,
+    points: Option<f64>,
+    map_finished: u32,
+}
+
+impl From<MappackStanding> for MappackPlayerSnapshot {
+    fn from(standing: MappackStanding) -> Self {
+        Self {
+            date: standing.snapshot_date,
+            rank: standing.inner.rank,
+            rank_avg: standing.inner.rank_avg,
+            points: standing.inner.points,
+            map_finished: standing.inner.maps_finished,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct MappackLeaderboardSnapshotRow {
+    player: Player,
+    #[graphql(flatten)]
+    snapshot: MappackPlayerSnapshot,
+}
+
 struct MappackPlayer<'a> {
     mappack: &'a Mappack,
     inner: Player,
@@ -138,6 +147,19 @@ pub(super) async fn player_rank_avg(
     Ok(rank)
 }
 
+pub(super) async fn player_points(
+    ctx: &async_graphql::Context<'_>,
+    mappack: AnyMappackId<'_>,
+    player_id: u32,
+) -> async_graphql::Result<Option<f64>> {
+    let redis_pool = ctx.data_unchecked::<RedisPool>();
+    let redis_conn = &mut redis_pool.get().await?;
+    let points = redis_conn
+        .get(mappack_player_points_key(mappack, player_id))
+        .await?;
+    Ok(points)
+}
+
 pub(super) async fn player_map_finished(
     ctx: &async_graphql::Context<'_>,
     mappack: AnyMappackId<'_>,
@@ -164,7 +186,18 @@ pub(super) async fn player_worst_rank(
     Ok(worst_rank)
 }
 
-#[async_graphql::Object]
+pub(super) async fn player_history(
+    ctx: &async_graphql::Context<'_>,
+    mappack: AnyMappackId<'_>,
+    player_id: u32,
+) -> async_graphql::Result<Vec<MappackPlayerSnapshot>> {
+    let mysql_pool = ctx.data_unchecked::<MySqlPool>();
+    let mysql_conn = &mut mysql_pool.acquire().await?;
+    let history = mappack::player_history(mysql_conn, mappack, player_id).await?;
+    Ok(history.into_iter().map(From::from).collect())
+}
+
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_LEADERBOARDS)")]
 impl MappackPlayer<'_> {
     async fn rank(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<usize> {
         player_rank(
@@ -235,6 +268,15 @@ impl MappackPlayer<'_> {
         .await
     }
 
+    async fn points(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Option<f64>> {
+        player_points(
+            ctx,
+            AnyMappackId::Id(&self.mappack.mappack_id),
+            self.inner.inner.id,
+        )
+        .await
+    }
+
     async fn map_finished(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<usize> {
         player_map_finished(
             ctx,
@@ -252,9 +294,21 @@ impl MappackPlayer<'_> {
         )
         .await
     }
+
+    async fn history(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<MappackPlayerSnapshot>> {
+        player_history(
+            ctx,
+            AnyMappackId::Id(&self.mappack.mappack_id),
+            self.inner.inner.id,
+        )
+        .await
+    }
 }
 
-#[async_graphql::Object]
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_LEADERBOARDS)")]
 impl Mappack {
     async fn nb_maps(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<usize> {
         let redis_pool = ctx.data_unchecked::<RedisPool>();
@@ -328,6 +382,36 @@ impl Mappack {
         Ok(out)
     }
 
+    async fn leaderboard_at(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        date: chrono::NaiveDateTime,
+    ) -> async_graphql::Result<Vec<MappackLeaderboardSnapshotRow>> {
+        let mysql_pool = ctx.data_unchecked::<MySqlPool>();
+        let mysql_conn = &mut mysql_pool.acquire().await?;
+
+        let standings =
+            mappack::standings_at(mysql_conn, AnyMappackId::Id(&self.mappack_id), date).await?;
+
+        let mut players = ctx
+            .data_unchecked::<DataLoader<PlayerLoader>>()
+            .load_many(standings.iter().map(|standing| standing.inner.player_id))
+            .await?;
+
+        standings
+            .into_iter()
+            .map(|standing| {
+                let player = players
+                    .remove(&standing.inner.player_id)
+                    .ok_or_else(|| async_graphql::Error::new("Player not found."))?;
+                Ok(MappackLeaderboardSnapshotRow {
+                    player,
+                    snapshot: standing.into(),
+                })
+            })
+            .collect()
+    }
+
     async fn player<'a>(
         &'a self,
         ctx: &async_graphql::Context<'_>,
@@ -385,10 +469,10 @@ pub async fn get_mappack(
             return Err(RecordsErrorKind::InvalidMappackId(mappack_id));
         };
 
-        let client = ctx.data_unchecked::<Client>();
+        let mx = ctx.data_unchecked::<MxClient>();
 
         // We fill the mappack
-        fill_mappack(client, &mut conn, mappack, mappack_id_int).await?;
+        fill_mappack(mx, &mut conn, mappack, mappack_id_int).await?;
 
         // And we update it to have its scores cached
         update_mappack(AnyMappackId::Id(&mappack_id), &mut conn)
@@ -1,43 +1,61 @@
 use actix_session::Session;
+use actix_web::dev::HttpServiceFactory;
 use actix_web::web::{self, Data};
-use actix_web::{HttpResponse, Resource, Responder};
+use actix_web::{guard, HttpRequest, HttpResponse, Responder};
 use async_graphql::dataloader::DataLoader;
 use async_graphql::extensions::ApolloTracing;
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql::{connection, Enum, ErrorExtensionValues, Object, Value, ID};
-use async_graphql_actix_web::GraphQLRequest;
+use async_graphql_actix_web::{GraphQLRequest, GraphQLSubscription};
 use futures::StreamExt as _;
 use records_lib::models;
+use records_lib::mx::MxClient;
 use records_lib::update_ranks::get_rank;
 use records_lib::{must, Database};
-use reqwest::Client;
 use sqlx::{mysql, query_as, FromRow, MySqlPool, Row};
 use std::vec::Vec;
 use tracing_actix_web::RequestId;
 
-use crate::auth::{self, privilege, WebToken, WEB_TOKEN_SESS_KEY};
+use crate::api_key::{self as scope, Scope};
+use crate::audit::{self, Action};
+use crate::auth::{self, permission, ApiKeyGuard, MPTokenInfo, WebToken, WEB_TOKEN_SESS_KEY};
 use crate::graphql::map::MapLoader;
 use crate::graphql::player::PlayerLoader;
+use crate::rate_limit::RateLimit;
 
+use self::ac_report::{AcReport, AcReportVerdict};
+use self::api_key::{ApiKey, CreatedApiKey};
+use self::audit_log::AuditLogEntry;
 use self::ban::Banishment;
-use self::event::{Event, EventCategoryLoader, EventEdition, EventLoader};
+use self::event::{Event, EventCategoryLoader, EventEdition, EventEditionInput, EventLoader};
 use self::map::Map;
 use self::mappack::Mappack;
 use self::player::Player;
 use self::record::RankedRecord;
+use self::subscription::SubscriptionRoot;
+use self::suspicious_record::SuspiciousRecord;
 use self::utils::{
-    connections_append_query_string, connections_bind_query_parameters, connections_pages_info,
-    decode_id,
+    check_api_key_scope, connections_append_query_string, connections_bind_query_parameters,
+    connections_pages_info, decode_id, request_id,
 };
+use self::world_record::WorldRecord;
 
+mod ac_report;
+mod api_key;
+mod audit_log;
 mod ban;
 mod event;
 mod map;
 mod mappack;
+mod moderation;
 mod player;
 mod rating;
 mod record;
+mod subscription;
+mod suspicious_record;
+mod token;
 mod utils;
+mod world_record;
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Enum)]
 pub(crate) enum SortState {
@@ -61,6 +79,11 @@ enum Node {
     Player(Player),
 }
 
+const LAST_RESOURCES_CONTENT: &str = "SELECT content, created_at AS last_modified
+    FROM resources_content
+    ORDER BY created_at DESC
+    LIMIT 1";
+
 struct QueryRoot;
 
 #[async_graphql::Object]
@@ -70,14 +93,9 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
     ) -> async_graphql::Result<models::ResourcesContent> {
         let mysql_pool = ctx.data_unchecked::<MySqlPool>();
-        let txt = sqlx::query_as(
-            "SELECT content, created_at AS last_modified
-            FROM resources_content
-            ORDER BY created_at DESC
-            LIMIT 1",
-        )
-        .fetch_one(mysql_pool)
-        .await?;
+        let txt = sqlx::query_as(LAST_RESOURCES_CONTENT)
+            .fetch_one(mysql_pool)
+            .await?;
         Ok(txt)
     }
 
@@ -86,6 +104,7 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         mx_id: i64,
     ) -> async_graphql::Result<Option<EventEdition>> {
+        check_api_key_scope(ctx, scope::READ_EVENTS)?;
         let mysql_pool = ctx.data_unchecked::<MySqlPool>();
 
         let edition = sqlx::query_as::<_, models::EventEdition>(
@@ -106,6 +125,7 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         mappack_id: String,
     ) -> async_graphql::Result<Mappack> {
+        check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
         let res = mappack::get_mappack(ctx, mappack_id).await?;
         Ok(res)
     }
@@ -118,7 +138,7 @@ impl QueryRoot {
         let Some(WebToken { login, token }) = ctx.data_opt::<WebToken>() else {
             return Err(async_graphql::Error::new("Unauthorized"));
         };
-        auth::website_check_auth_for(db, login, token, privilege::ADMIN).await?;
+        auth::website_check_auth_for(db, login, token, permission::MANAGE_BANS).await?;
         Ok(query_as("SELECT * FROM banishments")
             .fetch_all(&db.mysql_pool)
             .await?)
@@ -129,12 +149,14 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         handle: String,
     ) -> async_graphql::Result<Event> {
+        check_api_key_scope(ctx, scope::READ_EVENTS)?;
         let db = ctx.data_unchecked::<MySqlPool>();
         let event = must::have_event_handle(&mut *db.acquire().await?, &handle).await?;
         Ok(event.into())
     }
 
     async fn events(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Vec<Event>> {
+        check_api_key_scope(ctx, scope::READ_EVENTS)?;
         let db = ctx.data_unchecked::<MySqlPool>();
 
         Ok(query_as(
@@ -156,6 +178,7 @@ impl QueryRoot {
         first: Option<i32>,
         last: Option<i32>,
     ) -> async_graphql::Result<connection::Connection<ID, Player>> {
+        check_api_key_scope(ctx, scope::READ_PLAYERS)?;
         connection::query(
             after,
             before,
@@ -206,6 +229,7 @@ impl QueryRoot {
         first: Option<i32>,
         last: Option<i32>,
     ) -> async_graphql::Result<connection::Connection<ID, Map>> {
+        check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
         connection::query(
             after,
             before,
@@ -257,8 +281,104 @@ impl QueryRoot {
         .await
     }
 
+    #[allow(clippy::too_many_arguments)]
+    async fn world_record_history(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        map_uid: String,
+        event_handle: Option<String>,
+        edition: Option<u32>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<connection::Connection<ID, WorldRecord>> {
+        check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
+        world_record::world_record_history(
+            ctx,
+            map_uid,
+            event_handle,
+            edition,
+            after,
+            before,
+            first,
+            last,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn ac_reports(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        reviewed: Option<bool>,
+        player_login: Option<String>,
+        map_uid: Option<String>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<connection::Connection<ID, AcReport>> {
+        ac_report::ac_reports(
+            ctx,
+            reviewed,
+            player_login,
+            map_uid,
+            after,
+            before,
+            first,
+            last,
+        )
+        .await
+    }
+
+    async fn suspicious_records(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        reviewed: Option<bool>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<connection::Connection<ID, SuspiciousRecord>> {
+        suspicious_record::suspicious_records(ctx, reviewed, after, before, first, last).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn audit_log(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        actor_login: Option<String>,
+        target: Option<String>,
+        action: Option<Action>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<connection::Connection<ID, AuditLogEntry>> {
+        audit_log::audit_log(ctx, actor_login, target, action, after, before, first, last).await
+    }
+
+    async fn mp_tokens(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<MPTokenInfo>> {
+        token::mp_tokens(ctx).await
+    }
+
+    async fn api_keys(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<ApiKey>> {
+        api_key::api_keys(ctx).await
+    }
+
     // Global unique identifiers
-    async fn node(&self, ctx: &async_graphql::Context<'_>, id: async_graphql::ID) -> Option<Node> {
+    async fn node(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        id: async_graphql::ID,
+    ) -> async_graphql::Result<Option<Node>> {
         let mysql_pool = ctx.data_unchecked::<MySqlPool>();
         let parts: Vec<&str> = id.split(':').collect();
         println!("node");
@@ -270,21 +390,31 @@ impl QueryRoot {
                 parts[0],
                 parts[1]
             );
-            None
+            Ok(None)
         } else if parts[1] == "Map" {
-            let id = parts[2].parse::<u32>().ok()?;
+            check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
+            let Ok(id) = parts[2].parse::<u32>() else {
+                return Ok(None);
+            };
             let query = "SELECT id, game_id, player_id, name FROM maps WHERE id = ? ";
             println!("{} _ id: {}", &query, id);
             let query = sqlx::query(query).bind(id);
-            let result = query.fetch_one(mysql_pool).await.ok()?;
-            Some(Node::Map(Map::from_row(&result).unwrap()))
+            let Ok(result) = query.fetch_one(mysql_pool).await else {
+                return Ok(None);
+            };
+            Ok(Some(Node::Map(Map::from_row(&result).unwrap())))
         } else {
-            let id = parts[2].parse::<u32>().ok()?;
+            check_api_key_scope(ctx, scope::READ_PLAYERS)?;
+            let Ok(id) = parts[2].parse::<u32>() else {
+                return Ok(None);
+            };
             let query = "SELECT id, login, name FROM players WHERE id = ? ";
             println!("{} _ id: {}", &query, id);
             let query = sqlx::query(query).bind(id);
-            let result = query.fetch_one(mysql_pool).await.ok()?;
-            Some(Node::Player(Player::from_row(&result).unwrap()))
+            let Ok(result) = query.fetch_one(mysql_pool).await else {
+                return Ok(None);
+            };
+            Ok(Some(Node::Player(Player::from_row(&result).unwrap())))
         }
     }
 
@@ -295,6 +425,7 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         record_id: u32,
     ) -> async_graphql::Result<RankedRecord> {
+        check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
         let db = ctx.data_unchecked::<Database>();
         let mut conn = db.acquire().await?;
 
@@ -319,6 +450,7 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         game_id: String,
     ) -> async_graphql::Result<Map> {
+        check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
         let db = ctx.data_unchecked::<MySqlPool>();
         let mut conn = db.acquire().await?;
         records_lib::map::get_map_from_uid(&mut conn, &game_id)
@@ -332,6 +464,7 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         login: String,
     ) -> async_graphql::Result<Player> {
+        check_api_key_scope(ctx, scope::READ_PLAYERS)?;
         let mysql_pool = ctx.data_unchecked::<MySqlPool>();
         let query =
             sqlx::query_as::<_, Player>("SELECT * FROM players WHERE login = ? ").bind(login);
@@ -343,6 +476,7 @@ impl QueryRoot {
         ctx: &async_graphql::Context<'_>,
         date_sort_by: Option<SortState>,
     ) -> async_graphql::Result<Vec<RankedRecord>> {
+        check_api_key_scope(ctx, scope::READ_LEADERBOARDS)?;
         let db = ctx.data_unchecked::<Database>();
 
         let date_sort_by = SortState::sql_order_by(&date_sort_by);
@@ -384,21 +518,126 @@ impl MutationRoot {
         let web_token = ctx
             .data_opt::<WebToken>()
             .ok_or_else(|| async_graphql::Error::new("Unauthorized"))?;
-        auth::website_check_auth_for(db, &web_token.login, &web_token.token, privilege::ADMIN)
-            .await?;
+        let admin_id = auth::website_check_auth_for(
+            db,
+            &web_token.login,
+            &web_token.token,
+            permission::EDIT_RESOURCES,
+        )
+        .await?;
 
-        let mysql_conn = &mut db.mysql_pool.acquire().await?;
+        let mut txn = db.mysql_pool.begin().await?;
+
+        let before: Option<models::ResourcesContent> = sqlx::query_as(LAST_RESOURCES_CONTENT)
+            .fetch_optional(&mut *txn)
+            .await?;
 
         sqlx::query("INSERT INTO resources_content (content, created_at) VALUES (?, SYSDATE())")
             .bind(text)
-            .execute(&mut **mysql_conn)
+            .execute(&mut *txn)
             .await?;
-        let res = sqlx::query_as("SELECT * FROM resources_content")
-            .fetch_one(&mut **mysql_conn)
+        let res = sqlx::query_as(LAST_RESOURCES_CONTENT)
+            .fetch_one(&mut *txn)
             .await?;
+
+        audit::log(
+            &mut txn,
+            request_id(ctx)?,
+            admin_id,
+            Action::UpdateResourcesContent,
+            audit::RESOURCES_CONTENT_TARGET,
+            &before,
+            &res,
+        )
+        .await?;
+
+        txn.commit().await?;
+
         Ok(res)
     }
 
+    async fn review_ac_report(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        report_id: u32,
+        verdict: AcReportVerdict,
+        #[graphql(default = true)] invalidate_record: bool,
+    ) -> async_graphql::Result<AcReport> {
+        ac_report::review_ac_report(ctx, report_id, verdict, invalidate_record).await
+    }
+
+    async fn review_suspicious_record(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        record_id: u32,
+        approve: bool,
+    ) -> async_graphql::Result<SuspiciousRecord> {
+        suspicious_record::review_suspicious_record(ctx, record_id, approve).await
+    }
+
+    async fn invalidate_record(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        record_id: u32,
+    ) -> async_graphql::Result<Vec<u32>> {
+        moderation::invalidate_record(ctx, record_id).await
+    }
+
+    async fn restore_record(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        record_id: u32,
+    ) -> async_graphql::Result<Vec<u32>> {
+        moderation::restore_record(ctx, record_id).await
+    }
+
+    async fn update_event_edition(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        event_handle: String,
+        edition_id: u32,
+        input: EventEditionInput,
+    ) -> async_graphql::Result<EventEdition> {
+        event::update_event_edition(ctx, event_handle, edition_id, input).await
+    }
+
+    async fn logout(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<bool> {
+        token::logout(ctx).await
+    }
+
+    async fn revoke_mp_token(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        token_id: String,
+    ) -> async_graphql::Result<bool> {
+        token::revoke_mp_token(ctx, token_id).await
+    }
+
+    async fn revoke_tokens(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        player_login: String,
+    ) -> async_graphql::Result<Vec<MPTokenInfo>> {
+        token::revoke_tokens(ctx, player_login).await
+    }
+
+    async fn create_api_key(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        name: String,
+        scopes: Vec<Scope>,
+    ) -> async_graphql::Result<CreatedApiKey> {
+        api_key::create_api_key(ctx, name, scopes).await
+    }
+
+    async fn revoke_api_key(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        api_key_id: u32,
+    ) -> async_graphql::Result<ApiKey> {
+        api_key::revoke_api_key(ctx, api_key_id).await
+    }
+
     async fn calc_mappack_scores(
         &self,
         ctx: &async_graphql::Context<'_>,
@@ -410,35 +649,34 @@ impl MutationRoot {
     }
 }
 
-type Schema = async_graphql::Schema<QueryRoot, MutationRoot, async_graphql::EmptySubscription>;
+type Schema = async_graphql::Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 #[allow(clippy::let_and_return)]
-fn create_schema(db: Database, client: Client) -> Schema {
-    let schema =
-        async_graphql::Schema::build(QueryRoot, MutationRoot, async_graphql::EmptySubscription)
-            .extension(ApolloTracing)
-            .data(DataLoader::new(
-                PlayerLoader(db.mysql_pool.clone()),
-                tokio::spawn,
-            ))
-            .data(DataLoader::new(
-                MapLoader(db.mysql_pool.clone()),
-                tokio::spawn,
-            ))
-            .data(DataLoader::new(
-                EventLoader(db.mysql_pool.clone()),
-                tokio::spawn,
-            ))
-            .data(DataLoader::new(
-                EventCategoryLoader(db.mysql_pool.clone()),
-                tokio::spawn,
-            ))
-            .data(db.mysql_pool.clone())
-            .data(db.redis_pool.clone())
-            .data(db)
-            .data(client)
-            .limit_depth(16)
-            .finish();
+fn create_schema(db: Database, mx: MxClient) -> Schema {
+    let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .extension(ApolloTracing)
+        .data(DataLoader::new(
+            PlayerLoader(db.mysql_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            MapLoader(db.mysql_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            EventLoader(db.mysql_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            EventCategoryLoader(db.mysql_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(db.mysql_pool.clone())
+        .data(db.redis_pool.clone())
+        .data(db)
+        .data(mx)
+        .limit_depth(16)
+        .finish();
 
     #[cfg(feature = "gql_schema")]
     {
@@ -466,6 +704,7 @@ fn create_schema(db: Database, client: Client) -> Schema {
 async fn index_graphql(
     request_id: RequestId,
     session: Session,
+    ApiKeyGuard { api_key }: ApiKeyGuard,
     schema: Data<Schema>,
     GraphQLRequest(request): GraphQLRequest,
 ) -> impl Responder {
@@ -474,10 +713,16 @@ async fn index_graphql(
         .expect("unable to retrieve web token");
 
     let request = {
-        if let Some(web_token) = web_token {
+        let request = request.data(request_id);
+        let request = if let Some(web_token) = web_token {
             request.data(web_token)
         } else {
             request
+        };
+        if let Some(api_key) = api_key {
+            request.data(api_key)
+        } else {
+            request
         }
     };
 
@@ -493,17 +738,40 @@ async fn index_graphql(
     web::Json(result)
 }
 
+async fn index_subscription(
+    ApiKeyGuard { api_key }: ApiKeyGuard,
+    schema: Data<Schema>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let mut data = async_graphql::Data::default();
+    if let Some(api_key) = api_key {
+        data.insert(api_key);
+    }
+
+    GraphQLSubscription::new(Schema::clone(&schema))
+        .with_data(data)
+        .start(&req, payload)
+}
+
 async fn index_playground() -> impl Responder {
+    let endpoint = &crate::env().gql_endpoint;
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
-        .body(playground_source(GraphQLPlaygroundConfig::new(
-            &crate::env().gql_endpoint,
-        )))
+        .body(playground_source(
+            GraphQLPlaygroundConfig::new(endpoint).subscription_endpoint(endpoint),
+        ))
 }
 
-pub fn graphql_route(db: Database, client: Client) -> Resource {
+pub fn graphql_route(db: Database, mx: MxClient) -> impl HttpServiceFactory {
     web::resource("/graphql")
-        .app_data(Data::new(create_schema(db, client)))
+        .wrap(RateLimit::new("graphql", crate::env().rate_limit_graphql))
+        .app_data(Data::new(create_schema(db, mx)))
+        .route(
+            web::get()
+                .guard(guard::Header("upgrade", "websocket"))
+                .to(index_subscription),
+        )
         .route(web::get().to(index_playground))
         .route(web::post().to(index_graphql))
 }
@@ -0,0 +1,70 @@
+use async_graphql::Context;
+use records_lib::{moderation, Database};
+use sqlx::Connection as _;
+
+use crate::{
+    audit::{self, Action},
+    auth::permission,
+};
+
+use super::utils::{check_auth_for, request_id};
+
+pub(super) async fn invalidate_record(
+    ctx: &Context<'_>,
+    record_id: u32,
+) -> async_graphql::Result<Vec<u32>> {
+    let admin_id = check_auth_for(ctx, permission::MODERATE_RECORDS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut conn = db.acquire().await?;
+
+    let mut txn = conn.mysql_conn.begin().await?;
+
+    let record_ids = moderation::invalidate_record(&mut txn, record_id).await?;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        admin_id,
+        Action::InvalidateRecord,
+        &audit::record_target(record_id),
+        &(),
+        &serde_json::json!({ "record_ids": record_ids }),
+    )
+    .await?;
+
+    txn.commit().await?;
+    moderation::recompute(&mut conn, &record_ids).await?;
+
+    Ok(record_ids)
+}
+
+pub(super) async fn restore_record(
+    ctx: &Context<'_>,
+    record_id: u32,
+) -> async_graphql::Result<Vec<u32>> {
+    let admin_id = check_auth_for(ctx, permission::MODERATE_RECORDS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut conn = db.acquire().await?;
+
+    let mut txn = conn.mysql_conn.begin().await?;
+
+    let record_ids = moderation::restore_record(&mut txn, record_id).await?;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        admin_id,
+        Action::RestoreRecord,
+        &audit::record_target(record_id),
+        &(),
+        &serde_json::json!({ "record_ids": record_ids }),
+    )
+    .await?;
+
+    txn.commit().await?;
+    moderation::recompute(&mut conn, &record_ids).await?;
+
+    Ok(record_ids)
+}
@@ -8,7 +8,7 @@ use records_lib::{
 };
 use sqlx::{mysql, FromRow, MySqlPool, Row};
 
-use crate::RecordsErrorKind;
+use crate::{api_key as scope, RecordsErrorKind};
 
 use super::{
     ban::Banishment,
@@ -18,7 +18,7 @@ use super::{
     utils::{
         connections_append_query_string_order, connections_append_query_string_page,
         connections_bind_query_parameters_order, connections_bind_query_parameters_page,
-        connections_pages_info, decode_id,
+        connections_pages_info, decode_id, ApiKeyScopeGuard,
     },
     SortState,
 };
@@ -56,7 +56,7 @@ impl From<models::Player> for Player {
     }
 }
 
-#[async_graphql::Object]
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_PLAYERS)")]
 impl Player {
     pub async fn id(&self) -> ID {
         ID(format!("v0:Player:{}", self.inner.id))
@@ -4,9 +4,12 @@ use records_lib::{
     Database, MySqlPool,
 };
 
+use crate::api_key as scope;
+
 use super::{
     map::{Map, MapLoader},
     player::{Player, PlayerLoader},
+    utils::ApiKeyScopeGuard,
 };
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -21,7 +24,7 @@ impl From<models::RankedRecord> for RankedRecord {
     }
 }
 
-#[async_graphql::Object]
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_LEADERBOARDS)")]
 impl RankedRecord {
     async fn id(&self) -> u32 {
         self.inner.record.record_id
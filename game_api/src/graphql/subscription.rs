@@ -0,0 +1,99 @@
+use async_graphql::SimpleObject;
+use futures::{Stream, StreamExt as _};
+
+use crate::{
+    api_key as scope,
+    record_events::{self, RecordEvent},
+};
+
+use super::utils::ApiKeyScopeGuard;
+
+#[derive(SimpleObject)]
+struct NewRecord {
+    record_id: u32,
+    map_uid: String,
+    event_handle: Option<String>,
+    edition_id: Option<u32>,
+    player_login: String,
+    player_name: String,
+    time: i32,
+    rank: i32,
+    has_improved: bool,
+    record_date: chrono::NaiveDateTime,
+}
+
+impl From<RecordEvent> for NewRecord {
+    fn from(event: RecordEvent) -> Self {
+        Self {
+            record_id: event.record_id,
+            map_uid: event.map_uid,
+            event_handle: event.event_handle,
+            edition_id: event.edition_id,
+            player_login: event.player_login,
+            player_name: event.player_name,
+            time: event.time,
+            rank: event.rank,
+            has_improved: event.has_improved,
+            record_date: event.record_date,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct LeaderboardChange {
+    map_uid: String,
+    event_handle: Option<String>,
+    edition_id: Option<u32>,
+    player_login: String,
+    player_name: String,
+    time: i32,
+    rank: i32,
+    old_rank: Option<i32>,
+}
+
+impl From<RecordEvent> for LeaderboardChange {
+    fn from(event: RecordEvent) -> Self {
+        Self {
+            map_uid: event.map_uid,
+            event_handle: event.event_handle,
+            edition_id: event.edition_id,
+            player_login: event.player_login,
+            player_name: event.player_name,
+            time: event.time,
+            rank: event.rank,
+            old_rank: event.old_rank,
+        }
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[async_graphql::Subscription(guard = "ApiKeyScopeGuard(scope::READ_LEADERBOARDS)")]
+impl SubscriptionRoot {
+    async fn new_record(
+        &self,
+        map_uid: Option<String>,
+        event_handle: Option<String>,
+        edition: Option<u32>,
+    ) -> impl Stream<Item = NewRecord> {
+        record_events::subscribe()
+            .filter(move |event| {
+                let keep = map_uid.as_ref().is_none_or(|uid| *uid == event.map_uid)
+                    && event_handle
+                        .as_ref()
+                        .is_none_or(|handle| event.event_handle.as_ref() == Some(handle))
+                    && edition.is_none_or(|edition| event.edition_id == Some(edition));
+                async move { keep }
+            })
+            .map(From::from)
+    }
+
+    async fn leaderboard_changed(&self, map_uid: String) -> impl Stream<Item = LeaderboardChange> {
+        record_events::subscribe()
+            .filter(move |event| {
+                let keep = event.has_improved && event.map_uid == map_uid;
+                async move { keep }
+            })
+            .map(From::from)
+    }
+}
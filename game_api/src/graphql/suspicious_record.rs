@@ -0,0 +1,214 @@
+use async_graphql::{connection, dataloader::DataLoader, Context, ID};
+use records_lib::{models, moderation, Database};
+use sqlx::{mysql, Connection as _, FromRow, Row as _};
+
+use crate::{
+    audit::{self, Action},
+    auth::permission,
+};
+
+use super::{
+    map::{Map, MapLoader},
+    player::{Player, PlayerLoader},
+    utils::{
+        check_auth_for, connections_append_query_string, connections_bind_query_parameters,
+        connections_pages_info, decode_id, request_id,
+    },
+};
+
+#[derive(FromRow)]
+pub struct SuspiciousRecord {
+    #[sqlx(flatten)]
+    inner: models::SuspiciousRecord,
+    record_player_id: u32,
+    map_id: u32,
+    time: i32,
+    record_date: chrono::NaiveDateTime,
+}
+
+#[async_graphql::Object]
+impl SuspiciousRecord {
+    async fn id(&self) -> ID {
+        ID(format!("v0:SuspiciousRecord:{}", self.inner.id))
+    }
+
+    async fn record_id(&self) -> u32 {
+        self.inner.record_id
+    }
+
+    async fn player(&self, ctx: &Context<'_>) -> async_graphql::Result<Player> {
+        ctx.data_unchecked::<DataLoader<PlayerLoader>>()
+            .load_one(self.record_player_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Player not found."))
+    }
+
+    async fn map(&self, ctx: &Context<'_>) -> async_graphql::Result<Map> {
+        ctx.data_unchecked::<DataLoader<MapLoader>>()
+            .load_one(self.map_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Map not found."))
+    }
+
+    async fn time(&self) -> i32 {
+        self.time
+    }
+
+    async fn record_date(&self) -> chrono::NaiveDateTime {
+        self.record_date
+    }
+
+    async fn reason(&self) -> &str {
+        &self.inner.reason
+    }
+
+    async fn flagged_at(&self) -> chrono::NaiveDateTime {
+        self.inner.flagged_at
+    }
+
+    async fn reviewed_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.inner.reviewed_at
+    }
+
+    async fn reviewed_by(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Player>> {
+        match self.inner.reviewed_by {
+            Some(reviewed_by) => Ok(ctx
+                .data_unchecked::<DataLoader<PlayerLoader>>()
+                .load_one(reviewed_by)
+                .await?),
+            None => Ok(None),
+        }
+    }
+
+    async fn approved(&self) -> Option<bool> {
+        self.inner.approved
+    }
+}
+
+const SELECT_SUSPICIOUS_RECORDS: &str =
+    "SELECT sr.*, r.record_player_id, r.map_id, r.time, r.record_date
+    FROM suspicious_records sr
+    INNER JOIN records r ON r.record_id = sr.record_id ";
+
+pub(super) async fn suspicious_records(
+    ctx: &Context<'_>,
+    reviewed: Option<bool>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> async_graphql::Result<connection::Connection<ID, SuspiciousRecord>> {
+    check_auth_for(ctx, permission::MODERATE_RECORDS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+
+    connection::query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<ID>, before: Option<ID>, first: Option<usize>, last: Option<usize>| async move {
+            let after = decode_id(after.as_ref());
+            let before = decode_id(before.as_ref());
+
+            // Build the query string
+            let mut query = String::from(SELECT_SUSPICIOUS_RECORDS);
+            match reviewed {
+                Some(true) => query.push_str("WHERE sr.reviewed_at IS NOT NULL "),
+                Some(false) => query.push_str("WHERE sr.reviewed_at IS NULL "),
+                None => (),
+            }
+            connections_append_query_string(
+                &mut query,
+                reviewed.is_some(),
+                after,
+                before,
+                first,
+                last,
+            );
+            let reversed = first.is_none() && last.is_some();
+
+            // Bind the parameters
+            let mut query = sqlx::query(&query);
+            query = connections_bind_query_parameters(query, after, before, first, last);
+
+            // Execute the query
+            let mut records = query
+                .map(|x: mysql::MySqlRow| {
+                    let cursor = ID(format!("v0:SuspiciousRecord:{}", x.get::<u32, _>("id")));
+                    connection::Edge::new(cursor, SuspiciousRecord::from_row(&x).unwrap())
+                })
+                .fetch_all(&db.mysql_pool)
+                .await?;
+            if reversed {
+                records.reverse();
+            }
+
+            let (has_previous_page, has_next_page) =
+                connections_pages_info(records.len(), first, last);
+            let mut connection = connection::Connection::new(has_previous_page, has_next_page);
+            connection.edges.extend(records);
+
+            Ok::<_, sqlx::Error>(connection)
+        },
+    )
+    .await
+}
+
+pub(super) async fn review_suspicious_record(
+    ctx: &Context<'_>,
+    record_id: u32,
+    approve: bool,
+) -> async_graphql::Result<SuspiciousRecord> {
+    let reviewer_id = check_auth_for(ctx, permission::MODERATE_RECORDS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut conn = db.acquire().await?;
+
+    let mut txn = conn.mysql_conn.begin().await?;
+
+    let before: Option<bool> =
+        sqlx::query_scalar("SELECT approved FROM suspicious_records WHERE record_id = ?")
+            .bind(record_id)
+            .fetch_optional(&mut *txn)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Suspicious record not found."))?;
+
+    sqlx::query(
+        "UPDATE suspicious_records SET reviewed_at = SYSDATE(), reviewed_by = ?, approved = ?
+        WHERE record_id = ?",
+    )
+    .bind(reviewer_id)
+    .bind(approve)
+    .bind(record_id)
+    .execute(&mut *txn)
+    .await?;
+
+    let record_ids = if approve {
+        moderation::approve_record(&mut txn, record_id).await?
+    } else {
+        moderation::invalidate_record(&mut txn, record_id).await?
+    };
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        reviewer_id,
+        Action::ReviewSuspiciousRecord,
+        &audit::record_target(record_id),
+        &serde_json::json!({ "approved": before }),
+        &serde_json::json!({ "approved": approve, "record_ids": record_ids }),
+    )
+    .await?;
+
+    txn.commit().await?;
+    moderation::recompute(&mut conn, &record_ids).await?;
+
+    let query = format!("{SELECT_SUSPICIOUS_RECORDS} WHERE sr.record_id = ?");
+    let record = sqlx::query_as(&query)
+        .bind(record_id)
+        .fetch_one(&mut *conn.mysql_conn)
+        .await?;
+
+    Ok(record)
+}
@@ -0,0 +1,65 @@
+use async_graphql::Context;
+use records_lib::Database;
+
+use crate::{
+    audit::{self, Action},
+    auth::{self, permission, MPTokenInfo, WebToken},
+};
+
+use super::utils::{check_auth_for, request_id};
+
+fn web_token<'a>(ctx: &'a Context<'_>) -> async_graphql::Result<&'a WebToken> {
+    ctx.data_opt::<WebToken>()
+        .ok_or_else(|| async_graphql::Error::new("Unauthorized"))
+}
+
+pub(super) async fn mp_tokens(ctx: &Context<'_>) -> async_graphql::Result<Vec<MPTokenInfo>> {
+    check_auth_for(ctx, permission::NONE).await?;
+    let db = ctx.data_unchecked::<Database>();
+    Ok(auth::mp_tokens_of(db, &web_token(ctx)?.login).await?)
+}
+
+pub(super) async fn logout(ctx: &Context<'_>) -> async_graphql::Result<bool> {
+    let db = ctx.data_unchecked::<Database>();
+    Ok(auth::logout(db, web_token(ctx)?).await?)
+}
+
+pub(super) async fn revoke_mp_token(
+    ctx: &Context<'_>,
+    token_id: String,
+) -> async_graphql::Result<bool> {
+    check_auth_for(ctx, permission::NONE).await?;
+    let db = ctx.data_unchecked::<Database>();
+    Ok(auth::revoke_mp_token(db, &web_token(ctx)?.login, &token_id).await?)
+}
+
+pub(super) async fn revoke_tokens(
+    ctx: &Context<'_>,
+    player_login: String,
+) -> async_graphql::Result<Vec<MPTokenInfo>> {
+    let admin_id = check_auth_for(ctx, permission::MANAGE_BANS).await?;
+
+    let db = ctx.data_unchecked::<Database>();
+    let mut txn = db.mysql_pool.begin().await?;
+
+    // The tokens are stored in Redis, so the audit entry is written first, and only committed
+    // once they're revoked
+    let tokens = auth::mp_tokens_of(db, &player_login).await?;
+
+    audit::log(
+        &mut txn,
+        request_id(ctx)?,
+        admin_id,
+        Action::RevokeTokens,
+        &audit::player_target(&player_login),
+        &serde_json::json!({ "mp_tokens": tokens }),
+        &(),
+    )
+    .await?;
+
+    let revoked = auth::revoke_tokens_of(db, &player_login).await?;
+
+    txn.commit().await?;
+
+    Ok(revoked)
+}
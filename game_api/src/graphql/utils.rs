@@ -1,9 +1,87 @@
-use async_graphql::ID;
+use async_graphql::{Context, ID};
+use records_lib::Database;
 use sqlx::mysql;
+use tracing_actix_web::RequestId;
+
+use crate::{
+    api_key::{self, CheckedApiKey},
+    auth::{self, permission, WebToken},
+};
+
+/// Checks that the authenticated user has the required permissions, and returns their player ID.
+pub async fn check_auth_for(
+    ctx: &Context<'_>,
+    required: permission::Flags,
+) -> async_graphql::Result<u32> {
+    let db = ctx.data_unchecked::<Database>();
+    let Some(WebToken { login, token }) = ctx.data_opt::<WebToken>() else {
+        return Err(async_graphql::Error::new("Unauthorized"));
+    };
+    Ok(auth::website_check_auth_for(db, login, token, required).await?)
+}
+
+/// Checks that the authenticated user has the required permissions on the event edition,
+/// and returns their player ID.
+///
+/// The permissions may be granted by the role of the user, or because they're one of the admins
+/// of the edition.
+pub async fn check_auth_for_edition(
+    ctx: &Context<'_>,
+    required: permission::Flags,
+    event_id: u32,
+    edition_id: u32,
+) -> async_graphql::Result<u32> {
+    let db = ctx.data_unchecked::<Database>();
+    let Some(WebToken { login, token }) = ctx.data_opt::<WebToken>() else {
+        return Err(async_graphql::Error::new("Unauthorized"));
+    };
+    Ok(
+        auth::website_check_auth_for_edition(db, login, token, required, (event_id, edition_id))
+            .await?,
+    )
+}
+
+/// Checks that the API key of the request, if any, has the required scopes.
+///
+/// The key itself has already been checked when receiving the request.
+pub fn check_api_key_scope(
+    ctx: &Context<'_>,
+    required: api_key::Scopes,
+) -> async_graphql::Result<()> {
+    match ctx.data_opt::<CheckedApiKey>() {
+        Some(api_key) if api_key.scopes & required != required => {
+            Err(async_graphql::Error::new("Forbidden"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A guard checking that the API key of the request, if any, has the required scopes.
+///
+/// It is put on the object types, so that their fields can't be read through a field
+/// that isn't checked, such as `node` or the fields of the other object types.
+pub struct ApiKeyScopeGuard(pub api_key::Scopes);
+
+impl async_graphql::Guard for ApiKeyScopeGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        check_api_key_scope(ctx, self.0)
+    }
+}
+
+/// Returns the ID of the HTTP request of the GraphQL operation.
+pub fn request_id(ctx: &Context<'_>) -> async_graphql::Result<RequestId> {
+    ctx.data::<RequestId>().copied()
+}
 
 pub fn decode_id(id: Option<&ID>) -> Option<u32> {
     let parts: Vec<&str> = id?.split(':').collect();
-    if parts.len() != 3 || parts[0] != "v0" || (parts[1] != "Map" && parts[1] != "Player") {
+    if parts.len() != 3
+        || parts[0] != "v0"
+        || !matches!(
+            parts[1],
+            "Map" | "Player" | "WorldRecord" | "AcReport" | "SuspiciousRecord" | "AuditLogEntry"
+        )
+    {
         println!(
             "invalid, len: {}, [0]: {}, [1]: {}",
             parts.len(),
@@ -0,0 +1,137 @@
+use async_graphql::{connection, dataloader::DataLoader, Context, ID};
+use records_lib::{models, must, MySqlPool};
+use sqlx::{mysql, FromRow as _, Row as _};
+
+use crate::api_key as scope;
+
+use super::{
+    map::{Map, MapLoader},
+    player::{Player, PlayerLoader},
+    utils::{
+        connections_append_query_string, connections_bind_query_parameters, connections_pages_info,
+        decode_id, ApiKeyScopeGuard,
+    },
+};
+
+pub struct WorldRecord {
+    inner: models::WorldRecord,
+}
+
+impl From<models::WorldRecord> for WorldRecord {
+    fn from(inner: models::WorldRecord) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_graphql::Object(guard = "ApiKeyScopeGuard(scope::READ_LEADERBOARDS)")]
+impl WorldRecord {
+    async fn id(&self) -> ID {
+        ID(format!("v0:WorldRecord:{}", self.inner.id))
+    }
+
+    async fn record_id(&self) -> u32 {
+        self.inner.record_id
+    }
+
+    async fn time(&self) -> i32 {
+        self.inner.time
+    }
+
+    async fn record_date(&self) -> chrono::NaiveDateTime {
+        self.inner.record_date
+    }
+
+    async fn map(&self, ctx: &Context<'_>) -> async_graphql::Result<Map> {
+        ctx.data_unchecked::<DataLoader<MapLoader>>()
+            .load_one(self.inner.map_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Map not found."))
+    }
+
+    async fn player(&self, ctx: &Context<'_>) -> async_graphql::Result<Player> {
+        ctx.data_unchecked::<DataLoader<PlayerLoader>>()
+            .load_one(self.inner.player_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Player not found."))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn world_record_history(
+    ctx: &Context<'_>,
+    map_uid: String,
+    event_handle: Option<String>,
+    edition: Option<u32>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> async_graphql::Result<connection::Connection<ID, WorldRecord>> {
+    let mysql_pool = ctx.data_unchecked::<MySqlPool>();
+    let mut mysql_conn = mysql_pool.acquire().await?;
+
+    let map = must::have_map(&mut mysql_conn, &map_uid).await?;
+    let event = match (event_handle, edition) {
+        (Some(handle), Some(edition)) => {
+            let (event, edition) =
+                must::have_event_edition(&mut mysql_conn, &handle, edition).await?;
+            Some((event.id, edition.id))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(async_graphql::Error::new(
+                "Both the event handle and the edition must be provided.",
+            ))
+        }
+    };
+    let (event_id, edition_id) = event.unzip();
+
+    connection::query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<ID>, before: Option<ID>, first: Option<usize>, last: Option<usize>| async move {
+            let after = decode_id(after.as_ref());
+            let before = decode_id(before.as_ref());
+
+            // Build the query string
+            let mut query = String::from(
+                "SELECT * FROM world_records
+                WHERE map_id = ? AND event_id <=> ? AND edition_id <=> ? ",
+            );
+            connections_append_query_string(&mut query, true, after, before, first, last);
+            let reversed = first.is_none() && last.is_some();
+
+            // Bind the parameters
+            let mut query = sqlx::query(&query)
+                .bind(map.id)
+                .bind(event_id)
+                .bind(edition_id);
+            query = connections_bind_query_parameters(query, after, before, first, last);
+
+            // Execute the query
+            let mut world_records = query
+                .map(|x: mysql::MySqlRow| {
+                    let cursor = ID(format!("v0:WorldRecord:{}", x.get::<u32, _>("id")));
+                    connection::Edge::new(
+                        cursor,
+                        WorldRecord::from(models::WorldRecord::from_row(&x).unwrap()),
+                    )
+                })
+                .fetch_all(&mut *mysql_conn)
+                .await?;
+            if reversed {
+                world_records.reverse();
+            }
+
+            let (has_previous_page, has_next_page) =
+                connections_pages_info(world_records.len(), first, last);
+            let mut connection = connection::Connection::new(has_previous_page, has_next_page);
+            connection.edges.extend(world_records);
+
+            Ok::<_, sqlx::Error>(connection)
+        },
+    )
+    .await
+}
@@ -2,13 +2,15 @@ use actix_web::{
     web::{self, Json, Query},
     HttpResponse, Responder, Scope,
 };
-use records_lib::Database;
+use records_lib::{moderation, Database};
 use serde::{Deserialize, Serialize};
-use sqlx::{mysql::MySqlRow, FromRow, Row};
+use sqlx::{mysql::MySqlRow, Connection as _, FromRow, Row};
 use tracing_actix_web::RequestId;
 
 use crate::{
-    auth::{privilege, MPAuthGuard},
+    api_key,
+    audit::{self, Action},
+    auth::{self, permission, MPAuthGuard},
     utils::json,
     FitRequestId, RecordsErrorKind, RecordsResponse, RecordsResult, RecordsResultExt, Res,
 };
@@ -21,6 +23,12 @@ pub fn admin_scope() -> Scope {
         .route("/ban", web::post().to(ban))
         .route("/unban", web::post().to(unban))
         .route("/player_note", web::get().to(player_note))
+        .route("/invalidate_record", web::post().to(invalidate_record))
+        .route("/restore_record", web::post().to(restore_record))
+        .route("/revoke_tokens", web::post().to(revoke_tokens))
+        .route("/api_keys", web::get().to(api_keys))
+        .route("/create_api_key", web::post().to(create_api_key))
+        .route("/revoke_api_key", web::post().to(revoke_api_key))
 }
 
 #[derive(Deserialize)]
@@ -29,18 +37,42 @@ pub struct DelNoteBody {
 }
 
 pub async fn del_note(
-    _: MPAuthGuard<{ privilege::ADMIN }>,
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_BANS }>,
     req_id: RequestId,
     db: Res<Database>,
     Json(body): Json<DelNoteBody>,
 ) -> RecordsResponse<impl Responder> {
-    sqlx::query("UPDATE players SET admins_note = NULL WHERE login = ?")
-        .bind(&body.player_login)
-        .execute(&db.mysql_pool)
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
+
+    let player = records_lib::must::have_player(&mut txn, &body.player_login)
+        .await
+        .fit(req_id)?;
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    sqlx::query("UPDATE players SET admins_note = NULL WHERE id = ?")
+        .bind(player.id)
+        .execute(&mut *txn)
         .await
         .with_api_err()
         .fit(req_id)?;
 
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::DelNote,
+        &audit::player_target(&player.login),
+        &player.admins_note,
+        &(),
+    )
+    .await
+    .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -57,26 +89,50 @@ struct SetRoleResponse {
 }
 
 pub async fn set_role(
-    _: MPAuthGuard<{ privilege::ADMIN }>,
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_ROLES }>,
     req_id: RequestId,
     db: Res<Database>,
     Json(body): Json<SetRoleBody>,
 ) -> RecordsResponse<impl Responder> {
-    sqlx::query("UPDATE players SET role = ? WHERE login = ?")
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
+
+    let player = records_lib::must::have_player(&mut txn, &body.player_login)
+        .await
+        .fit(req_id)?;
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    sqlx::query("UPDATE players SET role = ? WHERE id = ?")
         .bind(body.role)
-        .bind(&body.player_login)
-        .execute(&db.mysql_pool)
+        .bind(player.id)
+        .execute(&mut *txn)
         .await
         .with_api_err()
         .fit(req_id)?;
 
     let role = sqlx::query_scalar("SELECT role_name FROM role WHERE id = ?")
         .bind(body.role)
-        .fetch_one(&db.mysql_pool)
+        .fetch_one(&mut *txn)
         .await
         .with_api_err()
         .fit(req_id)?;
 
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::SetRole,
+        &audit::player_target(&player.login),
+        &serde_json::json!({ "role": player.role }),
+        &serde_json::json!({ "role": body.role }),
+    )
+    .await
+    .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
     json(SetRoleResponse {
         player_login: body.player_login,
         role,
@@ -102,6 +158,7 @@ pub struct Banishment {
     #[serde(flatten)]
     inner: BanishmentInner,
     was_reprieved: bool,
+    shadow: bool,
     is_current: bool,
 }
 
@@ -116,6 +173,7 @@ impl<'r> FromRow<'r, MySqlRow> for Banishment {
         Ok(Self {
             inner: BanishmentInner::from_row(row)?,
             was_reprieved: row.try_get::<i8, _>("was_reprieved")? != 0,
+            shadow: row.try_get::<i8, _>("shadow")? != 0,
             is_current,
         })
     }
@@ -128,7 +186,7 @@ struct BanishmentsResponse {
 }
 
 pub async fn banishments(
-    _: MPAuthGuard<{ privilege::ADMIN }>,
+    _: MPAuthGuard<{ permission::MANAGE_BANS }>,
     req_id: RequestId,
     db: Res<Database>,
     Query(body): Query<BanishmentsBody>,
@@ -142,7 +200,7 @@ pub async fn banishments(
 
     let banishments = sqlx::query_as(
         "SELECT
-            id, date_ban, duration, was_reprieved, reason,
+            id, date_ban, duration, was_reprieved, shadow, reason,
             (SELECT login FROM players WHERE id = banished_by) AS banished_by,
             id IN (SELECT id FROM banishments WHERE player_id = ?
                 AND (date_ban + INTERVAL duration SECOND > NOW() OR duration IS NULL)) AS is_current
@@ -167,6 +225,9 @@ pub struct BanBody {
     player_login: String,
     duration: Option<u32>,
     reason: Option<String>,
+    /// If true, the player isn't blocked, but their records are hidden from the other players.
+    #[serde(default)]
+    shadow: bool,
 }
 
 #[derive(Serialize)]
@@ -176,18 +237,18 @@ struct BanResponse {
 }
 
 pub async fn ban(
-    MPAuthGuard { login }: MPAuthGuard<{ privilege::ADMIN }>,
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_BANS }>,
     req_id: RequestId,
     db: Res<Database>,
     Json(body): Json<BanBody>,
 ) -> RecordsResponse<impl Responder> {
-    let mut mysql_conn = db.0.mysql_pool.acquire().await.with_api_err().fit(req_id)?;
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
 
-    let player_id = records_lib::must::have_player(&mut mysql_conn, &body.player_login)
+    let player_id = records_lib::must::have_player(&mut txn, &body.player_login)
         .await
         .fit(req_id)?
         .id;
-    let admin_id = records_lib::must::have_player(&mut mysql_conn, &login)
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
         .await
         .fit(req_id)?
         .id;
@@ -195,7 +256,7 @@ pub async fn ban(
     let was_reprieved =
         sqlx::query_as::<_, Banishment>("SELECT * FROM banishments WHERE player_id = ?")
             .bind(&body.player_login)
-            .fetch_optional(&mut *mysql_conn)
+            .fetch_optional(&mut *txn)
             .await
             .with_api_err()
             .fit(req_id)?
@@ -203,28 +264,51 @@ pub async fn ban(
 
     let ban_id: u32 = sqlx::query_scalar(
         "INSERT INTO banishments
-                (date_ban,  duration,   was_reprieved,  reason, player_id,  banished_by)
-        VALUES  (SYSDATE(), ?,          ?,              ?,      ?,          ?)
+                (date_ban,  duration,   was_reprieved,  shadow, reason, player_id,  banished_by)
+        VALUES  (SYSDATE(), ?,          ?,              ?,      ?,      ?,          ?)
         RETURNING id",
     )
     .bind(body.duration)
     .bind(was_reprieved)
+    .bind(body.shadow)
     .bind(body.reason)
     .bind(player_id)
     .bind(admin_id)
-    .fetch_one(&mut *mysql_conn)
+    .fetch_one(&mut *txn)
     .await
     .with_api_err()
     .fit(req_id)?;
 
     let ban = sqlx::query_as(
-        r#"SELECT id, date_ban, duration, reason, (SELECT login FROM players WHERE id = banished_by) as "banished_by", was_reprieved
+        r#"SELECT id, date_ban, duration, reason, (SELECT login FROM players WHERE id = banished_by) as "banished_by", was_reprieved, shadow
         FROM banishments WHERE id = ?"#,
     )
     .bind(ban_id)
-    .fetch_one(&mut *mysql_conn)
+    .fetch_one(&mut *txn)
     .await.with_api_err().fit(req_id)?;
 
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::Ban,
+        &audit::player_target(&body.player_login),
+        &(),
+        &ban,
+    )
+    .await
+    .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
+    // The records of the player must be removed from the leaderboards, once the ban is saved
+    if body.shadow {
+        let mut conn = db.acquire().await.with_api_err().fit(req_id)?;
+        moderation::recompute_player(&mut conn, player_id)
+            .await
+            .fit(req_id)?;
+    }
+
     json(BanResponse {
         player_login: body.player_login,
         ban,
@@ -233,7 +317,7 @@ pub async fn ban(
 
 pub async fn is_banned(db: &Database, player_id: u32) -> RecordsResult<Option<Banishment>> {
     let ban = sqlx::query_as(
-        "SELECT id, date_ban, duration, was_reprieved, reason,
+        "SELECT id, date_ban, duration, was_reprieved, shadow, reason,
         (SELECT login FROM players WHERE id = banished_by) as banished_by
         FROM banishments
         WHERE player_id = ? AND (date_ban + INTERVAL duration SECOND > NOW() OR duration IS NULL)",
@@ -258,14 +342,19 @@ struct UnbanResponse {
 }
 
 pub async fn unban(
-    _: MPAuthGuard<{ privilege::ADMIN }>,
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_BANS }>,
     req_id: RequestId,
     db: Res<Database>,
     Json(body): Json<UnbanBody>,
 ) -> RecordsResponse<impl Responder> {
-    let mut mysql_conn = db.0.mysql_pool.acquire().await.with_api_err().fit(req_id)?;
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
 
-    let player_id = records_lib::must::have_player(&mut mysql_conn, &body.player_login)
+    let player_id = records_lib::must::have_player(&mut txn, &body.player_login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
         .await
         .fit(req_id)?
         .id;
@@ -277,7 +366,7 @@ pub async fn unban(
     if let Some(duration) =
         sqlx::query_scalar::<_, i64>("SELECT SYSDATE() - date_ban FROM banishments WHERE id = ?")
             .bind(ban.inner.id)
-            .fetch_optional(&mut *mysql_conn)
+            .fetch_optional(&mut *txn)
             .await
             .with_api_err()
             .fit(req_id)?
@@ -287,11 +376,40 @@ pub async fn unban(
 
     sqlx::query("UPDATE banishments SET duration = SYSDATE() - date_ban WHERE id = ?")
         .bind(ban.inner.id)
-        .execute(&mut *mysql_conn)
+        .execute(&mut *txn)
+        .await
+        .with_api_err()
+        .fit(req_id)?;
+
+    let duration: Option<i64> = sqlx::query_scalar("SELECT duration FROM banishments WHERE id = ?")
+        .bind(ban.inner.id)
+        .fetch_one(&mut *txn)
         .await
         .with_api_err()
         .fit(req_id)?;
 
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::Unban,
+        &audit::player_target(&body.player_login),
+        &ban,
+        &serde_json::json!({ "duration": duration }),
+    )
+    .await
+    .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
+    // The records of the player must be put back in the leaderboards, once the ban is lifted
+    if ban.shadow {
+        let mut conn = db.acquire().await.with_api_err().fit(req_id)?;
+        moderation::recompute_player(&mut conn, player_id)
+            .await
+            .fit(req_id)?;
+    }
+
     json(UnbanResponse {
         player_login: body.player_login,
         ban,
@@ -310,7 +428,7 @@ struct PlayerNoteResponse {
 }
 
 pub async fn player_note(
-    _: MPAuthGuard<{ privilege::ADMIN }>,
+    _: MPAuthGuard<{ permission::MANAGE_BANS }>,
     req_id: RequestId,
     db: Res<Database>,
     Json(body): Json<PlayerNoteBody>,
@@ -327,3 +445,283 @@ pub async fn player_note(
         admins_note,
     })
 }
+
+#[derive(Deserialize)]
+pub struct RecordModerationBody {
+    record_id: u32,
+}
+
+#[derive(Serialize)]
+struct RecordModerationResponse {
+    record_ids: Vec<u32>,
+}
+
+pub async fn invalidate_record(
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MODERATE_RECORDS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+    Json(body): Json<RecordModerationBody>,
+) -> RecordsResponse<impl Responder> {
+    let mut conn = db.acquire().await.with_api_err().fit(req_id)?;
+
+    let admin_id = records_lib::must::have_player(&mut conn.mysql_conn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    let mut txn = conn.mysql_conn.begin().await.with_api_err().fit(req_id)?;
+
+    let record_ids = moderation::invalidate_record(&mut txn, body.record_id)
+        .await
+        .fit(req_id)?;
+
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::InvalidateRecord,
+        &audit::record_target(body.record_id),
+        &(),
+        &serde_json::json!({ "record_ids": record_ids }),
+    )
+    .await
+    .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+    moderation::recompute(&mut conn, &record_ids)
+        .await
+        .fit(req_id)?;
+
+    json(RecordModerationResponse { record_ids })
+}
+
+pub async fn restore_record(
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MODERATE_RECORDS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+    Json(body): Json<RecordModerationBody>,
+) -> RecordsResponse<impl Responder> {
+    let mut conn = db.acquire().await.with_api_err().fit(req_id)?;
+
+    let admin_id = records_lib::must::have_player(&mut conn.mysql_conn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    let mut txn = conn.mysql_conn.begin().await.with_api_err().fit(req_id)?;
+
+    let record_ids = moderation::restore_record(&mut txn, body.record_id)
+        .await
+        .fit(req_id)?;
+
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::RestoreRecord,
+        &audit::record_target(body.record_id),
+        &(),
+        &serde_json::json!({ "record_ids": record_ids }),
+    )
+    .await
+    .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+    moderation::recompute(&mut conn, &record_ids)
+        .await
+        .fit(req_id)?;
+
+    json(RecordModerationResponse { record_ids })
+}
+
+#[derive(Deserialize)]
+pub struct RevokeTokensBody {
+    player_login: String,
+}
+
+#[derive(Serialize)]
+struct RevokeTokensResponse {
+    player_login: String,
+    revoked_mp_tokens: usize,
+}
+
+pub async fn revoke_tokens(
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_BANS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+    Json(body): Json<RevokeTokensBody>,
+) -> RecordsResponse<impl Responder> {
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
+
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    // The tokens are stored in Redis, so the audit entry is written first, and only committed
+    // once they're revoked
+    let tokens = auth::mp_tokens_of(&db, &body.player_login)
+        .await
+        .fit(req_id)?;
+
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::RevokeTokens,
+        &audit::player_target(&body.player_login),
+        &serde_json::json!({ "mp_tokens": tokens }),
+        &(),
+    )
+    .await
+    .fit(req_id)?;
+
+    let revoked = auth::revoke_tokens_of(&db, &body.player_login)
+        .await
+        .fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
+    json(RevokeTokensResponse {
+        player_login: body.player_login,
+        revoked_mp_tokens: revoked.len(),
+    })
+}
+
+#[derive(Serialize)]
+struct ApiKeyResponse {
+    #[serde(flatten)]
+    api_key: records_lib::models::ApiKey,
+    scopes: Vec<&'static str>,
+}
+
+async fn api_key_response(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    api_key: records_lib::models::ApiKey,
+) -> RecordsResult<ApiKeyResponse> {
+    let scopes = api_key::scopes_of(mysql_conn, api_key.id)
+        .await?
+        .into_iter()
+        .map(api_key::Scope::name)
+        .collect();
+    Ok(ApiKeyResponse { api_key, scopes })
+}
+
+pub async fn api_keys(
+    _: MPAuthGuard<{ permission::MANAGE_API_KEYS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+) -> RecordsResponse<impl Responder> {
+    let mut mysql_conn = db.0.mysql_pool.acquire().await.with_api_err().fit(req_id)?;
+
+    let api_keys: Vec<records_lib::models::ApiKey> =
+        sqlx::query_as("SELECT * FROM api_keys ORDER BY id DESC")
+            .fetch_all(&mut *mysql_conn)
+            .await
+            .with_api_err()
+            .fit(req_id)?;
+
+    let mut res = Vec::with_capacity(api_keys.len());
+    for api_key in api_keys {
+        res.push(
+            api_key_response(&mut mysql_conn, api_key)
+                .await
+                .fit(req_id)?,
+        );
+    }
+
+    json(res)
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyBody {
+    name: String,
+    scopes: Vec<api_key::Scope>,
+}
+
+#[derive(Serialize)]
+struct CreateApiKeyResponse {
+    #[serde(flatten)]
+    api_key: ApiKeyResponse,
+    key: String,
+}
+
+pub async fn create_api_key(
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_API_KEYS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+    Json(body): Json<CreateApiKeyBody>,
+) -> RecordsResponse<impl Responder> {
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
+
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    let (api_key, key) = api_key::create_api_key(&mut txn, &body.name, &body.scopes, admin_id)
+        .await
+        .fit(req_id)?;
+
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::CreateApiKey,
+        &audit::api_key_target(api_key.id),
+        &(),
+        &api_key,
+    )
+    .await
+    .fit(req_id)?;
+
+    let api_key = api_key_response(&mut txn, api_key).await.fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
+    json(CreateApiKeyResponse { api_key, key })
+}
+
+#[derive(Deserialize)]
+pub struct RevokeApiKeyBody {
+    api_key_id: u32,
+}
+
+pub async fn revoke_api_key(
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_API_KEYS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+    Json(body): Json<RevokeApiKeyBody>,
+) -> RecordsResponse<impl Responder> {
+    let mut txn = db.0.mysql_pool.begin().await.with_api_err().fit(req_id)?;
+
+    let admin_id = records_lib::must::have_player(&mut txn, &login)
+        .await
+        .fit(req_id)?
+        .id;
+
+    let before = api_key::revoke_api_key(&mut txn, body.api_key_id)
+        .await
+        .fit(req_id)?;
+    let after = api_key::get_api_key(&mut txn, body.api_key_id)
+        .await
+        .fit(req_id)?;
+
+    audit::log(
+        &mut txn,
+        req_id,
+        admin_id,
+        Action::RevokeApiKey,
+        &audit::api_key_target(body.api_key_id),
+        &before,
+        &after,
+    )
+    .await
+    .fit(req_id)?;
+
+    let res = api_key_response(&mut txn, after).await.fit(req_id)?;
+
+    txn.commit().await.with_api_err().fit(req_id)?;
+
+    json(res)
+}
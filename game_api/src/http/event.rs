@@ -7,6 +7,7 @@ use itertools::Itertools;
 use records_lib::{
     error::RecordsError,
     event::{self, EventMap, OptEvent},
+    mappack::AnyMappackId,
     models, opt_ser, Database, MpDefaultI32,
 };
 use serde::Serialize;
@@ -14,11 +15,17 @@ use sqlx::{FromRow, MySqlConnection};
 use tracing_actix_web::RequestId;
 
 use crate::{
-    auth::MPAuthGuard, utils::json, FitRequestId, RecordsErrorKind, RecordsResponse, RecordsResult,
-    RecordsResultExt, Res,
+    api_key,
+    auth::{ApiKeyGuard, MPAuthGuard},
+    utils::json,
+    FitRequestId, RecordsErrorKind, RecordsResponse, RecordsResult, RecordsResultExt, Res,
 };
 
-use super::{overview, pb, player::PlayerInfoNetBody, player_finished as pf};
+use super::{
+    overview, pb,
+    player::{self, PlayerInfoNetBody},
+    player_finished as pf,
+};
 
 pub fn event_scope() -> Scope {
     web::scope("/event")
@@ -159,7 +166,11 @@ struct EventHandleEditionResponse {
     categories: Vec<Category>,
 }
 
-async fn event_list(req_id: RequestId, db: Res<Database>) -> RecordsResponse<impl Responder> {
+async fn event_list(
+    _: ApiKeyGuard<{ api_key::READ_EVENTS }>,
+    req_id: RequestId,
+    db: Res<Database>,
+) -> RecordsResponse<impl Responder> {
     let mysql_conn = &mut db.mysql_pool.acquire().await.with_api_err().fit(req_id)?;
 
     let out = event::event_list(mysql_conn)
@@ -171,6 +182,7 @@ async fn event_list(req_id: RequestId, db: Res<Database>) -> RecordsResponse<imp
 }
 
 async fn event_editions(
+    _: ApiKeyGuard<{ api_key::READ_EVENTS }>,
     db: Res<Database>,
     req_id: RequestId,
     event_handle: Path<String>,
@@ -227,6 +239,7 @@ struct AuthorWithPlayerTime {
 }
 
 async fn edition(
+    _: ApiKeyGuard<{ api_key::READ_EVENTS }>,
     auth: Option<MPAuthGuard>,
     db: Res<Database>,
     req_id: RequestId,
@@ -285,7 +298,7 @@ async fn edition(
                     .fit(req_id)?;
 
                 let personal_best = sqlx::query_scalar(
-                    "select min(time) from records r
+                    "select min(time) from valid_records r
                     inner join players p on p.id = r.record_player_id
                     inner join event_edition_records eer on eer.record_id = r.record_id
                         and eer.event_id = ? and eer.edition_id = ?
@@ -418,6 +431,7 @@ async fn edition(
 }
 
 async fn edition_overview(
+    _: ApiKeyGuard<{ api_key::READ_LEADERBOARDS }>,
     req_id: RequestId,
     db: Res<Database>,
     path: Path<(String, u32)>,
@@ -521,6 +535,7 @@ pub async fn edition_finished_at(
             rest,
             Default::default(),
             Some(res.record_id),
+            res.status,
             at,
         )
         .await
@@ -534,6 +549,16 @@ pub async fn edition_finished_at(
         .await
         .fit(req_id)?;
 
+    if res.res.has_improved && res.status.is_ranked() {
+        let pending =
+            pf::queue_mappack_update(&mut conn, AnyMappackId::Event(&event, &edition), &map).await;
+        pf::update_pending_mappacks(db.0.clone(), pending.into_iter().collect());
+    }
+
+    if let Some(new_wr) = &res.new_wr {
+        player::announce_world_record(&mut conn.redis_conn, new_wr).await;
+    }
+
     json(res.res)
 }
 
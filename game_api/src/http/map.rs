@@ -1,5 +1,5 @@
 use crate::{
-    auth::{self, privilege, ApiAvailable, AuthHeader, MPAuthGuard},
+    auth::{self, permission, ApiAvailable, AuthHeader, MPAuthGuard},
     utils::{any_repeated, json},
     FitRequestId, RecordsErrorKind, RecordsResponse, RecordsResult, RecordsResultExt, Res,
 };
@@ -224,7 +224,7 @@ pub async fn ratings(
         .fit(req_id)?;
 
     let (role, author_login) = if map.player_id == player.id {
-        (privilege::PLAYER, login.clone())
+        (permission::NONE, login.clone())
     } else {
         let login = sqlx::query_scalar("SELECT login FROM players WHERE id = ?")
             .bind(map.player_id)
@@ -232,7 +232,7 @@ pub async fn ratings(
             .await
             .with_api_err()
             .fit(req_id)?;
-        (privilege::ADMIN, login)
+        (permission::MANAGE_MAPS, login)
     };
 
     auth::check_auth_for(&db, &login, &token, role)
@@ -556,7 +556,7 @@ struct ResetRatingsResponse {
 
 pub async fn reset_ratings(
     req_id: RequestId,
-    MPAuthGuard { login }: MPAuthGuard<{ privilege::ADMIN }>,
+    MPAuthGuard { login }: MPAuthGuard<{ permission::MANAGE_MAPS }>,
     db: Res<Database>,
     Json(body): Json<ResetRatingsBody>,
 ) -> RecordsResponse<impl Responder> {
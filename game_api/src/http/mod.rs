@@ -8,6 +8,9 @@ use records_lib::Database;
 use serde::Serialize;
 use tracing_actix_web::RequestId;
 
+use crate::api_key;
+use crate::auth::ApiKeyGuard;
+use crate::rate_limit::RateLimit;
 use crate::utils::{get_api_status, json, ApiStatus};
 use crate::{FitRequestId, RecordsResponse, RecordsResultExt, Res};
 use actix_web::Responder;
@@ -25,6 +28,7 @@ pub mod player;
 
 mod overview;
 mod pb;
+mod plausibility;
 mod player_finished;
 mod staggered;
 
@@ -37,10 +41,10 @@ pub fn api_route() -> Scope {
         .route("/info", web::get().to(info))
         .route("/overview", web::get().to(overview))
         .service(staggered_scope())
-        .service(player_scope())
-        .service(map_scope())
+        .service(player_scope().wrap(RateLimit::new("player", crate::env().rate_limit_player)))
+        .service(map_scope().wrap(RateLimit::new("map", crate::env().rate_limit_map)))
         .service(admin_scope())
-        .service(event_scope())
+        .service(event_scope().wrap(RateLimit::new("event", crate::env().rate_limit_event)))
 }
 
 #[derive(Serialize, sqlx::FromRow)]
@@ -79,6 +83,7 @@ async fn info(req_id: RequestId, db: Res<Database>) -> RecordsResponse<impl Resp
 }
 
 async fn overview(
+    _: ApiKeyGuard<{ api_key::READ_LEADERBOARDS }>,
     req_id: RequestId,
     db: Res<Database>,
     Query(query): overview::OverviewReq,
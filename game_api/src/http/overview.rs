@@ -1,10 +1,9 @@
 use actix_web::{web::Query, Responder};
-use deadpool_redis::redis::AsyncCommands;
 use futures::StreamExt;
 use records_lib::{
     event::OptEvent,
+    leaderboard::{leaderboard, Leaderboard as _},
     models, must,
-    redis_key::map_key,
     update_ranks::{get_rank, update_leaderboard},
     DatabaseConnection, MySqlPool,
 };
@@ -65,17 +64,11 @@ async fn get_range(
     (start, end): (u32, u32),
     event: OptEvent<'_, '_>,
 ) -> RecordsResult<Vec<RankedRecord>> {
-    let key = map_key(*map_id, event);
-
     let (join_event, and_event) = event.get_join();
 
-    // transforms exclusive to inclusive range
-    let end = end - 1;
-    let ids: Vec<i32> = conn
-        .redis_conn
-        .zrange(key, start as isize, end as isize)
-        .await
-        .with_api_err()?;
+    let ids = leaderboard(conn)
+        .range(*map_id, event, start, end, false)
+        .await?;
 
     if ids.is_empty() {
         // Avoids the query building to have a `AND record_player_id IN ()` fragment
@@ -94,7 +87,7 @@ async fn get_range(
             p.name AS nickname,
             min(time) as time,
             m.*
-        FROM records r
+        FROM valid_records r
         {join_event}
         INNER JOIN players p ON r.record_player_id = p.id
         INNER JOIN maps m ON m.id = r.map_id
@@ -136,6 +129,50 @@ async fn get_range(
     Ok(out)
 }
 
+/// Returns the record of the shadow-banned player on the map, with the rank it would have.
+///
+/// The shadow-banned players aren't in the leaderboards, but they still see their own record.
+async fn get_shadow_banned_record(
+    db: &sqlx::MySqlPool,
+    conn: &mut DatabaseConnection,
+    map_id: u32,
+    player_id: u32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<Option<RankedRecord>> {
+    let (join_event, and_event) = event.get_join();
+
+    let query = format!(
+        "SELECT CAST(0 AS UNSIGNED) AS rank,
+            p.login AS login,
+            p.name AS nickname,
+            min(time) as time
+        FROM valid_records r
+        {join_event}
+        INNER JOIN players p ON r.record_player_id = p.id
+        WHERE map_id = ? AND record_player_id = ?
+            {and_event}
+        GROUP BY record_player_id",
+    );
+
+    let mut query = sqlx::query_as(&query).bind(map_id).bind(player_id);
+    if let Some((event, edition)) = event.0 {
+        query = query.bind(event.id).bind(edition.id);
+    }
+
+    let Some(mut record): Option<RankedRecord> = query.fetch_optional(db).await.with_api_err()?
+    else {
+        return Ok(None);
+    };
+
+    let better_count = leaderboard(conn)
+        .better_count(map_id, event, record.time)
+        .await
+        .with_api_err()?;
+    record.rank = better_count as u32 + 1;
+
+    Ok(Some(record))
+}
+
 pub async fn overview(
     req_id: RequestId,
     db: &MySqlPool,
@@ -156,8 +193,7 @@ pub async fn overview(
         .id;
     let map_id = linked_map.unwrap_or(*id);
 
-    // Update redis if needed
-    let key = map_key(map_id, event);
+    // Update the leaderboard if needed
     let count = update_leaderboard(conn, map.id, event).await.fit(req_id)? as u32;
 
     let mut ranked_records: Vec<RankedRecord> = vec![];
@@ -166,13 +202,10 @@ pub async fn overview(
     const TOTAL_ROWS: u32 = 15;
     const NO_RECORD_ROWS: u32 = TOTAL_ROWS - 1;
 
-    let player_rank: Option<i64> = conn
-        .redis_conn
-        .zrank(&key, player_id)
+    let player_rank = leaderboard(conn)
+        .position(map_id, event, player_id)
         .await
-        .with_api_err()
         .fit(req_id)?;
-    let player_rank = player_rank.map(|r| r as u64 as u32);
 
     if let Some(player_rank) = player_rank {
         // The player has a record and is in top ROWS, display ROWS records
@@ -230,6 +263,21 @@ pub async fn overview(
         }
     }
 
+    if player_rank.is_none()
+        && records_lib::player::is_shadow_banned(&mut *conn.mysql_conn, player_id)
+            .await
+            .with_api_err()
+            .fit(req_id)?
+    {
+        if let Some(record) = get_shadow_banned_record(db, conn, map.id, player_id, event)
+            .await
+            .fit(req_id)?
+        {
+            ranked_records.push(record);
+            ranked_records.sort_by_key(|record| record.rank);
+        }
+    }
+
     #[derive(Serialize)]
     struct Response {
         response: Vec<RankedRecord>,
@@ -40,7 +40,8 @@ pub async fn pb(
     PbBody { map_uid }: PbBody,
     event: OptEvent<'_, '_>,
 ) -> RecordsResponse<impl Responder> {
-    let (view_name, and_event) = event.get_view();
+    // The shadow-banned players still see their own records
+    let (view_name, and_event) = event.get_player_view();
 
     let query = format!(
         "select r.respawn_count as rs_count, ct.cp_num as cp_num, ct.time as time
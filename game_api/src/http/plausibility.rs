@@ -0,0 +1,215 @@
+//! The plausibility checks of the runs submitted by the players.
+//!
+//! The checks are made of rules, configured with the environment of the API. A run breaking a rule
+//! isn't rejected: it is saved, but flagged as suspicious, and it stays out of the leaderboards
+//! until a moderator approves it.
+//!
+//! Only the rule of the positive splits is enabled by default. The other ones are disabled
+//! until their threshold is configured, e.g. with `RECORDS_WR_FLOOR`.
+
+use records_lib::event::OptEvent;
+use sqlx::MySqlConnection;
+
+use crate::{RecordsResult, RecordsResultExt};
+
+use super::player_finished::InsertRecordParams;
+
+/// A rule of the plausibility checks.
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    /// Every checkpoint split must be strictly positive.
+    PositiveSplits,
+    /// Every checkpoint split must last at least this amount of milliseconds.
+    MinSplit(i32),
+    /// The time must not be below this fraction of the author time of the event map.
+    AuthorTimeFloor(f64),
+    /// The time must not be below this fraction of the current world record.
+    WrFloor(f64),
+}
+
+/// The information about a run needed by the rules.
+struct Run<'a> {
+    cps: &'a [i32],
+    time: i32,
+    author_time: Option<i32>,
+    wr_time: Option<i32>,
+}
+
+impl Rule {
+    /// Returns the reason why the run breaks the rule, if it does.
+    fn check(self, run: &Run<'_>) -> Option<String> {
+        match self {
+            Self::PositiveSplits => run
+                .cps
+                .iter()
+                .enumerate()
+                .find(|(_, split)| **split <= 0)
+                .map(|(i, split)| format!("checkpoint split {i} isn't positive ({split} ms)")),
+            Self::MinSplit(min) => run
+                .cps
+                .iter()
+                .enumerate()
+                .find(|(_, split)| **split < min)
+                .map(|(i, split)| {
+                    format!("checkpoint split {i} is shorter than {min} ms ({split} ms)")
+                }),
+            Self::AuthorTimeFloor(ratio) => {
+                let author_time = run.author_time?;
+                ((run.time as f64) < author_time as f64 * ratio).then(|| {
+                    format!(
+                        "time {} ms is below {ratio} of the author time ({author_time} ms)",
+                        run.time
+                    )
+                })
+            }
+            Self::WrFloor(ratio) => {
+                let wr_time = run.wr_time?;
+                ((run.time as f64) < wr_time as f64 * ratio).then(|| {
+                    format!(
+                        "time {} ms is below {ratio} of the world record ({wr_time} ms)",
+                        run.time
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// Returns the rules enabled in the environment.
+fn rules() -> Vec<Rule> {
+    let env = crate::env();
+
+    let mut rules = vec![Rule::PositiveSplits];
+    if env.min_split > 0 {
+        rules.push(Rule::MinSplit(env.min_split));
+    }
+    if env.author_time_floor > 0. {
+        rules.push(Rule::AuthorTimeFloor(env.author_time_floor));
+    }
+    if env.wr_floor > 0. {
+        rules.push(Rule::WrFloor(env.wr_floor));
+    }
+
+    rules
+}
+
+/// Checks the plausibility of the run made by a player on the provided map.
+///
+/// It returns the reason why the run is suspicious, if it is.
+pub(super) async fn check_run(
+    mysql_conn: &mut MySqlConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+    params: &InsertRecordParams,
+) -> RecordsResult<Option<String>> {
+    let rules = rules();
+
+    // The author time is only known for the event maps
+    let author_time = match event.0 {
+        Some((event, edition)) if rules.iter().any(|r| matches!(r, Rule::AuthorTimeFloor(_))) => {
+            sqlx::query_scalar(
+                "SELECT author_time FROM event_edition_maps
+                WHERE event_id = ? AND edition_id = ? AND map_id = ?",
+            )
+            .bind(event.id)
+            .bind(edition.id)
+            .bind(map_id)
+            .fetch_optional(&mut *mysql_conn)
+            .await
+            .with_api_err()?
+            .flatten()
+        }
+        _ => None,
+    };
+
+    let wr_time = if rules.iter().any(|r| matches!(r, Rule::WrFloor(_))) {
+        let (view_name, and_event) = event.get_view();
+        let query = format!("SELECT MIN(time) FROM {view_name} r WHERE map_id = ? {and_event}");
+        let mut query = sqlx::query_scalar(&query).bind(map_id);
+        if let Some((event, edition)) = event.0 {
+            query = query.bind(event.id).bind(edition.id);
+        }
+        query.fetch_one(&mut *mysql_conn).await.with_api_err()?
+    } else {
+        None
+    };
+
+    let run = Run {
+        cps: &params.cps,
+        time: params.time,
+        author_time,
+        wr_time,
+    };
+
+    Ok(rules.into_iter().find_map(|rule| rule.check(&run)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rule, Run};
+
+    fn run(cps: &[i32]) -> Run<'_> {
+        Run {
+            cps,
+            time: cps.iter().sum(),
+            author_time: None,
+            wr_time: None,
+        }
+    }
+
+    fn timed(time: i32, author_time: Option<i32>, wr_time: Option<i32>) -> Run<'static> {
+        Run {
+            cps: &[],
+            time,
+            author_time,
+            wr_time,
+        }
+    }
+
+    #[test]
+    fn positive_splits() {
+        let rule = Rule::PositiveSplits;
+        assert!(rule.check(&run(&[1, 1000, 2000])).is_none());
+        assert!(rule.check(&run(&[])).is_none());
+
+        let reason = rule.check(&run(&[1000, 0, 2000])).unwrap();
+        assert!(reason.contains("checkpoint split 1"), "{reason}");
+        assert!(rule.check(&run(&[1000, 2000, -5])).is_some());
+    }
+
+    #[test]
+    fn min_split() {
+        let rule = Rule::MinSplit(500);
+        // The minimum split itself is allowed
+        assert!(rule.check(&run(&[500, 1000])).is_none());
+
+        let reason = rule.check(&run(&[1000, 499])).unwrap();
+        assert!(reason.contains("checkpoint split 1"), "{reason}");
+        assert!(rule.check(&run(&[0, 1000])).is_some());
+        assert!(rule.check(&run(&[1000, -1])).is_some());
+    }
+
+    #[test]
+    fn author_time_floor() {
+        let rule = Rule::AuthorTimeFloor(0.75);
+        // The floor is 7500 ms, which is allowed
+        assert!(rule.check(&timed(7500, Some(10_000), None)).is_none());
+        assert!(rule.check(&timed(12_000, Some(10_000), None)).is_none());
+        assert!(rule.check(&timed(7499, Some(10_000), None)).is_some());
+
+        // The rule doesn't apply without author time
+        assert!(rule.check(&timed(1, None, Some(10_000))).is_none());
+    }
+
+    #[test]
+    fn wr_floor() {
+        let rule = Rule::WrFloor(0.5);
+        // The floor is 5000 ms, which is allowed
+        assert!(rule.check(&timed(5000, None, Some(10_000))).is_none());
+        assert!(rule.check(&timed(9000, None, Some(10_000))).is_none());
+        assert!(rule.check(&timed(4999, None, Some(10_000))).is_some());
+
+        // The rule doesn't apply without world record
+        assert!(rule.check(&timed(1, Some(10_000), None)).is_none());
+    }
+}
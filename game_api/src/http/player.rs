@@ -1,28 +1,29 @@
 use actix_session::Session;
 use actix_web::{
+    http::header,
     web::{self, Data, Json, Query},
-    HttpResponse, Responder, Scope,
+    HttpRequest, HttpResponse, Responder, Scope,
 };
 use futures::TryStreamExt;
 use records_lib::{
     event::{self, OptEvent},
     models::Banishment,
-    must, Database,
+    moderation, must, Database, DatabaseConnection, RedisConnection,
 };
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, MySqlConnection};
-use tokio::time::timeout;
 use tracing::Level;
 use tracing_actix_web::RequestId;
 
 use crate::{
+    api_key,
     auth::{
-        self, privilege, ApiAvailable, AuthHeader, AuthState, MPAuthGuard, Message, WebToken,
-        TIMEOUT, WEB_TOKEN_SESS_KEY,
+        self, permission, ApiAvailable, ApiKeyGuard, AuthHeader, AuthState, MPAuthGuard, Message,
+        WebToken, WEB_TOKEN_SESS_KEY,
     },
     utils::json,
-    AccessTokenErr, FitRequestId, RecordsErrorKind, RecordsResponse, RecordsResult,
+    webhook, AccessTokenErr, FitRequestId, RecordsErrorKind, RecordsResponse, RecordsResult,
     RecordsResultExt, Res,
 };
 
@@ -34,6 +35,10 @@ pub fn player_scope() -> Scope {
         .route("/finished", web::post().to(finished))
         .route("/get_token", web::post().to(get_token))
         .route("/give_token", web::post().to(post_give_token))
+        .route("/rotate_token", web::post().to(rotate_token))
+        .route("/tokens", web::get().to(tokens))
+        .route("/revoke_token", web::post().to(revoke_token))
+        .route("/logout", web::post().to(logout))
         .route("/pb", web::get().to(pb))
         .route("/times", web::post().to(times))
         .route("/info", web::get().to(info))
@@ -84,7 +89,7 @@ pub async fn update(
     AuthHeader { login, token }: AuthHeader,
     Json(body): Json<PlayerInfoNetBody>,
 ) -> RecordsResponse<impl Responder> {
-    match auth::check_auth_for(&db, &login, &token, privilege::PLAYER).await {
+    match auth::check_auth_for(&db, &login, &token, permission::NONE).await {
         Ok(id) => update_player(&db, id, body).await.fit(req_id)?,
         // At this point, if Redis has registered a token with the login, it means that
         // the player is not yet added to the Obstacle database but effectively
@@ -155,7 +160,7 @@ async fn test_access_token(
     redirect_uri: &str,
 ) -> RecordsResult<bool> {
     let res = client
-        .post("https://prod.live.maniaplanet.com/login/oauth2/access_token")
+        .post(&crate::env().mp_oauth_token_url)
         .form(&MPAccessTokenBody {
             grant_type: "authorization_code",
             client_id: &crate::env().mp_client_id,
@@ -180,7 +185,7 @@ async fn test_access_token(
 
 async fn check_mp_token(client: &Client, login: &str, token: String) -> RecordsResult<bool> {
     let res = client
-        .get("https://prod.live.maniaplanet.com/webservices/me")
+        .get(&crate::env().mp_webservices_me_url)
         .header("Accept", "application/json")
         .bearer_auth(token)
         .send()
@@ -202,7 +207,7 @@ pub async fn finished_at(
     at: chrono::NaiveDateTime,
 ) -> RecordsResponse<impl Responder> {
     let mut conn = db.acquire().await.with_api_err().fit(req_id)?;
-    let res = pf::finished(
+    let out = pf::finished(
         login,
         &mut conn,
         body.into_params(None),
@@ -210,9 +215,15 @@ pub async fn finished_at(
         at,
     )
     .await
-    .fit(req_id)?
-    .res;
-    json(res)
+    .fit(req_id)?;
+
+    if let Some(new_wr) = &out.new_wr {
+        announce_world_record(&mut conn.redis_conn, new_wr).await;
+    }
+
+    pf::update_pending_mappacks(db.0.clone(), out.pending_mappacks);
+
+    json(out.res)
 }
 
 #[inline(always)]
@@ -240,6 +251,7 @@ struct GetTokenResponse {
 
 pub async fn get_token(
     _: ApiAvailable,
+    req: HttpRequest,
     req_id: RequestId,
     db: Res<Database>,
     Res(client): Res<Client>,
@@ -247,12 +259,12 @@ pub async fn get_token(
     Json(body): Json<GetTokenBody>,
 ) -> RecordsResponse<impl Responder> {
     // retrieve access_token from browser redirection
-    let (tx, rx) = state
+    let mut browser = state
         .connect_with_browser(body.state.clone())
         .await
         .fit(req_id)?;
-    let code = match timeout(TIMEOUT, rx).await {
-        Ok(Ok(Message::MPCode(access_token))) => access_token,
+    let code = match browser.recv().await.fit(req_id)? {
+        Some(Message::MPCode(access_token)) => access_token,
         _ => {
             tracing::event!(
                 Level::WARN,
@@ -264,18 +276,18 @@ pub async fn get_token(
         }
     };
 
-    let err_msg = "/get_token rx should not be dropped at this point";
-
     // check access_token and generate new token for player ...
     match test_access_token(&client, &body.login, &code, &body.redirect_uri).await {
         Ok(true) => (),
         Ok(false) => {
-            tx.send(Message::InvalidMPCode).expect(err_msg);
+            browser.send(Message::InvalidMPCode).await.fit(req_id)?;
             return Err(RecordsErrorKind::InvalidMPCode).fit(req_id);
         }
         Err(RecordsErrorKind::AccessTokenErr(err)) => {
-            tx.send(Message::AccessTokenErr(err.clone()))
-                .expect(err_msg);
+            browser
+                .send(Message::AccessTokenErr(err.clone()))
+                .await
+                .fit(req_id)?;
             return Err(RecordsErrorKind::AccessTokenErr(err)).fit(req_id);
         }
         err => {
@@ -283,12 +295,21 @@ pub async fn get_token(
         }
     }
 
-    let (mp_token, web_token) = auth::gen_token_for(&db, &body.login).await.fit(req_id)?;
-    tx.send(Message::Ok(WebToken {
-        login: body.login,
-        token: web_token,
-    }))
-    .expect(err_msg);
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|h| h.to_str().map(str::to_owned).ok());
+
+    let (mp_token, web_token) = auth::gen_token_for(&db, &body.login, user_agent)
+        .await
+        .fit(req_id)?;
+    browser
+        .send(Message::Ok(WebToken {
+            login: body.login,
+            token: web_token,
+        }))
+        .await
+        .fit(req_id)?;
 
     json(GetTokenResponse { token: mp_token })
 }
@@ -315,6 +336,63 @@ pub async fn post_give_token(
     Ok(HttpResponse::Ok().finish())
 }
 
+async fn rotate_token(
+    req_id: RequestId,
+    AuthHeader { login, token }: AuthHeader,
+    db: Res<Database>,
+) -> RecordsResponse<impl Responder> {
+    auth::check_auth_for(&db, &login, &token, permission::NONE)
+        .await
+        .fit(req_id)?;
+    let token = auth::rotate_mp_token(&db, &login, &token)
+        .await
+        .fit(req_id)?;
+    json(GetTokenResponse { token })
+}
+
+async fn tokens(
+    req_id: RequestId,
+    MPAuthGuard { login }: MPAuthGuard,
+    db: Res<Database>,
+) -> RecordsResponse<impl Responder> {
+    let tokens = auth::mp_tokens_of(&db, &login).await.fit(req_id)?;
+    json(tokens)
+}
+
+#[derive(Deserialize)]
+struct RevokeTokenBody {
+    token_id: String,
+}
+
+#[derive(Serialize)]
+struct RevokeTokenResponse {
+    revoked: bool,
+}
+
+async fn revoke_token(
+    req_id: RequestId,
+    MPAuthGuard { login }: MPAuthGuard,
+    db: Res<Database>,
+    Json(body): Json<RevokeTokenBody>,
+) -> RecordsResponse<impl Responder> {
+    let revoked = auth::revoke_mp_token(&db, &login, &body.token_id)
+        .await
+        .fit(req_id)?;
+    json(RevokeTokenResponse { revoked })
+}
+
+async fn logout(
+    req_id: RequestId,
+    session: Session,
+    db: Res<Database>,
+) -> RecordsResponse<impl Responder> {
+    if let Ok(Some(web_token)) = session.get::<WebToken>(WEB_TOKEN_SESS_KEY) {
+        auth::logout(&db, &web_token).await.fit(req_id)?;
+    }
+    session.purge();
+    Ok(HttpResponse::Ok().finish())
+}
+
 async fn pb(
     _: ApiAvailable,
     req_id: RequestId,
@@ -379,7 +457,7 @@ async fn times(
     let query = format!(
         "SELECT m.game_id AS map_uid, MIN(r.time) AS time
         FROM maps m
-        INNER JOIN records r ON r.map_id = m.id
+        INNER JOIN valid_records r ON r.map_id = m.id
         WHERE r.record_player_id = ? AND m.game_id IN ({})
         GROUP BY m.id",
         body.maps_uids
@@ -419,6 +497,7 @@ struct InfoResponse {
 }
 
 pub async fn info(
+    _: ApiKeyGuard<{ api_key::READ_PLAYERS }>,
     req_id: RequestId,
     db: Res<Database>,
     Query(body): Query<InfoBody>,
@@ -477,10 +556,84 @@ struct WebhookBody {
     embeds: Vec<WebhookBodyEmbed>,
 }
 
+/// Formats the time in milliseconds like `1:02.345`.
+fn format_time(time: i32) -> String {
+    let (minutes, ms) = (time / 60_000, time % 60_000);
+    if minutes > 0 {
+        format!("{minutes}:{:02}.{:03}", ms / 1000, ms % 1000)
+    } else {
+        format!("{}.{:03}", ms / 1000, ms % 1000)
+    }
+}
+
+/// Enqueues the announcement of the new world record on the Discord webhook, if any.
+///
+/// The error is only logged, because the record is already saved at this point.
+pub(super) async fn announce_world_record(
+    redis_conn: &mut RedisConnection,
+    wr: &pf::NewWorldRecord,
+) {
+    let mut fields = vec![
+        WebhookBodyEmbedField {
+            name: "Player".to_owned(),
+            value: format!("`{}`", wr.player.login),
+            inline: Some(true),
+        },
+        WebhookBodyEmbedField {
+            name: "Time".to_owned(),
+            value: format!("`{}`", format_time(wr.time)),
+            inline: Some(true),
+        },
+    ];
+
+    if let Some((previous, previous_player)) = &wr.previous {
+        fields.push(WebhookBodyEmbedField {
+            name: "Previous world record".to_owned(),
+            value: format!(
+                "`{}` by `{}` (-{})",
+                format_time(previous.time),
+                previous_player.login,
+                format_time(previous.time - wr.time),
+            ),
+            inline: None,
+        });
+    }
+
+    if let Some((event_handle, edition_id)) = &wr.event {
+        fields.push(WebhookBodyEmbedField {
+            name: "Event".to_owned(),
+            value: format!("`{event_handle}` edition `{edition_id}`"),
+            inline: None,
+        });
+    }
+
+    let body = WebhookBody {
+        content: format!("🏆 New world record by `{}`!", wr.player.login),
+        embeds: vec![WebhookBodyEmbed {
+            title: wr.map.name.clone(),
+            description: None,
+            color: 16766720,
+            url: Some(format!(
+                "https://obstacle.titlepack.io/map/{}",
+                wr.map.game_id
+            )),
+            fields: Some(fields),
+        }],
+    };
+
+    if let Err(e) = webhook::enqueue(redis_conn, &crate::env().wh_wr_url, &body).await {
+        tracing::event!(
+            Level::WARN,
+            "Couldn't announce the world record on `{}`: {e}",
+            wr.map.game_id
+        );
+    }
+}
+
 async fn report_error(
     req_id: RequestId,
     MPAuthGuard { login }: MPAuthGuard,
-    Res(client): Res<Client>,
+    db: Res<Database>,
     Json(body): Json<ReportErrorBody>,
 ) -> RecordsResponse<impl Responder> {
     let mut fields = vec![
@@ -529,9 +682,11 @@ async fn report_error(
         )
     };
 
-    client
-        .post(&crate::env().wh_report_url)
-        .json(&WebhookBody {
+    let mut redis_conn = db.0.redis_pool.get().await.with_api_err().fit(req_id)?;
+    webhook::enqueue(
+        &mut redis_conn,
+        &crate::env().wh_report_url,
+        &WebhookBody {
             content,
             embeds: vec![
                 WebhookBodyEmbed {
@@ -549,11 +704,10 @@ async fn report_error(
                     url: None,
                 },
             ],
-        })
-        .send()
-        .await
-        .with_api_err()
-        .fit(req_id)?;
+        },
+    )
+    .await
+    .fit(req_id)?;
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -572,14 +726,97 @@ struct ACBody {
     ac_version: String,
 }
 
+/// Parses a time sent by the anticheat, either in milliseconds or formatted like `1:02.345`.
+fn parse_time(time: &str) -> Option<i32> {
+    if let Ok(time) = time.trim().parse() {
+        return Some(time);
+    }
+
+    let (minutes, seconds) = time.trim().split_once(':').unwrap_or(("0", time.trim()));
+    let (seconds, fraction) = seconds.split_once('.')?;
+
+    // The fraction of a second is right-padded, so `1:02.3` is 1:02.300
+    if fraction.is_empty() || fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let ms = format!("{fraction:0<3}");
+
+    let (minutes, seconds, ms): (i32, i32, i32) = (
+        minutes.parse().ok()?,
+        seconds.parse().ok()?,
+        ms.parse().ok()?,
+    );
+    minutes
+        .checked_mul(60_000)?
+        .checked_add(seconds.checked_mul(1000)?)?
+        .checked_add(ms)
+}
+
+/// Saves the report, and links it to the record of the run if it is already saved.
+///
+/// The player of the report is only known if the request was authenticated.
+async fn insert_ac_report(
+    db: &mut DatabaseConnection,
+    login: Option<&str>,
+    body: &ACBody,
+) -> RecordsResult<u32> {
+    let player_id = match login {
+        Some(login) => Some(
+            records_lib::must::have_player(&mut db.mysql_conn, login)
+                .await?
+                .id,
+        ),
+        None => None,
+    };
+    let map = records_lib::must::have_map(&mut db.mysql_conn, &body.map_uid).await?;
+
+    let id = sqlx::query_scalar(
+        "INSERT INTO ac_reports
+        (player_id, map_id, report_date, run_time, time, cp_times, player_field, server_text,
+            irl_time_passed, discrepancy, discrepancy_ratio, ac_version)
+        VALUES (?, ?, SYSDATE(), ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id",
+    )
+    .bind(player_id)
+    .bind(map.id)
+    .bind(&body.run_time)
+    .bind(parse_time(&body.run_time))
+    .bind(&body.cp_times)
+    .bind(&body.player_field)
+    .bind(&body.server_text)
+    .bind(&body.irl_time_passed)
+    .bind(&body.discrepancy)
+    .bind(&body.discrepancy_ratio)
+    .bind(&body.ac_version)
+    .fetch_one(&mut *db.mysql_conn)
+    .await
+    .with_api_err()?;
+
+    // If the record was saved first, it is linked now, otherwise it will be when it is saved
+    if let Some(player_id) = player_id {
+        moderation::link_ac_reports(&mut db.mysql_conn, player_id, map.id).await?;
+    }
+
+    Ok(id)
+}
+
 async fn ac(
     req_id: RequestId,
-    Res(client): Res<Client>,
+    auth: Option<MPAuthGuard>,
+    db: Res<Database>,
     Json(body): Json<ACBody>,
 ) -> RecordsResponse<impl Responder> {
-    client
-        .post(&crate::env().wh_ac_url)
-        .json(&WebhookBody {
+    let mut conn = db.acquire().await.with_api_err().fit(req_id)?;
+
+    let login = auth.map(|MPAuthGuard { login }| login);
+    let report_id = insert_ac_report(&mut conn, login.as_deref(), &body)
+        .await
+        .fit(req_id)?;
+
+    webhook::enqueue(
+        &mut conn.redis_conn,
+        &crate::env().wh_ac_url,
+        &WebhookBody {
             content: format!("Map has been finished in {}", body.run_time),
             embeds: vec![WebhookBodyEmbed {
                 title: body.map_name,
@@ -620,13 +857,45 @@ async fn ac(
                         value: body.ac_version,
                         inline: None,
                     },
+                    WebhookBodyEmbedField {
+                        name: "Report ID".to_owned(),
+                        value: format!("`{report_id}`"),
+                        inline: None,
+                    },
                 ]),
             }],
-        })
-        .send()
-        .await
-        .with_api_err()
-        .fit(req_id)?;
+        },
+    )
+    .await
+    .fit(req_id)?;
 
     Ok(HttpResponse::Ok().finish())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_time;
+
+    #[test]
+    fn parse_times() {
+        assert_eq!(parse_time("62345"), Some(62345));
+        assert_eq!(parse_time(" 1:02.345 "), Some(62345));
+        assert_eq!(parse_time("02.345"), Some(2345));
+        assert_eq!(parse_time("1:02.3"), Some(62300));
+        assert_eq!(parse_time("1:02.03"), Some(62030));
+        assert_eq!(parse_time("1:02.003"), Some(62003));
+    }
+
+    #[test]
+    fn invalid_times() {
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("1:02"), None);
+        assert_eq!(parse_time("1:02."), None);
+        assert_eq!(parse_time("1:02.3456"), None);
+        assert_eq!(parse_time("1:02.+3"), None);
+        assert_eq!(parse_time("abc"), None);
+        // Overflows
+        assert_eq!(parse_time("40000:00.000"), None);
+        assert_eq!(parse_time("0:3000000.000"), None);
+    }
+}
@@ -1,19 +1,23 @@
 use actix_web::web::Json;
-use deadpool_redis::redis::AsyncCommands;
 use futures::TryStreamExt;
 use records_lib::{
     event::OptEvent,
-    models, opt_ser,
-    redis_key::map_key,
+    leaderboard::{leaderboard, Leaderboard as _},
+    mappack::{mappacks_of_map, queue_map_update, update_pending_maps, AnyMappackId},
+    models, moderation, must, opt_ser,
     update_ranks::{get_rank, get_rank_opt, update_leaderboard},
-    DatabaseConnection, MpDefaultI32,
+    Database, DatabaseConnection, MpDefaultI32,
 };
 use serde::{Deserialize, Serialize};
 use sqlx::Connection;
+use tracing::Level;
 
-use crate::{RecordsErrorKind, RecordsResult, RecordsResultExt};
+use crate::{
+    record_events::{self, RecordEvent},
+    RecordsErrorKind, RecordsResult, RecordsResultExt,
+};
 
-use super::{event, map::MapParam};
+use super::{event, map::MapParam, plausibility};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct InsertRecordParams {
@@ -49,7 +53,10 @@ pub type PlayerFinishedBody = Json<HasFinishedBody>;
 
 #[derive(Serialize)]
 pub struct HasFinishedResponse {
-    has_improved: bool,
+    pub has_improved: bool,
+    /// Whether the record is suspicious, and waits for the approval of a moderator
+    /// before counting in the leaderboards.
+    pub pending: bool,
     login: String,
     old: i32,
     new: i32,
@@ -58,17 +65,34 @@ pub struct HasFinishedResponse {
     old_rank: Option<MpDefaultI32>,
 }
 
+/// The state of a new record, which tells whether it counts in the leaderboards.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordStatus {
+    /// The record is suspicious, and waits for the approval of a moderator.
+    pub pending: bool,
+    /// The player is shadow-banned, so the record is only visible to them.
+    pub shadow_banned: bool,
+}
+
+impl RecordStatus {
+    /// Returns true if the record counts in the leaderboards.
+    pub fn is_ranked(self) -> bool {
+        !self.pending && !self.shadow_banned
+    }
+}
+
 async fn send_query(
     db: &mut sqlx::MySqlConnection,
     map_id: u32,
     player_id: u32,
     body: InsertRecordParams,
     event_record_id: Option<u32>,
+    pending: bool,
     at: chrono::NaiveDateTime,
 ) -> records_lib::error::RecordsResult<u32> {
     let record_id: u32 = sqlx::query_scalar(
-        "INSERT INTO records (record_player_id, map_id, time, respawn_count, record_date, flags, event_record_id)
-                    VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING record_id",
+        "INSERT INTO records (record_player_id, map_id, time, respawn_count, record_date, flags, event_record_id, pending)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING record_id",
     )
     .bind(player_id)
     .bind(map_id)
@@ -77,6 +101,7 @@ async fn send_query(
     .bind(at)
     .bind(body.flags)
     .bind(event_record_id)
+    .bind(pending)
     .fetch_one(&mut *db)
     .await?;
 
@@ -101,6 +126,7 @@ async fn send_query(
     Ok(record_id)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn insert_record(
     db: &mut DatabaseConnection,
     map_id: u32,
@@ -108,13 +134,10 @@ pub(super) async fn insert_record(
     body: InsertRecordParams,
     event: OptEvent<'_, '_>,
     event_record_id: Option<u32>,
+    status: RecordStatus,
     at: chrono::NaiveDateTime,
 ) -> RecordsResult<u32> {
-    let key = map_key(map_id, event);
-    let added: Option<i64> = db.redis_conn.zadd(key, player_id, body.time).await.ok();
-    if added.is_none() {
-        let _count = update_leaderboard(db, map_id, event).await?;
-    }
+    let time = body.time;
 
     let record_id = db
         .mysql_conn
@@ -125,18 +148,283 @@ pub(super) async fn insert_record(
                 player_id,
                 body,
                 event_record_id,
+                status.pending,
                 at,
             ))
         })
         .await?;
 
+    // The leaderboard is updated once the record is committed, so a concurrent reconciliation
+    // of the leaderboard either sees the record, or is aborted by the update.
+    // A pending record doesn't count in the leaderboard until it is approved,
+    // and the records of a shadow-banned player never do.
+    if status.is_ranked() {
+        let added = leaderboard(db).insert(map_id, event, player_id, time).await;
+        if added.is_err() {
+            let _count = update_leaderboard(db, map_id, event).await?;
+        }
+    }
+
     Ok(record_id)
 }
 
+/// Flags the record as suspicious, for the moderators to review it.
+async fn flag_record(
+    db: &mut sqlx::MySqlConnection,
+    record_id: u32,
+    reason: &str,
+) -> RecordsResult<()> {
+    sqlx::query(
+        "INSERT INTO suspicious_records (record_id, reason, flagged_at) VALUES (?, ?, SYSDATE())",
+    )
+    .bind(record_id)
+    .bind(reason)
+    .execute(db)
+    .await
+    .with_api_err()?;
+
+    tracing::event!(
+        Level::INFO,
+        "Flagged the record {record_id} as suspicious: {reason}"
+    );
+
+    Ok(())
+}
+
+/// A mappack whose scores must be updated after a new personal best.
+///
+/// It is owned, so the update can be done in the background, after the response is sent.
+#[derive(Debug, Clone)]
+pub enum PendingMappack {
+    /// A regular MX mappack.
+    Id(String),
+    /// The mappack of an event edition.
+    Event { event_id: u32, edition_id: u32 },
+}
+
+/// Queues the update of the scores of the provided mappack after a new personal best on the map.
+///
+/// The error is only logged, because the record is already saved at this point, and the mappacks
+/// are fully updated periodically anyway.
+pub(super) async fn queue_mappack_update(
+    db: &mut DatabaseConnection,
+    mappack: AnyMappackId<'_>,
+    map: &models::Map,
+) -> Option<PendingMappack> {
+    match queue_map_update(&mut db.redis_conn, mappack, &map.game_id).await {
+        Ok(()) => Some(match mappack {
+            AnyMappackId::Id(id) => PendingMappack::Id(id.to_owned()),
+            AnyMappackId::Event(event, edition) => PendingMappack::Event {
+                event_id: event.id,
+                edition_id: edition.id,
+            },
+        }),
+        Err(e) => {
+            tracing::event!(
+                Level::WARN,
+                "Couldn't queue the update of the mappack {mappack:?} after a record on `{}`: {e}",
+                map.game_id
+            );
+            None
+        }
+    }
+}
+
+/// Queues the update of the scores of the regular MX mappacks and the event editions containing
+/// the map, after a new personal best made outside of an event context.
+async fn queue_mappacks_updates(
+    db: &mut DatabaseConnection,
+    map: &models::Map,
+    editions: &[(u32, u32, Option<u32>)],
+) -> RecordsResult<Vec<PendingMappack>> {
+    let mappacks = mappacks_of_map(&mut db.redis_conn, &map.game_id)
+        .await
+        .with_api_err()?;
+
+    let mut pending = Vec::with_capacity(mappacks.len() + editions.len());
+
+    for mappack_id in mappacks {
+        pending.extend(queue_mappack_update(db, AnyMappackId::Id(&mappack_id), map).await);
+    }
+
+    for (event_id, edition_id, _) in editions {
+        let (event, edition) =
+            must::have_event_edition_from_ids(&mut db.mysql_conn, *event_id, *edition_id)
+                .await
+                .with_api_err()?;
+        pending.extend(queue_mappack_update(db, AnyMappackId::Event(&event, &edition), map).await);
+    }
+
+    Ok(pending)
+}
+
+/// Updates the provided mappacks in the background, so the request doesn't wait for it.
+///
+/// The errors are only logged, like when queuing the updates.
+pub fn update_pending_mappacks(db: Database, mappacks: Vec<PendingMappack>) {
+    if mappacks.is_empty() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut conn = match db.acquire().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::event!(
+                    Level::WARN,
+                    "Couldn't acquire a connection to update the mappacks: {e}"
+                );
+                return;
+            }
+        };
+
+        for mappack in mappacks {
+            let result = match &mappack {
+                PendingMappack::Id(id) => {
+                    update_pending_maps(AnyMappackId::Id(id), &mut conn).await
+                }
+                PendingMappack::Event {
+                    event_id,
+                    edition_id,
+                } => {
+                    match must::have_event_edition_from_ids(
+                        &mut conn.mysql_conn,
+                        *event_id,
+                        *edition_id,
+                    )
+                    .await
+                    {
+                        Ok((event, edition)) => {
+                            update_pending_maps(AnyMappackId::Event(&event, &edition), &mut conn)
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+
+            if let Err(e) = result {
+                tracing::event!(Level::WARN, "Couldn't update the mappack {mappack:?}: {e}");
+            }
+        }
+    });
+}
+
+/// A new world record, made by the player who finished the map.
+pub struct NewWorldRecord {
+    pub map: models::Map,
+    pub player: models::Player,
+    pub time: i32,
+    /// The handle of the event and the ID of its edition, if it was made in an event context.
+    pub event: Option<(String, u32)>,
+    /// The previous world record with its player, if any.
+    pub previous: Option<(models::WorldRecord, models::Player)>,
+}
+
+/// Returns the best time on the map of the players other than the provided one,
+/// among the records counting in the leaderboard.
+async fn best_time_of_others(
+    db: &mut sqlx::MySqlConnection,
+    map_id: u32,
+    player_id: u32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<Option<i32>> {
+    let (join_event, and_event) = event.get_join();
+
+    let query = format!(
+        "SELECT MIN(r.time) FROM ranked_records r
+        {join_event}
+        WHERE r.map_id = ? AND r.record_player_id <> ?
+        {and_event}"
+    );
+
+    let mut query = sqlx::query_scalar(&query).bind(map_id).bind(player_id);
+
+    if let Some((event, edition)) = event.0 {
+        query = query.bind(event.id).bind(edition.id);
+    }
+
+    query.fetch_one(db).await.with_api_err()
+}
+
+/// Saves the new world record in the history, and returns the previous one.
+async fn save_world_record(
+    db: &mut sqlx::MySqlConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+    record_id: u32,
+    player_id: u32,
+    time: i32,
+    at: chrono::NaiveDateTime,
+) -> RecordsResult<Option<(models::WorldRecord, models::Player)>> {
+    let (event_id, edition_id) = event
+        .0
+        .map(|(event, edition)| (event.id, edition.id))
+        .unzip();
+
+    let previous: Option<models::WorldRecord> = sqlx::query_as(
+        "SELECT * FROM world_records
+        WHERE map_id = ? AND event_id <=> ? AND edition_id <=> ?
+        ORDER BY id DESC LIMIT 1",
+    )
+    .bind(map_id)
+    .bind(event_id)
+    .bind(edition_id)
+    .fetch_optional(&mut *db)
+    .await
+    .with_api_err()?;
+
+    sqlx::query(
+        "INSERT INTO world_records
+        (map_id, event_id, edition_id, record_id, player_id, time, record_date)
+        VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(map_id)
+    .bind(event_id)
+    .bind(edition_id)
+    .bind(record_id)
+    .bind(player_id)
+    .bind(time)
+    .bind(at)
+    .execute(&mut *db)
+    .await
+    .with_api_err()?;
+
+    let Some(previous) = previous else {
+        return Ok(None);
+    };
+
+    let previous_player = records_lib::player::get_player_from_id(&mut *db, previous.player_id)
+        .await
+        .with_api_err()?;
+
+    Ok(Some((previous, previous_player)))
+}
+
 pub struct FinishedOutput {
     pub record_id: u32,
     pub player_id: u32,
+    pub status: RecordStatus,
     pub res: HasFinishedResponse,
+    pub new_wr: Option<NewWorldRecord>,
+    /// The mappacks to update in the background with [`update_pending_mappacks`].
+    pub pending_mappacks: Vec<PendingMappack>,
+}
+
+/// Returns the rank the time would have in the leaderboard of the map.
+///
+/// This is used for the shadow-banned players, whose times aren't in the leaderboards.
+async fn shadow_rank(
+    db: &mut DatabaseConnection,
+    map_id: u32,
+    time: i32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<i32> {
+    let better_count = leaderboard(db)
+        .better_count(map_id, event, time)
+        .await
+        .with_api_err()?;
+    Ok(better_count + 1)
 }
 
 pub async fn finished(
@@ -147,9 +435,8 @@ pub async fn finished(
     at: chrono::NaiveDateTime,
 ) -> RecordsResult<FinishedOutput> {
     // First, we retrieve all what we need to save the record
-    let player_id = records_lib::must::have_player(&mut db.mysql_conn, &login)
-        .await?
-        .id;
+    let player = records_lib::must::have_player(&mut db.mysql_conn, &login).await?;
+    let player_id = player.id;
     let map @ models::Map {
         id: map_id,
         cps_number,
@@ -169,7 +456,7 @@ pub async fn finished(
     }
 
     let query = format!(
-        "SELECT r.* FROM records r
+        "SELECT r.* FROM valid_records r
         {join_event}
         WHERE map_id = ? AND record_player_id = ?
         {and_event}
@@ -192,21 +479,99 @@ pub async fn finished(
         .await
         .with_api_err()?;
 
+    let suspicion =
+        plausibility::check_run(&mut db.mysql_conn, map.id, event, &params.rest).await?;
+    let pending = suspicion.is_some();
+    let status = RecordStatus {
+        pending,
+        shadow_banned: records_lib::player::is_shadow_banned(&mut *db.mysql_conn, player_id)
+            .await
+            .with_api_err()?,
+    };
+
+    let had_record = old_record.is_some();
+
+    // A pending record can't improve the leaderboard until it is approved
     let (old, new, has_improved) = if let Some(models::Record { time: old, .. }) = old_record {
-        let improved = params.rest.time < old;
+        let improved = !pending && params.rest.time < old;
 
         (old, params.rest.time, improved)
     } else {
-        (params.rest.time, params.rest.time, true)
+        (params.rest.time, params.rest.time, !pending)
     };
 
-    let old_rank = get_rank_opt(&mut db.redis_conn, &map_key(map.id, event), old).await?;
+    let old_rank = if status.shadow_banned && had_record {
+        Some(shadow_rank(db, map.id, old, event).await?)
+    } else {
+        get_rank_opt(db, map.id, old, event).await?
+    };
 
     // We insert the record (whether it is the new personal best or not)
-    let record_id =
-        insert_record(db, map.id, player_id, params.rest.clone(), event, None, at).await?;
+    let record_id = insert_record(
+        db,
+        map.id,
+        player_id,
+        params.rest.clone(),
+        event,
+        None,
+        status,
+        at,
+    )
+    .await?;
 
-    let current_rank = get_rank(db, map.id, old.min(new), event).await?;
+    if let Some(reason) = &suspicion {
+        flag_record(&mut db.mysql_conn, record_id, reason).await?;
+    }
+
+    // The anticheat report of the run may have been sent before the record
+    moderation::link_ac_reports(&mut db.mysql_conn, player_id, map.id).await?;
+
+    let ranked_time = match (pending, had_record) {
+        (false, _) => Some(old.min(new)),
+        // The pending record isn't in the leaderboard, so the rank of the player doesn't change
+        (true, true) => Some(old),
+        (true, false) => None,
+    };
+
+    let current_rank = match ranked_time {
+        // The shadow-banned player sees the rank they would have
+        Some(time) if status.shadow_banned => shadow_rank(db, map.id, time, event).await?,
+        Some(time) => get_rank(db, map.id, time, event).await?,
+        // -1 is the value of a missing rank in ManiaScript, see `MpDefaultI32`
+        None => -1,
+    };
+
+    // A world record must beat the times of the other players, a tie isn't enough
+    let new_wr = if has_improved
+        && status.is_ranked()
+        && best_time_of_others(&mut db.mysql_conn, map.id, player_id, event)
+            .await?
+            .is_none_or(|best| new < best)
+    {
+        let previous = save_world_record(
+            &mut db.mysql_conn,
+            map.id,
+            event,
+            record_id,
+            player_id,
+            new,
+            at,
+        )
+        .await?;
+        Some(NewWorldRecord {
+            map: map.clone(),
+            player: player.clone(),
+            time: new,
+            event: event
+                .0
+                .map(|(event, edition)| (event.handle.clone(), edition.id)),
+            previous,
+        })
+    } else {
+        None
+    };
+
+    let mut pending_mappacks = Vec::new();
 
     // If the record isn't in an event context, save the record to the events that have the map
     // and allow records saving without an event context.
@@ -216,7 +581,7 @@ pub async fn finished(
             .await
             .with_api_err()?;
 
-        for (event_id, edition_id, original_map_id) in editions {
+        for &(event_id, edition_id, original_map_id) in &editions {
             event::insert_event_record(&mut db.mysql_conn, record_id, event_id, edition_id).await?;
 
             let Some(original_map_id) = original_map_id else {
@@ -230,22 +595,56 @@ pub async fn finished(
                 params.rest.clone(),
                 Default::default(),
                 Some(record_id),
+                status,
                 at,
             )
             .await?;
         }
+
+        // In an event context, the mappack of the edition is updated once the record
+        // is bound to it.
+        if has_improved && status.is_ranked() {
+            pending_mappacks = queue_mappacks_updates(db, map, &editions).await?;
+        }
+    }
+
+    // Notify the GraphQL subscribers, on this instance and the other ones.
+    // The pending records are kept private until they're approved, and the records
+    // of the shadow-banned players are never published.
+    if status.is_ranked() {
+        let record_event = RecordEvent::new(
+            record_id,
+            map.game_id.clone(),
+            event
+                .0
+                .map(|(event, edition)| (event.handle.clone(), edition.id)),
+            login.clone(),
+            player.name,
+            new,
+            current_rank,
+            old_rank,
+            has_improved,
+            at,
+        );
+        if let Err(e) = record_events::publish(&mut db.redis_conn, record_event).await {
+            tracing::event!(Level::WARN, "Couldn't publish the record {record_id}: {e}");
+        }
     }
 
     Ok(FinishedOutput {
         record_id,
         player_id,
+        status,
         res: HasFinishedResponse {
             has_improved,
+            pending,
             login,
             old,
             new,
             current_rank,
             old_rank: old_rank.map(From::from),
         },
+        new_wr,
+        pending_mappacks,
     })
 }
@@ -3,7 +3,7 @@
 //! The content of this library is only made for the API program.
 
 use actix_web::dev::Payload;
-use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use actix_web::{http::header, FromRequest, HttpRequest, HttpResponse};
 use chrono::{DateTime, Utc};
 use core::fmt;
 pub use deadpool_redis::Pool as RedisPool;
@@ -21,17 +21,22 @@ use thiserror::Error;
 use tokio::sync::mpsc::error::SendError;
 use tracing_actix_web::RequestId;
 
+mod api_key;
+mod audit;
 mod auth;
 mod graphql;
 mod http;
 pub(crate) mod must;
+mod rate_limit;
+pub mod record_events;
 mod utils;
+pub mod webhook;
 
 pub use auth::AuthState;
 pub use graphql::graphql_route;
 pub use http::api_route;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(unused)] // not used in code, but displayed as debug
 pub struct AccessTokenErr {
     error: String,
@@ -84,6 +89,8 @@ pub enum RecordsErrorKind {
     InvalidMPCode = 207,
     #[error("timeout exceeded (max 5 minutes)")]
     Timeout = 208,
+    #[error("too many requests, retry in {0} seconds")]
+    TooManyRequests(u64) = 209,
 
     // --------
     // --- Logical errors
@@ -116,6 +123,8 @@ pub enum RecordsErrorKind {
     InvalidMappackId(String),
     #[error("event `{0}` {1} has expired")]
     EventHasExpired(String, u32),
+    #[error("API key with id `{0}` not found")]
+    ApiKeyNotFound(u32) = 317,
 
     #[error(transparent)]
     Lib(#[from] records_lib::error::RecordsError),
@@ -221,6 +230,9 @@ impl actix_web::ResponseError for RecordsError {
             R::AccessTokenErr(_) => HttpResponse::BadRequest().json(self.to_err_res()),
             R::InvalidMPCode => HttpResponse::BadRequest().json(self.to_err_res()),
             R::Timeout => HttpResponse::RequestTimeout().json(self.to_err_res()),
+            R::TooManyRequests(retry_after) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, *retry_after))
+                .json(self.to_err_res()),
 
             // Logical errors
             R::EndpointNotFound => HttpResponse::NotFound().json(self.to_err_res()),
@@ -232,6 +244,7 @@ impl actix_web::ResponseError for RecordsError {
             R::InvalidTimes => HttpResponse::BadRequest().json(self.to_err_res()),
             R::InvalidMappackId(_) => HttpResponse::BadRequest().json(self.to_err_res()),
             R::EventHasExpired(..) => HttpResponse::BadRequest().json(self.to_err_res()),
+            R::ApiKeyNotFound(_) => HttpResponse::BadRequest().json(self.to_err_res()),
 
             R::Lib(e) => match e {
                 // Internal server errors
@@ -241,6 +254,18 @@ impl actix_web::ResponseError for RecordsError {
                     HttpResponse::InternalServerError().json(self.to_err_res())
                 }
                 LR::PoolError(_) => HttpResponse::InternalServerError().json(self.to_err_res()),
+                LR::Migration(_) => HttpResponse::InternalServerError().json(self.to_err_res()),
+                LR::TimeNotInLeaderboard(..) => {
+                    HttpResponse::InternalServerError().json(self.to_err_res())
+                }
+                LR::InvalidMxResponse(_) => {
+                    HttpResponse::InternalServerError().json(self.to_err_res())
+                }
+                LR::MxFixture(..) => HttpResponse::InternalServerError().json(self.to_err_res()),
+                LR::MappackLocked(_) => HttpResponse::ServiceUnavailable().json(self.to_err_res()),
+                LR::MappackLockLost(_) => {
+                    HttpResponse::ServiceUnavailable().json(self.to_err_res())
+                }
 
                 // Logical errors
                 LR::PlayerNotFound(_) => HttpResponse::BadRequest().json(self.to_err_res()),
@@ -248,6 +273,7 @@ impl actix_web::ResponseError for RecordsError {
                 LR::EventNotFound(_) => HttpResponse::BadRequest().json(self.to_err_res()),
                 LR::EventEditionNotFound(..) => HttpResponse::BadRequest().json(self.to_err_res()),
                 LR::MapNotInEventEdition(..) => HttpResponse::BadRequest().json(self.to_err_res()),
+                LR::RecordNotFound(_) => HttpResponse::BadRequest().json(self.to_err_res()),
             },
         }
     }
@@ -342,6 +368,19 @@ mkenv::make_env! {pub ApiEnvUsedOnce:
 }
 
 const DEFAULT_GQL_ENDPOINT: &str = "/graphql";
+const DEFAULT_WH_WR_URL: &str = "";
+const DEFAULT_WH_SIGNING_SECRET: &str = "";
+const DEFAULT_MIN_SPLIT: i32 = 0;
+const DEFAULT_AUTHOR_TIME_FLOOR: f64 = 0.;
+const DEFAULT_WR_FLOOR: f64 = 0.;
+const DEFAULT_RATE_LIMIT_PLAYER: u32 = 120;
+const DEFAULT_RATE_LIMIT_MAP: u32 = 60;
+const DEFAULT_RATE_LIMIT_EVENT: u32 = 120;
+const DEFAULT_RATE_LIMIT_GRAPHQL: u32 = 300;
+const DEFAULT_TRUSTED_PROXY_HEADER: &str = "";
+const DEFAULT_MP_OAUTH_TOKEN_URL: &str =
+    "https://prod.live.maniaplanet.com/login/oauth2/access_token";
+const DEFAULT_MP_WEBSERVICES_ME_URL: &str = "https://prod.live.maniaplanet.com/webservices/me";
 
 mkenv::make_env! {pub ApiEnv includes [
     DbEnv as db_env,
@@ -384,6 +423,22 @@ mkenv::make_env! {pub ApiEnv includes [
         desc: "The path to the file containing the Obstacle ManiaPlanet client secret",
     },
 
+    mp_oauth_token_url: {
+        id: MpOauthTokenUrl(String),
+        kind: normal,
+        var: "RECORDS_MP_OAUTH_TOKEN_URL",
+        desc: "The URL of the ManiaPlanet OAuth endpoint exchanging a code for an access token",
+        default: DEFAULT_MP_OAUTH_TOKEN_URL,
+    },
+
+    mp_webservices_me_url: {
+        id: MpWebservicesMeUrl(String),
+        kind: normal,
+        var: "RECORDS_MP_WEBSERVICES_ME_URL",
+        desc: "The URL of the ManiaPlanet web service returning the player of an access token",
+        default: DEFAULT_MP_WEBSERVICES_ME_URL,
+    },
+
     wh_report_url: {
         id: WebhookReportUrl(String),
         kind: normal,
@@ -398,6 +453,86 @@ mkenv::make_env! {pub ApiEnv includes [
         desc: "The URL to the Discord webhook used to share in-game statistics",
     },
 
+    wh_wr_url: {
+        id: WebhookWrUrl(String),
+        kind: normal,
+        var: "WEBHOOK_WR_URL",
+        desc: "The URL to the Discord webhook used to announce the new world records (empty to disable)",
+        default: DEFAULT_WH_WR_URL,
+    },
+
+    wh_signing_secret: {
+        id: WebhookSigningSecret(String),
+        kind: normal,
+        var: "WEBHOOK_SIGNING_SECRET",
+        desc: "The secret used to sign the webhook payloads with HMAC-SHA256 (empty to disable)",
+        default: DEFAULT_WH_SIGNING_SECRET,
+    },
+
+    min_split: {
+        id: MinSplit(i32),
+        kind: parse,
+        var: "RECORDS_MIN_SPLIT",
+        desc: "The minimum duration of a checkpoint split (in milliseconds), below which a run is suspicious (0 to disable)",
+        default: DEFAULT_MIN_SPLIT,
+    },
+
+    author_time_floor: {
+        id: AuthorTimeFloor(f64),
+        kind: parse,
+        var: "RECORDS_AUTHOR_TIME_FLOOR",
+        desc: "The fraction of the author time of an event map below which a run is suspicious (0 to disable)",
+        default: DEFAULT_AUTHOR_TIME_FLOOR,
+    },
+
+    wr_floor: {
+        id: WrFloor(f64),
+        kind: parse,
+        var: "RECORDS_WR_FLOOR",
+        desc: "The fraction of the world record of a map below which a run is suspicious (0 to disable)",
+        default: DEFAULT_WR_FLOOR,
+    },
+
+    rate_limit_player: {
+        id: RateLimitPlayer(u32),
+        kind: parse,
+        var: "RECORDS_RATE_LIMIT_PLAYER",
+        desc: "The amount of requests per minute a client can send to the /player routes (0 to disable)",
+        default: DEFAULT_RATE_LIMIT_PLAYER,
+    },
+
+    rate_limit_map: {
+        id: RateLimitMap(u32),
+        kind: parse,
+        var: "RECORDS_RATE_LIMIT_MAP",
+        desc: "The amount of requests per minute a client can send to the /map routes (0 to disable)",
+        default: DEFAULT_RATE_LIMIT_MAP,
+    },
+
+    rate_limit_event: {
+        id: RateLimitEvent(u32),
+        kind: parse,
+        var: "RECORDS_RATE_LIMIT_EVENT",
+        desc: "The amount of requests per minute a client can send to the /event routes (0 to disable)",
+        default: DEFAULT_RATE_LIMIT_EVENT,
+    },
+
+    rate_limit_graphql: {
+        id: RateLimitGraphql(u32),
+        kind: parse,
+        var: "RECORDS_RATE_LIMIT_GRAPHQL",
+        desc: "The amount of requests per minute a client can send to the GraphQL endpoint (0 to disable)",
+        default: DEFAULT_RATE_LIMIT_GRAPHQL,
+    },
+
+    trusted_proxy_header: {
+        id: TrustedProxyHeader(String),
+        kind: normal,
+        var: "RECORDS_TRUSTED_PROXY_HEADER",
+        desc: "The header set by the reverse proxy with the IP address of the client, e.g. X-Forwarded-For (empty to use the address of the peer)",
+        default: DEFAULT_TRUSTED_PROXY_HEADER,
+    },
+
     gql_endpoint: {
         id: GqlEndpoint(String),
         kind: normal,
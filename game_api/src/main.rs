@@ -18,7 +18,7 @@ use anyhow::Context;
 use game_api_lib::{
     api_route, graphql_route, AuthState, FitRequestId, RecordsErrorKind, RecordsResponse,
 };
-use records_lib::{get_mysql_pool, get_redis_pool, Database};
+use records_lib::{get_mysql_pool, get_redis_pool, mx::MxClient, Database};
 use reqwest::Client;
 use tracing_actix_web::{RequestId, TracingLogger};
 use tracing_subscriber::fmt::format::FmtSpan;
@@ -37,8 +37,8 @@ async fn main() -> anyhow::Result<()> {
     let mysql_pool = get_mysql_pool(env.db_env.db_url.db_url)
         .await
         .context("Cannot create MySQL pool")?;
-    let redis_pool =
-        get_redis_pool(env.db_env.redis_url.redis_url).context("Cannot create Redis pool")?;
+    let redis_url = env.db_env.redis_url.redis_url;
+    let redis_pool = get_redis_pool(redis_url.clone()).context("Cannot create Redis pool")?;
 
     let db = Database {
         mysql_pool,
@@ -46,12 +46,23 @@ async fn main() -> anyhow::Result<()> {
     };
 
     let client = Client::new();
+    let mx = MxClient::from_env(client.clone()).with_cache(db.redis_pool.clone());
 
     tracing_subscriber::fmt()
         .with_span_events(FmtSpan::CLOSE)
         .init();
 
-    let auth_state = Data::new(AuthState::default());
+    // Forward the records saved by the other instances to the GraphQL subscribers
+    tokio::spawn(game_api_lib::record_events::listen(redis_url.clone()));
+    // Deliver the enqueued webhook payloads
+    tokio::spawn(game_api_lib::webhook::dispatch(
+        db.redis_pool.clone(),
+        client.clone(),
+        game_api_lib::env().wh_signing_secret.clone(),
+    ));
+
+    let auth_state =
+        Data::new(AuthState::new(&redis_url).context("Cannot create the authentication state")?);
 
     let sess_key = Key::from(env.used_once.sess_key.as_bytes());
     drop(env.used_once.sess_key);
@@ -82,7 +93,7 @@ async fn main() -> anyhow::Result<()> {
             .app_data(auth_state.clone())
             .app_data(client.clone())
             .app_data(db.clone())
-            .service(graphql_route(db.clone(), client.clone()))
+            .service(graphql_route(db.clone(), mx.clone()))
             .service(api_route())
             .default_service(web::to(not_found))
     })
@@ -0,0 +1,238 @@
+//! The rate limiting of the requests.
+//!
+//! The `/player`, `/map` and `/event` scopes, and the GraphQL endpoint, are each wrapped
+//! by a [`RateLimit`] middleware, with its own limit configured in the API environment
+//! (e.g. `RECORDS_RATE_LIMIT_PLAYER`).
+//!
+//! The limit is implemented with a token bucket per client, saved in the Redis database so it is
+//! shared between the instances of the API. A bucket holds at most as many tokens as
+//! the requests allowed per minute, and is refilled continuously. Each request takes a token,
+//! and when the bucket is empty, the request is rejected with a `TooManyRequests` error
+//! and a `Retry-After` header, so the Obstacle gamemode can back off.
+//!
+//! The clients are identified by their API key if they send a valid one, otherwise by their login
+//! if they send a valid ManiaPlanet token, otherwise by their IP address. The IP address is the one
+//! of the peer, unless the API runs behind a reverse proxy, whose header is then configured
+//! with `RECORDS_TRUSTED_PROXY_HEADER`. This way, a client can't spread its requests
+//! over several buckets by changing the headers of its requests.
+
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpRequest;
+use deadpool_redis::redis;
+use futures::future::LocalBoxFuture;
+use records_lib::{redis_key::rate_limit_key, Database};
+use tracing::Level;
+
+use crate::{
+    api_key::{self, API_KEY_HEADER},
+    auth, must, RecordsError, RecordsErrorKind, RecordsResult, RecordsResultExt,
+};
+
+/// Takes a token from the bucket, after refilling it since its last update.
+///
+/// It returns 0 if the token was taken, otherwise the delay (in milliseconds)
+/// before a token is available.
+const TAKE_TOKEN_SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local refill = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * refill)
+
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) / refill)
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / refill))
+return wait
+";
+
+/// A middleware limiting the amount of requests per minute of each client on a group of routes.
+#[derive(Clone, Copy)]
+pub struct RateLimit {
+    scope: &'static str,
+    per_minute: u32,
+}
+
+impl RateLimit {
+    /// Creates the middleware of the group of routes named `scope`, allowing `per_minute`
+    /// requests per minute for each client.
+    ///
+    /// If `per_minute` is 0, the requests aren't limited.
+    pub fn new(scope: &'static str, per_minute: u32) -> Self {
+        Self { scope, per_minute }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+
+    type Error = actix_web::Error;
+
+    type Transform = RateLimitMiddleware<S>;
+
+    type InitError = ();
+
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limit: *self,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limit: RateLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+
+    type Error = actix_web::Error;
+
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let RateLimit { scope, per_minute } = self.limit;
+
+        Box::pin(async move {
+            if per_minute > 0 {
+                let request_id = must::have_request_id(req.request());
+                let db = must::have_db(req.request());
+
+                match check_limit(req.request(), &db, scope, per_minute).await {
+                    Ok(None) => (),
+                    Ok(Some(retry_after)) => {
+                        return Err(RecordsError {
+                            request_id,
+                            kind: RecordsErrorKind::TooManyRequests(retry_after),
+                        }
+                        .into());
+                    }
+                    // The requests aren't blocked if the limit can't be checked
+                    Err(e) => {
+                        tracing::event!(Level::WARN, "Cannot check the rate limit on {scope}: {e}")
+                    }
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+/// Takes a token from the bucket of the client who sent the request.
+///
+/// It has the same return value behavior than [`take_token`].
+async fn check_limit(
+    req: &HttpRequest,
+    db: &Database,
+    scope: &str,
+    per_minute: u32,
+) -> RecordsResult<Option<u64>> {
+    let client = client_of(req, db).await?;
+    take_token(&db.redis_pool, scope, &client, per_minute).await
+}
+
+/// Returns the identifier of the client who sent the request.
+///
+/// The API key and the login are checked before being used, otherwise the IP address is used.
+async fn client_of(req: &HttpRequest, db: &Database) -> RecordsResult<String> {
+    let header = |name| req.headers().get(name).and_then(|h| h.to_str().ok());
+
+    if let Some(key) = header(API_KEY_HEADER) {
+        let mut mysql_conn = db.mysql_pool.acquire().await.with_api_err()?;
+        if let Some(id) = api_key::api_key_id(&mut mysql_conn, key).await? {
+            return Ok(format!("api_key:{id}"));
+        }
+    } else if let (Some(login), Some(token)) = (header("PlayerLogin"), header("Authorization")) {
+        if auth::is_valid_mp_token(db, login, token).await? {
+            return Ok(format!("login:{login}"));
+        }
+    }
+
+    Ok(format!("ip:{}", client_ip(req)))
+}
+
+/// Returns the IP address of the client who sent the request.
+///
+/// If the API runs behind a reverse proxy, the header it sets is configured
+/// with `RECORDS_TRUSTED_PROXY_HEADER`. The other headers sent by the client are ignored.
+fn client_ip(req: &HttpRequest) -> String {
+    let proxy_header = &crate::env().trusted_proxy_header;
+
+    let forwarded = (!proxy_header.is_empty())
+        .then(|| req.headers().get(proxy_header.as_str()))
+        .flatten()
+        .and_then(|h| h.to_str().ok())
+        // The last address is the one added by the trusted proxy
+        .and_then(|h| h.rsplit(',').next())
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty());
+
+    match forwarded {
+        Some(ip) => ip.to_owned(),
+        None => req
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_owned()),
+    }
+}
+
+/// Takes a token from the bucket of the client.
+///
+/// It returns `None` if the request is allowed, otherwise the amount of seconds to wait
+/// before retrying it.
+async fn take_token(
+    redis_pool: &crate::RedisPool,
+    scope: &str,
+    client: &str,
+    per_minute: u32,
+) -> RecordsResult<Option<u64>> {
+    let mut redis_conn = redis_pool.get().await.with_api_err()?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time should be after the Unix epoch")
+        .as_millis() as u64;
+    // The amount of tokens added to the bucket each millisecond
+    let refill = per_minute as f64 / 60_000.;
+
+    let wait: u64 = redis::cmd("EVAL")
+        .arg(TAKE_TOKEN_SCRIPT)
+        .arg(1)
+        .arg(rate_limit_key(scope, client))
+        .arg(per_minute)
+        .arg(refill)
+        .arg(now)
+        .query_async(&mut redis_conn)
+        .await
+        .with_api_err()?;
+
+    Ok((wait > 0).then(|| wait.div_ceil(1000)))
+}
@@ -0,0 +1,159 @@
+//! The feed of the records saved by the API, used by the GraphQL subscriptions.
+//!
+//! When a record is saved, it is sent to the subscribers of this instance through a broadcast
+//! channel, and published on a Redis Pub/Sub channel for the other instances of the API.
+//! Each instance listens to this channel (see [`listen`]) and forwards the records saved by the
+//! other instances to its own subscribers.
+
+use std::time::Duration;
+
+use deadpool_redis::redis::{self, AsyncCommands as _};
+use futures::{Stream, StreamExt as _};
+use once_cell::sync::Lazy;
+use records_lib::{redis_key::record_events_channel, RedisConnection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::Level;
+
+use crate::{RecordsResult, RecordsResultExt};
+
+/// The maximum amount of records kept for the subscribers which are lagging behind.
+const FEED_CAPACITY: usize = 256;
+
+/// The delay before reconnecting to the Redis channel after an error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The ID of this instance of the API, used to ignore its own messages on the Redis channel.
+static INSTANCE_ID: Lazy<u64> = Lazy::new(rand::random);
+
+static FEED: Lazy<broadcast::Sender<RecordEvent>> =
+    Lazy::new(|| broadcast::channel(FEED_CAPACITY).0);
+
+/// A record saved by the API.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordEvent {
+    /// The ID of the API instance which saved the record.
+    origin: u64,
+    pub record_id: u32,
+    pub map_uid: String,
+    pub event_handle: Option<String>,
+    pub edition_id: Option<u32>,
+    pub player_login: String,
+    pub player_name: String,
+    pub time: i32,
+    /// The rank of the player on the map after this record, which may be their previous one.
+    pub rank: i32,
+    pub old_rank: Option<i32>,
+    pub has_improved: bool,
+    pub record_date: chrono::NaiveDateTime,
+}
+
+impl RecordEvent {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        record_id: u32,
+        map_uid: String,
+        event: Option<(String, u32)>,
+        player_login: String,
+        player_name: String,
+        time: i32,
+        rank: i32,
+        old_rank: Option<i32>,
+        has_improved: bool,
+        record_date: chrono::NaiveDateTime,
+    ) -> Self {
+        let (event_handle, edition_id) = event.unzip();
+        Self {
+            origin: *INSTANCE_ID,
+            record_id,
+            map_uid,
+            event_handle,
+            edition_id,
+            player_login,
+            player_name,
+            time,
+            rank,
+            old_rank,
+            has_improved,
+            record_date,
+        }
+    }
+}
+
+/// Sends the record to the subscribers of this instance, and publishes it
+/// to the other instances.
+pub async fn publish(redis_conn: &mut RedisConnection, event: RecordEvent) -> RecordsResult<()> {
+    let payload = serde_json::to_string(&event).expect("record event should be serializable");
+
+    // An error only means there isn't any subscriber at the moment
+    let _ = FEED.send(event);
+
+    let _: i64 = redis_conn
+        .publish(record_events_channel(), payload)
+        .await
+        .with_api_err()?;
+
+    Ok(())
+}
+
+/// Returns a stream of the records saved by any instance of the API from now on.
+///
+/// If the subscriber is lagging behind, the oldest records are skipped.
+pub fn subscribe() -> impl Stream<Item = RecordEvent> {
+    futures::stream::unfold(FEED.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+async fn forward_messages(client: &redis::Client) -> redis::RedisResult<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(record_events_channel()).await?;
+
+    let mut messages = pubsub.on_message();
+
+    while let Some(msg) = messages.next().await {
+        let payload: String = msg.get_payload()?;
+        let event: RecordEvent = match serde_json::from_str(&payload) {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::event!(Level::WARN, "Invalid record event `{payload}`: {e}");
+                continue;
+            }
+        };
+
+        // The records of this instance were already sent
+        if event.origin != *INSTANCE_ID {
+            let _ = FEED.send(event);
+        }
+    }
+
+    Ok(())
+}
+
+/// Listens to the records published by the other instances of the API,
+/// and forwards them to the subscribers of this instance.
+///
+/// This function never returns: it reconnects to the Redis database on error.
+pub async fn listen(redis_url: String) {
+    loop {
+        let res = match redis::Client::open(redis_url.as_str()) {
+            Ok(client) => forward_messages(&client).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = res {
+            tracing::event!(
+                Level::WARN,
+                "Lost the record events channel: {e}, reconnecting in {RECONNECT_DELAY:?}"
+            );
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
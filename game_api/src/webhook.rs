@@ -0,0 +1,604 @@
+//! The dispatcher of the webhooks sent by the API.
+//!
+//! The webhook payloads aren't sent directly by the request handlers. They are enqueued in a Redis
+//! stream (see [`enqueue`]), and delivered by a background worker (see [`dispatch`]), so a webhook
+//! being down doesn't make the requests fail, and the deliveries survive a restart of the API.
+//!
+//! A failed delivery is retried later, with an exponential backoff. After [`MAX_ATTEMPTS`]
+//! attempts, it is moved to a dead-letter stream, to be inspected manually.
+//!
+//! If a signing secret is configured (`WEBHOOK_SIGNING_SECRET`), each payload is signed with
+//! HMAC-SHA256, and the signature is sent in the [`SIGNATURE_HEADER`] header.
+//!
+//! With the `test-utils` feature, the `stand_in` module provides a local HTTP server that can be
+//! used in place of a real webhook, for example in tests.
+
+use std::time::Duration;
+
+use deadpool_redis::redis::{
+    streams::{
+        StreamClaimReply, StreamId, StreamMaxlen, StreamPendingCountReply, StreamReadOptions,
+        StreamReadReply,
+    },
+    AsyncCommands as _,
+};
+use hmac::{Hmac, Mac as _};
+use records_lib::{
+    redis_key::{webhooks_dead_key, webhooks_key, webhooks_retry_key},
+    RedisConnection,
+};
+use reqwest::{header, Client};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::Level;
+
+use crate::{RecordsResult, RecordsResultExt, RedisPool};
+
+/// The maximum amount of delivery attempts of a webhook payload before dead-lettering it.
+pub const MAX_ATTEMPTS: u32 = 10;
+
+/// The header containing the HMAC-SHA256 signature of the payload, formatted as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// The header containing the ID of the delivery, which stays the same between the attempts.
+pub const DELIVERY_ID_HEADER: &str = "X-Webhook-Id";
+
+/// The name of the consumer group of the dispatchers on the Redis stream.
+const GROUP: &str = "dispatchers";
+
+/// The delay before the first retry of a failed delivery. It doubles on each attempt.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two attempts of a delivery.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// The timeout of a single delivery attempt.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a dispatcher waits for new payloads before checking the retries again.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The idle time after which a payload read by another dispatcher is considered lost,
+/// e.g. because the dispatcher crashed, and is claimed by this one.
+const CLAIM_IDLE_TIME: Duration = Duration::from_secs(60);
+
+/// The amount of payloads handled at once.
+const BATCH_SIZE: usize = 16;
+
+/// The approximate maximum length of the dead-letter stream.
+const DEAD_LETTER_MAXLEN: usize = 1000;
+
+/// The delay before restarting the dispatcher after an error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A webhook payload waiting to be delivered.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookJob {
+    /// The ID of the delivery.
+    pub id: String,
+    /// The URL of the webhook.
+    pub url: String,
+    /// The JSON payload.
+    pub body: String,
+    /// The amount of failed attempts so far.
+    pub attempts: u32,
+}
+
+/// Returns the signature of the payload with the provided secret, as sent
+/// in the [`SIGNATURE_HEADER`] header.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC should accept keys of any size");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Enqueues the payload to be delivered to the webhook with the provided URL.
+///
+/// An empty URL means the webhook is disabled, so nothing is enqueued.
+pub async fn enqueue<T: Serialize>(
+    redis_conn: &mut RedisConnection,
+    url: &str,
+    body: &T,
+) -> RecordsResult<()> {
+    if url.is_empty() {
+        return Ok(());
+    }
+
+    let job = WebhookJob {
+        id: format!("{:016x}", rand::random::<u64>()),
+        url: url.to_owned(),
+        body: serde_json::to_string(body).expect("webhook body should be serializable"),
+        attempts: 0,
+    };
+
+    let _: String = redis_conn
+        .xadd(webhooks_key(), "*", &[("job", to_payload(&job))])
+        .await
+        .with_api_err()?;
+
+    Ok(())
+}
+
+fn to_payload(job: &WebhookJob) -> String {
+    serde_json::to_string(job).expect("webhook job should be serializable")
+}
+
+/// Returns the delay before the next attempt of a delivery which failed `attempts` times.
+fn retry_delay(attempts: u32) -> Duration {
+    BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+async fn deliver(
+    client: &Client,
+    job: &WebhookJob,
+    signing_secret: &str,
+) -> Result<(), reqwest::Error> {
+    let mut req = client
+        .post(&job.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(DELIVERY_ID_HEADER, &job.id);
+
+    if !signing_secret.is_empty() {
+        req = req.header(SIGNATURE_HEADER, sign(signing_secret, &job.body));
+    }
+
+    req.body(job.body.clone())
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+}
+
+async fn create_group(redis_conn: &mut RedisConnection) -> RecordsResult<()> {
+    match redis_conn
+        .xgroup_create_mkstream::<_, _, _, ()>(webhooks_key(), GROUP, "0")
+        .await
+    {
+        Ok(()) => Ok(()),
+        // The group was already created by another dispatcher
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        Err(e) => Err(e).with_api_err(),
+    }
+}
+
+/// Moves the failed deliveries whose next attempt is due back to the stream.
+async fn promote_retries(redis_conn: &mut RedisConnection) -> RecordsResult<()> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let due: Vec<String> = redis_conn
+        .zrangebyscore_limit(webhooks_retry_key(), "-inf", now, 0, BATCH_SIZE as isize)
+        .await
+        .with_api_err()?;
+
+    for payload in due {
+        // Only the dispatcher which removed the entry re-enqueues it
+        let removed: i64 = redis_conn
+            .zrem(webhooks_retry_key(), &payload)
+            .await
+            .with_api_err()?;
+        if removed == 1 {
+            let _: String = redis_conn
+                .xadd(webhooks_key(), "*", &[("job", payload)])
+                .await
+                .with_api_err()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Claims the payloads read by other dispatchers which didn't handle them in time.
+async fn claim_stale(
+    redis_conn: &mut RedisConnection,
+    consumer: &str,
+) -> RecordsResult<Vec<StreamId>> {
+    let min_idle = CLAIM_IDLE_TIME.as_millis() as usize;
+
+    let pending: StreamPendingCountReply = redis_conn
+        .xpending_count(webhooks_key(), GROUP, "-", "+", BATCH_SIZE)
+        .await
+        .with_api_err()?;
+    let ids = pending
+        .ids
+        .into_iter()
+        .filter(|p| p.last_delivered_ms >= min_idle)
+        .map(|p| p.id)
+        .collect::<Vec<_>>();
+
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let claimed: StreamClaimReply = redis_conn
+        .xclaim(webhooks_key(), GROUP, consumer, min_idle, &ids)
+        .await
+        .with_api_err()?;
+
+    Ok(claimed.ids)
+}
+
+async fn read_new(
+    redis_conn: &mut RedisConnection,
+    consumer: &str,
+) -> RecordsResult<Vec<StreamId>> {
+    let opts = StreamReadOptions::default()
+        .group(GROUP, consumer)
+        .count(BATCH_SIZE)
+        .block(POLL_TIMEOUT.as_millis() as usize);
+
+    let reply: Option<StreamReadReply> = redis_conn
+        .xread_options(&[webhooks_key()], &[">"], &opts)
+        .await
+        .with_api_err()?;
+
+    Ok(reply
+        .into_iter()
+        .flat_map(|reply| reply.keys)
+        .flat_map(|key| key.ids)
+        .collect())
+}
+
+async fn handle_entry(
+    redis_conn: &mut RedisConnection,
+    client: &Client,
+    signing_secret: &str,
+    entry: StreamId,
+) -> RecordsResult<()> {
+    let job = entry
+        .get::<String>("job")
+        .and_then(|payload| serde_json::from_str::<WebhookJob>(&payload).ok());
+
+    match job {
+        Some(mut job) => {
+            if let Err(e) = deliver(client, &job, signing_secret).await {
+                job.attempts += 1;
+
+                if job.attempts >= MAX_ATTEMPTS {
+                    tracing::event!(
+                        Level::WARN,
+                        "Webhook delivery {} to `{}` dead-lettered after {} attempts: {e}",
+                        job.id,
+                        job.url,
+                        job.attempts
+                    );
+                    let _: String = redis_conn
+                        .xadd_maxlen(
+                            webhooks_dead_key(),
+                            StreamMaxlen::Approx(DEAD_LETTER_MAXLEN),
+                            "*",
+                            &[("job", to_payload(&job)), ("error", e.to_string())],
+                        )
+                        .await
+                        .with_api_err()?;
+                } else {
+                    let delay = retry_delay(job.attempts);
+                    let due = chrono::Utc::now().timestamp_millis() + delay.as_millis() as i64;
+                    let _: i64 = redis_conn
+                        .zadd(webhooks_retry_key(), to_payload(&job), due)
+                        .await
+                        .with_api_err()?;
+                }
+            }
+        }
+        None => {
+            tracing::event!(
+                Level::WARN,
+                "Invalid webhook entry `{}`, dropping it",
+                entry.id
+            );
+        }
+    }
+
+    let _: i64 = redis_conn
+        .xack(webhooks_key(), GROUP, &[&entry.id])
+        .await
+        .with_api_err()?;
+    let _: i64 = redis_conn
+        .xdel(webhooks_key(), &[&entry.id])
+        .await
+        .with_api_err()?;
+
+    Ok(())
+}
+
+async fn run(
+    redis_pool: &RedisPool,
+    client: &Client,
+    signing_secret: &str,
+    consumer: &str,
+) -> RecordsResult<()> {
+    let mut redis_conn = redis_pool.get().await.with_api_err()?;
+    create_group(&mut redis_conn).await?;
+
+    loop {
+        promote_retries(&mut redis_conn).await?;
+
+        let mut entries = claim_stale(&mut redis_conn, consumer).await?;
+        entries.extend(read_new(&mut redis_conn, consumer).await?);
+
+        for entry in entries {
+            handle_entry(&mut redis_conn, client, signing_secret, entry).await?;
+        }
+    }
+}
+
+/// Delivers the enqueued webhook payloads.
+///
+/// Many dispatchers can run at the same time, e.g. one per instance of the API: each payload
+/// is delivered by only one of them.
+///
+/// The payloads are signed with the provided secret, unless it is empty
+/// (see [`SIGNATURE_HEADER`]).
+///
+/// This function never returns: it restarts on error.
+pub async fn dispatch(redis_pool: RedisPool, client: Client, signing_secret: String) {
+    let consumer = format!("{:016x}", rand::random::<u64>());
+
+    loop {
+        if let Err(e) = run(&redis_pool, &client, &signing_secret, &consumer).await {
+            tracing::event!(
+                Level::WARN,
+                "Webhook dispatcher failed: {e}, restarting in {RECONNECT_DELAY:?}"
+            );
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// A local HTTP server standing in for a webhook.
+///
+/// It records the requests it receives, and can be configured to fail a given amount of times
+/// before accepting them, to exercise the retries of the dispatcher.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod stand_in {
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use actix_web::{
+        dev::ServerHandle,
+        web::{self, Data},
+        App, HttpRequest, HttpResponse, HttpServer,
+    };
+
+    use super::{DELIVERY_ID_HEADER, SIGNATURE_HEADER};
+
+    /// A request received by the stand-in.
+    #[derive(Debug, Clone)]
+    pub struct Received {
+        /// The value of the [`DELIVERY_ID_HEADER`] header.
+        pub id: Option<String>,
+        /// The value of the [`SIGNATURE_HEADER`] header.
+        pub signature: Option<String>,
+        /// The payload.
+        pub body: String,
+        /// Whether the stand-in accepted the request, or answered with an error.
+        pub accepted: bool,
+    }
+
+    struct State {
+        remaining_failures: AtomicUsize,
+        received: Mutex<Vec<Received>>,
+    }
+
+    /// The handle to a running stand-in. It is stopped with [`StandIn::stop`].
+    pub struct StandIn {
+        url: String,
+        state: Data<State>,
+        handle: ServerHandle,
+    }
+
+    fn header(req: &HttpRequest, name: &str) -> Option<String> {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+    }
+
+    async fn receive(req: HttpRequest, state: Data<State>, body: String) -> HttpResponse {
+        let accepted = state
+            .remaining_failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_err();
+
+        state.received.lock().unwrap().push(Received {
+            id: header(&req, DELIVERY_ID_HEADER),
+            signature: header(&req, SIGNATURE_HEADER),
+            body,
+            accepted,
+        });
+
+        if accepted {
+            HttpResponse::NoContent().finish()
+        } else {
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+
+    impl StandIn {
+        /// Starts a stand-in on a random local port, which fails the first `failures` requests.
+        pub async fn start(failures: usize) -> io::Result<Self> {
+            let state = Data::new(State {
+                remaining_failures: AtomicUsize::new(failures),
+                received: Mutex::new(Vec::new()),
+            });
+
+            let server = HttpServer::new({
+                let state = state.clone();
+                move || {
+                    App::new()
+                        .app_data(state.clone())
+                        .default_service(web::to(receive))
+                }
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))?;
+
+            let url = format!("http://{}/", server.addrs()[0]);
+            let server = server.run();
+            let handle = server.handle();
+            tokio::spawn(server);
+
+            Ok(Self { url, state, handle })
+        }
+
+        /// Returns the URL of the stand-in, to be used as a webhook URL.
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        /// Returns the requests received so far, in order.
+        pub fn received(&self) -> Vec<Received> {
+            self.state.received.lock().unwrap().clone()
+        }
+
+        /// Stops the stand-in.
+        pub async fn stop(self) {
+            self.handle.stop(true).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use deadpool_redis::redis::{streams::StreamRangeReply, AsyncCommands as _};
+    use records_lib::redis_key::{webhooks_dead_key, webhooks_key};
+    use reqwest::Client;
+
+    use super::{dispatch, enqueue, sign, stand_in::StandIn, to_payload, WebhookJob, MAX_ATTEMPTS};
+    use crate::RedisPool;
+
+    const SIGNING_SECRET: &str = "stand-in secret";
+
+    /// How long the tests wait for the dispatcher. The first retry is made after 1 second.
+    const WAIT_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Returns the pool to the Redis database of the tests, whose URL is read from `REDIS_URL`.
+    ///
+    /// The tests needing it are ignored by default. To run them:
+    ///
+    /// ```sh
+    /// REDIS_URL=redis://... cargo test -p game-api webhook -- --ignored
+    /// ```
+    fn redis_pool() -> RedisPool {
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL should be set");
+        records_lib::get_redis_pool(url).expect("REDIS_URL should be a valid Redis URL")
+    }
+
+    async fn wait_until(mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(
+                start.elapsed() < WAIT_TIMEOUT,
+                "timed out waiting for the dispatcher"
+            );
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    async fn dead_letters(redis_pool: &RedisPool) -> Vec<WebhookJob> {
+        let mut redis_conn = redis_pool.get().await.unwrap();
+        let reply: StreamRangeReply = redis_conn.xrange_all(webhooks_dead_key()).await.unwrap();
+        reply
+            .ids
+            .iter()
+            .filter_map(|entry| entry.get::<String>("job"))
+            .filter_map(|job| serde_json::from_str(&job).ok())
+            .collect()
+    }
+
+    // The scenarios share the Redis stream, so they run one after the other with a single
+    // dispatcher, which signs the payloads.
+    #[actix_web::test]
+    #[ignore = "needs a Redis database"]
+    async fn dispatch_to_stand_in() {
+        let redis_pool = redis_pool();
+
+        tokio::spawn(dispatch(
+            redis_pool.clone(),
+            Client::new(),
+            SIGNING_SECRET.to_owned(),
+        ));
+
+        retried_until_delivered(&redis_pool).await;
+        dead_lettered_after_max_attempts(&redis_pool).await;
+    }
+
+    async fn retried_until_delivered(redis_pool: &RedisPool) {
+        let stand_in = StandIn::start(1).await.unwrap();
+        let mut redis_conn = redis_pool.get().await.unwrap();
+
+        let body = serde_json::json!({ "content": "retried" });
+        enqueue(&mut redis_conn, stand_in.url(), &body)
+            .await
+            .unwrap();
+
+        wait_until(|| stand_in.received().len() == 2).await;
+        let received = stand_in.received();
+
+        // The first attempt failed, and the retry has the same delivery ID
+        assert!(!received[0].accepted);
+        assert!(received[1].accepted);
+        assert!(received[0].id.is_some());
+        assert_eq!(received[0].id, received[1].id);
+
+        let expected_body = body.to_string();
+        for request in &received {
+            assert_eq!(request.body, expected_body);
+            assert_eq!(
+                request.signature.as_deref(),
+                Some(sign(SIGNING_SECRET, &expected_body).as_str())
+            );
+        }
+
+        stand_in.stop().await;
+    }
+
+    async fn dead_lettered_after_max_attempts(redis_pool: &RedisPool) {
+        let stand_in = StandIn::start(usize::MAX).await.unwrap();
+        let mut redis_conn = redis_pool.get().await.unwrap();
+
+        // The job is enqueued on its last attempt, to avoid waiting for all the retries
+        let job = WebhookJob {
+            id: format!("{:016x}", rand::random::<u64>()),
+            url: stand_in.url().to_owned(),
+            body: r#"{"content":"dead-lettered"}"#.to_owned(),
+            attempts: MAX_ATTEMPTS - 1,
+        };
+        let _: String = redis_conn
+            .xadd(webhooks_key(), "*", &[("job", to_payload(&job))])
+            .await
+            .unwrap();
+
+        wait_until(|| !stand_in.received().is_empty()).await;
+
+        let start = Instant::now();
+        let dead = loop {
+            let dead = dead_letters(redis_pool).await;
+            if let Some(dead) = dead.into_iter().find(|dead| dead.id == job.id) {
+                break dead;
+            }
+            assert!(
+                start.elapsed() < WAIT_TIMEOUT,
+                "the job wasn't dead-lettered"
+            );
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+
+        assert_eq!(dead.attempts, MAX_ATTEMPTS);
+        assert_eq!(dead.body, job.body);
+        assert_eq!(stand_in.received().len(), 1);
+
+        stand_in.stop().await;
+    }
+}
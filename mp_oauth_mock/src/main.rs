@@ -0,0 +1,198 @@
+//! A stand-in for the ManiaPlanet OAuth system, used to run the authentication flow of the API
+//! locally, without a ManiaPlanet account.
+//!
+//! It implements the endpoints used during the flow, for fake logins:
+//! * `GET /login/oauth2/authorize` redirects the browser to the `redirect_uri` with a code
+//!   for the login provided in the `login` query parameter (`mock_player` by default).
+//! * `POST /login/oauth2/access_token` exchanges the code for an access token.
+//! * `GET /webservices/me` returns the player of the access token.
+//!
+//! The codes and access tokens are derived from the login, so the server doesn't hold any state.
+//! The codes of the logins starting with `invalid` are rejected, to exercise the error handling
+//! of the API.
+//!
+//! To use it, run it then start the API with these environment variables:
+//!
+//! ```sh
+//! cargo run -p mp_oauth_mock -- --port 8081
+//! RECORDS_MP_OAUTH_TOKEN_URL=http://localhost:8081/login/oauth2/access_token \
+//! RECORDS_MP_WEBSERVICES_ME_URL=http://localhost:8081/webservices/me \
+//! cargo run -p game-api
+//! ```
+//!
+//! The integration tests can also skip the browser, and send the code directly to the
+//! `/player/give_token` endpoint: the code of a login `x` is `mock_code:x`.
+
+use actix_web::{
+    http::header,
+    web::{self, Form, Query},
+    App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use anyhow::Context as _;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+const CODE_PREFIX: &str = "mock_code:";
+const ACCESS_TOKEN_PREFIX: &str = "mock_token:";
+const DEFAULT_LOGIN: &str = "mock_player";
+
+#[derive(clap::Parser)]
+struct Args {
+    /// The port used to expose the server.
+    #[arg(short, long, default_value_t = 8081)]
+    port: u16,
+}
+
+/// Encodes the provided string to be used in a query string.
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct AuthorizeQuery {
+    redirect_uri: String,
+    state: String,
+    login: Option<String>,
+}
+
+async fn authorize(Query(query): Query<AuthorizeQuery>) -> impl Responder {
+    let login = query.login.as_deref().unwrap_or(DEFAULT_LOGIN);
+    tracing::info!("Authorizing `{login}` for the state `{}`", query.state);
+
+    let separator = if query.redirect_uri.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let location = format!(
+        "{}{separator}code={}&state={}",
+        query.redirect_uri,
+        url_encode(&format!("{CODE_PREFIX}{login}")),
+        url_encode(&query.state),
+    );
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+#[derive(Deserialize)]
+#[allow(unused)]
+struct AccessTokenBody {
+    grant_type: String,
+    client_id: String,
+    client_secret: String,
+    code: String,
+    redirect_uri: String,
+}
+
+#[derive(Serialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: u32,
+}
+
+/// The same shape as the errors returned by the ManiaPlanet OAuth system.
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: &'static str,
+    message: String,
+    hint: String,
+}
+
+fn error_response(error: &'static str, message: String, hint: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        error,
+        message,
+        hint: hint.to_owned(),
+    })
+}
+
+async fn access_token(Form(body): Form<AccessTokenBody>) -> impl Responder {
+    if body.grant_type != "authorization_code" {
+        return error_response(
+            "unsupported_grant_type",
+            format!("The grant type `{}` is not supported", body.grant_type),
+            "Use the `authorization_code` grant type",
+        );
+    }
+
+    let login = match body.code.strip_prefix(CODE_PREFIX) {
+        Some(login) if !login.is_empty() && !login.starts_with("invalid") => login,
+        _ => {
+            return error_response(
+                "invalid_request",
+                "The authorization code is invalid".to_owned(),
+                &format!("The code of a login `x` is `{CODE_PREFIX}x`"),
+            )
+        }
+    };
+
+    tracing::info!("Exchanged a code for an access token of `{login}`");
+
+    HttpResponse::Ok().json(AccessTokenResponse {
+        access_token: format!("{ACCESS_TOKEN_PREFIX}{login}"),
+        token_type: "Bearer",
+        expires_in: 3600,
+    })
+}
+
+#[derive(Serialize)]
+struct MeResponse<'a> {
+    login: &'a str,
+    nickname: &'a str,
+    path: &'static str,
+}
+
+async fn me(req: HttpRequest) -> impl Responder {
+    let login = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .and_then(|token| token.strip_prefix(ACCESS_TOKEN_PREFIX))
+        .filter(|login| !login.is_empty());
+
+    match login {
+        Some(login) => HttpResponse::Ok().json(MeResponse {
+            login,
+            nickname: login,
+            path: "World",
+        }),
+        None => HttpResponse::Unauthorized().finish(),
+    }
+}
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    tracing_subscriber::fmt().init();
+
+    tracing::info!(
+        "Mock ManiaPlanet OAuth server listening on port {}",
+        args.port
+    );
+
+    HttpServer::new(|| {
+        App::new()
+            .route("/login/oauth2/authorize", web::get().to(authorize))
+            .route("/login/oauth2/access_token", web::post().to(access_token))
+            .route("/webservices/me", web::get().to(me))
+    })
+    .bind(("0.0.0.0", args.port))
+    .context("Cannot bind 0.0.0.0 address")?
+    .run()
+    .await
+    .context("Cannot create actix-web server")?;
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+-- Initial schema of the API database.
+--
+-- The statements use `IF NOT EXISTS` so this migration can also be applied on a database
+-- that was created before the migrations were introduced.
+
+CREATE TABLE IF NOT EXISTS role (
+    id TINYINT UNSIGNED NOT NULL,
+    role_name VARCHAR(32) NOT NULL,
+    privileges TINYINT UNSIGNED NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS players (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    login VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    join_date DATETIME NULL,
+    zone_path VARCHAR(255) NULL,
+    admins_note TEXT NULL,
+    role TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    PRIMARY KEY (id),
+    UNIQUE KEY players_login (login),
+    CONSTRAINT players_role_fk FOREIGN KEY (role) REFERENCES role (id)
+);
+
+CREATE TABLE IF NOT EXISTS maps (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    game_id VARCHAR(255) NOT NULL,
+    player_id INT UNSIGNED NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    cps_number INT UNSIGNED NULL,
+    linked_map INT UNSIGNED NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY maps_game_id (game_id),
+    CONSTRAINT maps_player_fk FOREIGN KEY (player_id) REFERENCES players (id),
+    CONSTRAINT maps_linked_map_fk FOREIGN KEY (linked_map) REFERENCES maps (id)
+);
+
+CREATE TABLE IF NOT EXISTS records (
+    record_id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    record_player_id INT UNSIGNED NOT NULL,
+    map_id INT UNSIGNED NOT NULL,
+    time INT NOT NULL,
+    respawn_count INT NOT NULL,
+    record_date DATETIME(3) NOT NULL,
+    flags INT UNSIGNED NOT NULL DEFAULT 0,
+    try_count INT UNSIGNED NULL,
+    event_record_id INT UNSIGNED NULL,
+    PRIMARY KEY (record_id),
+    KEY records_map_player_time (map_id, record_player_id, time),
+    KEY records_player (record_player_id),
+    CONSTRAINT records_player_fk FOREIGN KEY (record_player_id) REFERENCES players (id),
+    CONSTRAINT records_map_fk FOREIGN KEY (map_id) REFERENCES maps (id),
+    CONSTRAINT records_event_record_fk FOREIGN KEY (event_record_id) REFERENCES records (record_id)
+);
+
+CREATE TABLE IF NOT EXISTS checkpoint_times (
+    cp_num INT UNSIGNED NOT NULL,
+    map_id INT UNSIGNED NOT NULL,
+    record_id INT UNSIGNED NOT NULL,
+    time INT NOT NULL,
+    PRIMARY KEY (record_id, cp_num),
+    CONSTRAINT checkpoint_times_map_fk FOREIGN KEY (map_id) REFERENCES maps (id),
+    CONSTRAINT checkpoint_times_record_fk FOREIGN KEY (record_id) REFERENCES records (record_id)
+        ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS banishments (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    date_ban DATETIME NOT NULL,
+    duration BIGINT NULL,
+    was_reprieved BOOLEAN NOT NULL DEFAULT FALSE,
+    reason VARCHAR(255) NOT NULL,
+    player_id INT UNSIGNED NULL,
+    banished_by INT UNSIGNED NULL,
+    PRIMARY KEY (id),
+    CONSTRAINT banishments_player_fk FOREIGN KEY (player_id) REFERENCES players (id),
+    CONSTRAINT banishments_banished_by_fk FOREIGN KEY (banished_by) REFERENCES players (id)
+);
+
+CREATE TABLE IF NOT EXISTS rating_kind (
+    id TINYINT UNSIGNED NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS rating (
+    player_id INT UNSIGNED NOT NULL,
+    map_id INT UNSIGNED NOT NULL,
+    rating_date DATETIME NOT NULL,
+    PRIMARY KEY (player_id, map_id),
+    CONSTRAINT rating_player_fk FOREIGN KEY (player_id) REFERENCES players (id),
+    CONSTRAINT rating_map_fk FOREIGN KEY (map_id) REFERENCES maps (id)
+);
+
+CREATE TABLE IF NOT EXISTS player_rating (
+    player_id INT UNSIGNED NOT NULL,
+    map_id INT UNSIGNED NOT NULL,
+    kind TINYINT UNSIGNED NOT NULL,
+    rating FLOAT NOT NULL,
+    PRIMARY KEY (player_id, map_id, kind),
+    CONSTRAINT player_rating_rating_fk FOREIGN KEY (player_id, map_id)
+        REFERENCES rating (player_id, map_id) ON DELETE CASCADE,
+    CONSTRAINT player_rating_kind_fk FOREIGN KEY (kind) REFERENCES rating_kind (id)
+);
+
+CREATE TABLE IF NOT EXISTS event (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    handle VARCHAR(255) NOT NULL,
+    cooldown TINYINT UNSIGNED NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY event_handle (handle)
+);
+
+CREATE TABLE IF NOT EXISTS event_admins (
+    event_id INT UNSIGNED NOT NULL,
+    player_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (event_id, player_id),
+    CONSTRAINT event_admins_event_fk FOREIGN KEY (event_id) REFERENCES event (id),
+    CONSTRAINT event_admins_player_fk FOREIGN KEY (player_id) REFERENCES players (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_category (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    handle VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    banner_img_url VARCHAR(512) NULL,
+    hex_color VARCHAR(16) NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_categories (
+    event_id INT UNSIGNED NOT NULL,
+    category_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (event_id, category_id),
+    CONSTRAINT event_categories_event_fk FOREIGN KEY (event_id) REFERENCES event (id),
+    CONSTRAINT event_categories_category_fk FOREIGN KEY (category_id) REFERENCES event_category (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_edition (
+    id INT UNSIGNED NOT NULL,
+    event_id INT UNSIGNED NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    subtitle VARCHAR(255) NULL,
+    start_date DATETIME NOT NULL,
+    banner_img_url VARCHAR(512) NULL,
+    banner2_img_url VARCHAR(512) NULL,
+    mx_id BIGINT NULL,
+    mx_secret VARCHAR(255) NULL,
+    ttl BIGINT UNSIGNED NULL,
+    save_non_event_record BOOLEAN NOT NULL DEFAULT TRUE,
+    non_original_maps BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id, event_id),
+    CONSTRAINT event_edition_event_fk FOREIGN KEY (event_id) REFERENCES event (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_edition_admins (
+    event_id INT UNSIGNED NOT NULL,
+    edition_id INT UNSIGNED NOT NULL,
+    player_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (event_id, edition_id, player_id),
+    CONSTRAINT event_edition_admins_edition_fk FOREIGN KEY (edition_id, event_id)
+        REFERENCES event_edition (id, event_id),
+    CONSTRAINT event_edition_admins_player_fk FOREIGN KEY (player_id) REFERENCES players (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_edition_categories (
+    event_id INT UNSIGNED NOT NULL,
+    edition_id INT UNSIGNED NOT NULL,
+    category_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (event_id, edition_id, category_id),
+    CONSTRAINT event_edition_categories_edition_fk FOREIGN KEY (edition_id, event_id)
+        REFERENCES event_edition (id, event_id),
+    CONSTRAINT event_edition_categories_category_fk FOREIGN KEY (category_id)
+        REFERENCES event_category (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_edition_maps (
+    event_id INT UNSIGNED NOT NULL,
+    edition_id INT UNSIGNED NOT NULL,
+    map_id INT UNSIGNED NOT NULL,
+    category_id INT UNSIGNED NULL,
+    mx_id BIGINT NULL,
+    `order` INT UNSIGNED NOT NULL DEFAULT 0,
+    original_map_id INT UNSIGNED NULL,
+    original_mx_id BIGINT NULL,
+    transitive_save BOOLEAN NULL,
+    bronze_time INT NULL,
+    silver_time INT NULL,
+    gold_time INT NULL,
+    author_time INT NULL,
+    PRIMARY KEY (event_id, edition_id, map_id),
+    KEY event_edition_maps_map (map_id),
+    CONSTRAINT event_edition_maps_edition_fk FOREIGN KEY (edition_id, event_id)
+        REFERENCES event_edition (id, event_id),
+    CONSTRAINT event_edition_maps_map_fk FOREIGN KEY (map_id) REFERENCES maps (id),
+    CONSTRAINT event_edition_maps_category_fk FOREIGN KEY (category_id)
+        REFERENCES event_category (id),
+    CONSTRAINT event_edition_maps_original_map_fk FOREIGN KEY (original_map_id)
+        REFERENCES maps (id)
+);
+
+CREATE TABLE IF NOT EXISTS event_edition_records (
+    record_id INT UNSIGNED NOT NULL,
+    event_id INT UNSIGNED NOT NULL,
+    edition_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (record_id),
+    KEY event_edition_records_edition (event_id, edition_id),
+    CONSTRAINT event_edition_records_record_fk FOREIGN KEY (record_id)
+        REFERENCES records (record_id) ON DELETE CASCADE,
+    CONSTRAINT event_edition_records_edition_fk FOREIGN KEY (edition_id, event_id)
+        REFERENCES event_edition (id, event_id)
+);
+
+CREATE TABLE IF NOT EXISTS api_status (
+    status_id TINYINT UNSIGNED NOT NULL,
+    status_name VARCHAR(32) NOT NULL,
+    PRIMARY KEY (status_id)
+);
+
+CREATE TABLE IF NOT EXISTS api_status_history (
+    status_history_id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    status_id TINYINT UNSIGNED NOT NULL,
+    status_history_date DATETIME NOT NULL,
+    PRIMARY KEY (status_history_id),
+    CONSTRAINT api_status_history_status_fk FOREIGN KEY (status_id) REFERENCES api_status (status_id)
+);
+
+CREATE TABLE IF NOT EXISTS latestnews_image (
+    img_url VARCHAR(512) NOT NULL,
+    link VARCHAR(512) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS resources_content (
+    content TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
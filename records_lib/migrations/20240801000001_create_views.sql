@@ -0,0 +1,35 @@
+-- The views used to retrieve the personal bests of the players and the current banishments.
+
+-- The best record of each player on each map.
+CREATE OR REPLACE VIEW global_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id
+FROM (
+    SELECT r.*, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM records r
+) r
+WHERE rn = 1;
+
+-- The best record of each player on each map, for each event edition.
+CREATE OR REPLACE VIEW global_event_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id, event_id, edition_id
+FROM (
+    SELECT r.*, eer.event_id, eer.edition_id, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id, eer.event_id, eer.edition_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM records r
+    INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+) r
+WHERE rn = 1;
+
+-- The banishments that are still running.
+CREATE OR REPLACE VIEW current_bans AS
+SELECT *
+FROM banishments
+WHERE duration IS NULL
+    OR date_ban + INTERVAL duration SECOND > SYSDATE();
@@ -0,0 +1,20 @@
+-- The constant rows the API relies on.
+
+INSERT IGNORE INTO role (id, role_name, privileges) VALUES
+    (0, 'player', 1),
+    (1, 'mod', 3),
+    (2, 'admin', 15);
+
+INSERT IGNORE INTO rating_kind (id, kind) VALUES
+    (0, 'route'),
+    (1, 'deco'),
+    (2, 'smoothness'),
+    (3, 'difficulty');
+
+INSERT IGNORE INTO api_status (status_id, status_name) VALUES
+    (1, 'normal'),
+    (2, 'maintenance');
+
+INSERT INTO api_status_history (status_id, status_history_date)
+SELECT 1, SYSDATE() FROM DUAL
+WHERE NOT EXISTS (SELECT 1 FROM api_status_history);
@@ -0,0 +1,4 @@
+-- The scoring strategy of the event editions, see the `records_lib::scoring` module
+-- for the format of the values.
+ALTER TABLE event_edition
+    ADD COLUMN IF NOT EXISTS scoring VARCHAR(255) NOT NULL DEFAULT 'average_rank';
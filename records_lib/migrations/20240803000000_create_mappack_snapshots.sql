@@ -0,0 +1,23 @@
+-- The snapshots of the mappack standings, saved on each update of the scores of a mappack.
+CREATE TABLE IF NOT EXISTS mappack_snapshot (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    -- The displayed mappack ID, see `records_lib::mappack::AnyMappackId`
+    mappack_id VARCHAR(255) NOT NULL,
+    snapshot_date DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    INDEX mappack_snapshot_date_idx (mappack_id, snapshot_date)
+);
+
+CREATE TABLE IF NOT EXISTS mappack_snapshot_player (
+    snapshot_id INT UNSIGNED NOT NULL,
+    player_id INT UNSIGNED NOT NULL,
+    `rank` INT UNSIGNED NOT NULL,
+    rank_avg DOUBLE NOT NULL,
+    points DOUBLE NULL,
+    maps_finished INT UNSIGNED NOT NULL,
+    PRIMARY KEY (snapshot_id, player_id),
+    INDEX mappack_snapshot_player_idx (player_id),
+    CONSTRAINT mappack_snapshot_player_snapshot_fk FOREIGN KEY (snapshot_id)
+        REFERENCES mappack_snapshot (id) ON DELETE CASCADE,
+    CONSTRAINT mappack_snapshot_player_player_fk FOREIGN KEY (player_id) REFERENCES players (id)
+);
@@ -0,0 +1,19 @@
+-- The history of the world records, saved each time a run takes the first rank of a map
+-- or of an event map.
+CREATE TABLE IF NOT EXISTS world_records (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    map_id INT UNSIGNED NOT NULL,
+    event_id INT UNSIGNED NULL,
+    edition_id INT UNSIGNED NULL,
+    record_id INT UNSIGNED NOT NULL,
+    player_id INT UNSIGNED NOT NULL,
+    time INT NOT NULL,
+    record_date DATETIME(3) NOT NULL,
+    PRIMARY KEY (id),
+    INDEX world_records_map_idx (map_id, event_id, edition_id),
+    CONSTRAINT world_records_map_fk FOREIGN KEY (map_id) REFERENCES maps (id),
+    CONSTRAINT world_records_edition_fk FOREIGN KEY (edition_id, event_id)
+        REFERENCES event_edition (id, event_id),
+    CONSTRAINT world_records_record_fk FOREIGN KEY (record_id) REFERENCES records (record_id),
+    CONSTRAINT world_records_player_fk FOREIGN KEY (player_id) REFERENCES players (id)
+);
@@ -0,0 +1,57 @@
+-- The records can be invalidated by a moderator, e.g. when they are confirmed as cheated.
+-- An invalidated record is kept, but it doesn't count in the leaderboards anymore.
+ALTER TABLE records ADD COLUMN IF NOT EXISTS invalidated_at DATETIME(3) NULL;
+
+CREATE OR REPLACE VIEW global_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id
+FROM (
+    SELECT r.*, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM records r
+    WHERE r.invalidated_at IS NULL
+) r
+WHERE rn = 1;
+
+CREATE OR REPLACE VIEW global_event_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id, event_id, edition_id
+FROM (
+    SELECT r.*, eer.event_id, eer.edition_id, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id, eer.event_id, eer.edition_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM records r
+    INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+    WHERE r.invalidated_at IS NULL
+) r
+WHERE rn = 1;
+
+-- The reports sent by the anticheat of the Obstacle gamemode, waiting to be reviewed
+-- by a moderator.
+CREATE TABLE IF NOT EXISTS ac_reports (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    player_id INT UNSIGNED NOT NULL,
+    map_id INT UNSIGNED NOT NULL,
+    record_id INT UNSIGNED NULL,
+    report_date DATETIME NOT NULL,
+    run_time VARCHAR(255) NOT NULL,
+    cp_times TEXT NOT NULL,
+    player_field VARCHAR(255) NOT NULL,
+    server_text VARCHAR(255) NOT NULL,
+    irl_time_passed VARCHAR(255) NOT NULL,
+    discrepancy VARCHAR(255) NOT NULL,
+    discrepancy_ratio VARCHAR(255) NOT NULL,
+    ac_version VARCHAR(255) NOT NULL,
+    reviewed_at DATETIME NULL,
+    reviewed_by INT UNSIGNED NULL,
+    is_cheat BOOLEAN NULL,
+    PRIMARY KEY (id),
+    INDEX ac_reports_reviewed_idx (reviewed_at),
+    CONSTRAINT ac_reports_player_fk FOREIGN KEY (player_id) REFERENCES players (id),
+    CONSTRAINT ac_reports_map_fk FOREIGN KEY (map_id) REFERENCES maps (id),
+    CONSTRAINT ac_reports_record_fk FOREIGN KEY (record_id) REFERENCES records (record_id),
+    CONSTRAINT ac_reports_reviewed_by_fk FOREIGN KEY (reviewed_by) REFERENCES players (id)
+);
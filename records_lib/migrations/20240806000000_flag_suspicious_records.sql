@@ -0,0 +1,51 @@
+-- The records which failed the plausibility checks are saved, but they're pending until
+-- a moderator approves them. They don't count in the leaderboards in the meantime.
+ALTER TABLE records ADD COLUMN IF NOT EXISTS pending BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The records counting in the leaderboards, meaning neither invalidated nor pending.
+CREATE OR REPLACE VIEW valid_records AS
+SELECT *
+FROM records
+WHERE invalidated_at IS NULL AND NOT pending;
+
+CREATE OR REPLACE VIEW global_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id
+FROM (
+    SELECT r.*, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM valid_records r
+) r
+WHERE rn = 1;
+
+CREATE OR REPLACE VIEW global_event_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id, event_id, edition_id
+FROM (
+    SELECT r.*, eer.event_id, eer.edition_id, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id, eer.event_id, eer.edition_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM valid_records r
+    INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+) r
+WHERE rn = 1;
+
+-- The records flagged by the plausibility checks, with the review of a moderator.
+CREATE TABLE IF NOT EXISTS suspicious_records (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    record_id INT UNSIGNED NOT NULL,
+    reason VARCHAR(255) NOT NULL,
+    flagged_at DATETIME NOT NULL,
+    reviewed_at DATETIME NULL,
+    reviewed_by INT UNSIGNED NULL,
+    approved BOOLEAN NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY suspicious_records_record_uk (record_id),
+    INDEX suspicious_records_reviewed_idx (reviewed_at),
+    CONSTRAINT suspicious_records_record_fk FOREIGN KEY (record_id)
+        REFERENCES records (record_id) ON DELETE CASCADE,
+    CONSTRAINT suspicious_records_reviewed_by_fk FOREIGN KEY (reviewed_by) REFERENCES players (id)
+);
@@ -0,0 +1,15 @@
+-- The log of the changes made by the privileged users through the API.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    actor_id INT UNSIGNED NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    target VARCHAR(255) NOT NULL,
+    before_state TEXT NULL,
+    after_state TEXT NULL,
+    request_id VARCHAR(64) NOT NULL,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    INDEX audit_log_action_idx (action),
+    INDEX audit_log_target_idx (target),
+    CONSTRAINT audit_log_actor_fk FOREIGN KEY (actor_id) REFERENCES players (id)
+);
@@ -0,0 +1,51 @@
+-- A shadow-banned player isn't blocked by the API, but their records are hidden from the other
+-- players, and don't count in the leaderboards.
+ALTER TABLE banishments ADD COLUMN IF NOT EXISTS shadow BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The banishments that are still running, and block the player.
+CREATE OR REPLACE VIEW current_bans AS
+SELECT *
+FROM banishments
+WHERE NOT shadow
+    AND (duration IS NULL OR date_ban + INTERVAL duration SECOND > SYSDATE());
+
+-- The shadow bans that are still running.
+CREATE OR REPLACE VIEW current_shadow_bans AS
+SELECT *
+FROM banishments
+WHERE shadow
+    AND (duration IS NULL OR date_ban + INTERVAL duration SECOND > SYSDATE());
+
+-- The records counting in the leaderboards, meaning the valid records of the players who
+-- aren't shadow-banned.
+CREATE OR REPLACE VIEW ranked_records AS
+SELECT *
+FROM valid_records r
+WHERE NOT EXISTS (
+    SELECT 1 FROM current_shadow_bans sb WHERE sb.player_id = r.record_player_id
+);
+
+CREATE OR REPLACE VIEW global_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id
+FROM (
+    SELECT r.*, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM ranked_records r
+) r
+WHERE rn = 1;
+
+CREATE OR REPLACE VIEW global_event_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id, event_id, edition_id
+FROM (
+    SELECT r.*, eer.event_id, eer.edition_id, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id, eer.event_id, eer.edition_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM ranked_records r
+    INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+) r
+WHERE rn = 1;
@@ -0,0 +1,19 @@
+-- The permissions granted to the players of each role, by their name.
+-- This replaces the `role.privileges` bitmask, which is kept for compatibility.
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role_id TINYINT UNSIGNED NOT NULL,
+    permission VARCHAR(32) NOT NULL,
+    PRIMARY KEY (role_id, permission),
+    CONSTRAINT role_permissions_role_fk FOREIGN KEY (role_id) REFERENCES role (id)
+        ON DELETE CASCADE
+);
+
+INSERT IGNORE INTO role_permissions (role_id, permission) VALUES
+    (1, 'moderate_records'),
+    (2, 'manage_bans'),
+    (2, 'manage_roles'),
+    (2, 'edit_resources'),
+    (2, 'manage_events'),
+    (2, 'moderate_records'),
+    (2, 'manage_maps'),
+    (2, 'view_audit_log');
@@ -0,0 +1,27 @@
+-- The API keys issued by the admins to the third-party consumers of the API,
+-- e.g. the Discord bots or the stats websites. Only the SHA-256 hash of a key is saved.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(64) NOT NULL,
+    key_hash CHAR(64) NOT NULL,
+    created_by INT UNSIGNED NOT NULL,
+    created_at DATETIME NOT NULL,
+    revoked_at DATETIME NULL,
+    last_used_at DATETIME NULL,
+    usage_count BIGINT UNSIGNED NOT NULL DEFAULT 0,
+    PRIMARY KEY (id),
+    UNIQUE INDEX api_keys_key_hash_idx (key_hash),
+    CONSTRAINT api_keys_created_by_fk FOREIGN KEY (created_by) REFERENCES players (id)
+);
+
+-- The scopes of each API key, by their name.
+CREATE TABLE IF NOT EXISTS api_key_scopes (
+    api_key_id INT UNSIGNED NOT NULL,
+    scope VARCHAR(32) NOT NULL,
+    PRIMARY KEY (api_key_id, scope),
+    CONSTRAINT api_key_scopes_api_key_fk FOREIGN KEY (api_key_id) REFERENCES api_keys (id)
+        ON DELETE CASCADE
+);
+
+INSERT IGNORE INTO role_permissions (role_id, permission) VALUES
+    (2, 'manage_api_keys');
@@ -0,0 +1,7 @@
+-- The anticheat reports can be sent without authentication, so their player may be unknown.
+-- They're linked to their record afterwards by the time of the run, because the report and
+-- the record of a run are sent by different requests, in any order.
+ALTER TABLE ac_reports MODIFY player_id INT UNSIGNED NULL;
+ALTER TABLE ac_reports ADD COLUMN IF NOT EXISTS time INT NULL AFTER run_time;
+
+CREATE INDEX IF NOT EXISTS ac_reports_unlinked_idx ON ac_reports (player_id, map_id, record_id);
@@ -0,0 +1,27 @@
+-- The best records of each player, including the shadow-banned players. Unlike `global_records`
+-- and `global_event_records`, they're only used to show the records of a player to themselves,
+-- so a shadow-banned player still sees their own records.
+CREATE OR REPLACE VIEW player_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id
+FROM (
+    SELECT r.*, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM valid_records r
+) r
+WHERE rn = 1;
+
+CREATE OR REPLACE VIEW player_event_records AS
+SELECT record_id, record_player_id, map_id, time, respawn_count, record_date, flags, try_count,
+    event_record_id, event_id, edition_id
+FROM (
+    SELECT r.*, eer.event_id, eer.edition_id, ROW_NUMBER() OVER (
+        PARTITION BY r.map_id, r.record_player_id, eer.event_id, eer.edition_id
+        ORDER BY r.time, r.record_date
+    ) AS rn
+    FROM valid_records r
+    INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+) r
+WHERE rn = 1;
@@ -36,6 +36,43 @@ pub enum RecordsError {
     /// An error that happened when using the Redis pool.
     #[error(transparent)]
     PoolError(#[from] PoolError) = 108,
+    /// An error that happened when applying the migrations of the database.
+    #[error(transparent)]
+    Migration(#[from] sqlx::migrate::MigrateError) = 109,
+    /// A time wasn't found in the leaderboard of a map, even after its reconciliation.
+    #[error("time {1} not found in the leaderboard of the map with ID {0}")]
+    TimeNotInLeaderboard(
+        /// The ID of the map.
+        u32,
+        /// The time.
+        i32,
+    ) = 110,
+    /// The ManiaExchange API returned an unexpected response.
+    #[error("invalid response from ManiaExchange: {0}")]
+    InvalidMxResponse(
+        /// The decoding error.
+        serde_json::Error,
+    ) = 111,
+    /// A fixture of the fake ManiaExchange client couldn't be read.
+    #[error("couldn't read the ManiaExchange fixture `{0}`: {1}")]
+    MxFixture(
+        /// The path to the fixture.
+        String,
+        /// The I/O error.
+        std::io::Error,
+    ) = 112,
+    /// The lock of a mappack couldn't be acquired in time, because another update is running.
+    #[error("timed out waiting for the lock of the mappack `{0}`")]
+    MappackLocked(
+        /// The mappack ID.
+        String,
+    ) = 113,
+    /// The lock of a mappack expired during its update, and another update may have taken it.
+    #[error("lost the lock of the mappack `{0}` during its update")]
+    MappackLockLost(
+        /// The mappack ID.
+        String,
+    ) = 114,
 
     // --------
     // --- Logical errors
@@ -77,6 +114,12 @@ pub enum RecordsError {
         /// The event edition ID.
         u32,
     ) = 312,
+    /// The record with the provided ID was not found.
+    #[error("record with id `{0}` not found in database")]
+    RecordNotFound(
+        /// The record ID.
+        u32,
+    ) = 316,
 }
 
 impl RecordsError {
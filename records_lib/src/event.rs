@@ -59,6 +59,21 @@ impl<'ev, 'ed> OptEvent<'ev, 'ed> {
         }
     }
 
+    /// Returns the same fragments as [`get_view`](Self::get_view), but the view also contains
+    /// the records of the shadow-banned players.
+    ///
+    /// It must only be used to show the records of a player to themselves.
+    pub fn get_player_view(&self) -> (&'static str, &'static str) {
+        if self.0.is_some() {
+            (
+                "player_event_records",
+                "and r.event_id = ? and r.edition_id = ?",
+            )
+        } else {
+            ("player_records", "")
+        }
+    }
+
     /// Returns the fragments used to build an SQL query to retrieve records bound
     /// to a specific event.
     ///
@@ -247,7 +262,7 @@ pub async fn get_categories_by_edition_id(
 }
 
 /// Represents the medal times, in milliseconds.
-#[derive(async_graphql::SimpleObject, Clone, Copy)]
+#[derive(async_graphql::SimpleObject, Clone, Copy, Debug)]
 pub struct MedalTimes {
     /// The time of the bronze medal.
     pub bronze_time: i32,
@@ -305,6 +320,49 @@ pub async fn get_medal_times_of<E: for<'c> sqlx::Executor<'c, Database = sqlx::M
     }))
 }
 
+/// Returns the medal times of all the maps of the provided event edition, with their map ID.
+///
+/// The maps without medal times are skipped.
+///
+/// ## Parameters
+///
+/// * `event_id`: the database ID of the event.
+/// * `edition_id` the ID of the edition bound to this event.
+pub async fn get_medal_times_of_edition(
+    db: &mut MySqlConnection,
+    event_id: u32,
+    edition_id: u32,
+) -> RecordsResult<Vec<(u32, MedalTimes)>> {
+    let res = sqlx::query_as::<_, (u32, i32, i32, i32, i32)>(
+        "select eem.map_id, eem.bronze_time, eem.silver_time, eem.gold_time, eem.author_time
+        from event_edition_maps eem
+        where eem.event_id = ? and eem.edition_id = ?
+            and eem.bronze_time is not null and eem.silver_time is not null
+            and eem.gold_time is not null and eem.author_time is not null",
+    )
+    .bind(event_id)
+    .bind(edition_id)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(
+        |(map_id, bronze_time, silver_time, gold_time, champion_time)| {
+            (
+                map_id,
+                MedalTimes {
+                    bronze_time,
+                    silver_time,
+                    gold_time,
+                    champion_time,
+                },
+            )
+        },
+    )
+    .collect();
+
+    Ok(res)
+}
+
 /// Returns the admins/authors of the provided event edition.
 ///
 /// ## Parameters
@@ -0,0 +1,642 @@
+//! This module contains the [`Leaderboard`] trait, which abstracts the storage of the maps
+//! leaderboards, with its implementations.
+//!
+//! There are 2 implementations of the leaderboards:
+//!
+//! * [`RedisLeaderboard`]: the leaderboards are stored in Redis sorted sets, which are rebuilt
+//!   from the MySQL/MariaDB database when needed.
+//! * [`MySqlLeaderboard`]: the leaderboards are computed directly from the records saved in
+//!   the MySQL/MariaDB database, with window functions.
+//!
+//! The implementation used by the API is chosen by the `RECORDS_API_LEADERBOARD_BACKEND`
+//! environment variable, and is retrieved with the [`leaderboard`] function.
+//!
+//! In both implementations, the players with the same time are sorted by the date of their
+//! record, then by their ID.
+
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use deadpool_redis::redis::{self, AsyncCommands as _};
+use sqlx::MySqlConnection;
+
+use crate::{
+    error::RecordsResult, event::OptEvent, redis_key::map_key, DatabaseConnection, RedisConnection,
+};
+
+/// Represents the storage of the leaderboards of the maps.
+///
+/// A leaderboard contains the best time of each player on a map, for an optional event edition.
+/// The ranking type is the standard competition ranking (1224).
+#[allow(async_fn_in_trait)]
+pub trait Leaderboard {
+    /// Saves the time of the player on the map, if it is better than the one already saved.
+    ///
+    /// The players with the same time are sorted by record date, so the record is placed after
+    /// them. It is expected to be the most recent one of the leaderboard, which is the case of
+    /// a new record.
+    async fn insert(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+        time: i32,
+    ) -> RecordsResult<()>;
+
+    /// Returns the rank of the time on the map, or `None` if no player has this time.
+    async fn rank(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<Option<i32>>;
+
+    /// Returns the amount of players with a strictly better time than the provided one.
+    ///
+    /// Unlike the [rank](Leaderboard::rank), the time doesn't need to be in the leaderboard.
+    async fn better_count(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<i32>;
+
+    /// Returns the position of the player in the leaderboard, starting at 0, or `None` if
+    /// the player has no record on the map.
+    ///
+    /// Unlike the [rank](Leaderboard::rank), the position is unique for each player.
+    async fn position(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+    ) -> RecordsResult<Option<u32>>;
+
+    /// Returns the IDs of the players from the `start` position (inclusive) to the `end` position
+    /// (exclusive) of the leaderboard.
+    ///
+    /// If `reversed` is true, the leaderboard is read from the worst time to the best one.
+    async fn range(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        start: u32,
+        end: u32,
+        reversed: bool,
+    ) -> RecordsResult<Vec<u32>>;
+
+    /// Returns the amount of players in the leaderboard.
+    async fn count(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<i64>;
+
+    /// Rebuilds the leaderboard from the records saved in the MySQL/MariaDB database.
+    async fn rebuild(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<()>;
+}
+
+/// The kind of storage used for the leaderboards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderboardBackend {
+    /// The leaderboards are stored in Redis.
+    Redis,
+    /// The leaderboards are computed from the MySQL/MariaDB database.
+    MySql,
+}
+
+/// The error returned when parsing an unknown leaderboard backend.
+#[derive(thiserror::Error, Debug)]
+#[error("unknown leaderboard backend `{0}`, expected `redis` or `mysql`")]
+pub struct UnknownLeaderboardBackend(String);
+
+impl FromStr for LeaderboardBackend {
+    type Err = UnknownLeaderboardBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redis" => Ok(Self::Redis),
+            "mysql" => Ok(Self::MySql),
+            other => Err(UnknownLeaderboardBackend(other.to_owned())),
+        }
+    }
+}
+
+/// The amount of lower bits used by the tie-break of the score in a Redis leaderboard.
+const SCORE_TIE_BITS: u32 = 26;
+
+/// The maximum tie-break of a score.
+const MAX_SCORE_TIE: i64 = (1 << SCORE_TIE_BITS) - 1;
+
+/// Returns the lower bound (inclusive) of the scores of the provided time in a Redis leaderboard.
+fn time_score(time: i32) -> i64 {
+    (time as i64) << SCORE_TIE_BITS
+}
+
+/// Returns the time of the provided score of a Redis leaderboard.
+pub(crate) fn score_time(score: i64) -> i32 {
+    (score >> SCORE_TIE_BITS) as _
+}
+
+/// Returns the score of a record in a Redis leaderboard.
+///
+/// Redis sorts the members with the same score by their value, which is the player ID. To keep
+/// the same order as the MySQL/MariaDB database, the score is a composite of the time in the
+/// upper bits, and of a tie-break in the lower [`SCORE_TIE_BITS`] bits, which is the position
+/// of the record among the records with the same time, sorted by date.
+///
+/// The record dates can't be saved in the scores themselves, because the score stays exact
+/// in the `f64` representation of Redis only as long as it is below 2^53, so the time must be
+/// below 2^27 milliseconds (about 37 hours).
+fn record_score(time: i32, tie: i64) -> i64 {
+    time_score(time) | tie.min(MAX_SCORE_TIE)
+}
+
+/// Saves the time of a player in a Redis leaderboard, if it is better than their current one.
+///
+/// The record is placed after the other records with the same time. It returns 1 if the time
+/// was saved, 0 otherwise.
+const INSERT_SCRIPT: &str = r"
+local time_score = tonumber(ARGV[2])
+local next_time_score = tonumber(ARGV[3])
+
+local current = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if current and tonumber(current) < next_time_score then
+    return 0
+end
+
+local tie = 0
+local last = redis.call('ZREVRANGEBYSCORE', KEYS[1], '(' .. next_time_score, time_score, 'WITHSCORES', 'LIMIT', 0, 1)
+if #last > 0 then
+    tie = math.min(tonumber(last[2]) - time_score + 1, tonumber(ARGV[4]))
+end
+
+redis.call('ZADD', KEYS[1], time_score + tie, ARGV[1])
+return 1
+";
+
+/// The leaderboards stored in Redis sorted sets.
+///
+/// The score of a player is a composite of its time and of its position among the players with
+/// the same time, so they are sorted by the date of their record (see [`record_score`]).
+/// The MySQL/MariaDB connection is used to rebuild the leaderboards. A leaderboard which doesn't
+/// exist yet is rebuilt before saving a new time in it, so it is never partially filled.
+pub struct RedisLeaderboard<'a> {
+    db: &'a mut DatabaseConnection,
+}
+
+impl<'a> RedisLeaderboard<'a> {
+    /// Returns the Redis leaderboards using the provided database connection.
+    pub fn new(db: &'a mut DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+impl Leaderboard for RedisLeaderboard<'_> {
+    async fn insert(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+        time: i32,
+    ) -> RecordsResult<()> {
+        let key = map_key(map_id, event);
+
+        let exists: bool = self.db.redis_conn.exists(&key).await?;
+        if !exists {
+            self.rebuild(map_id, event).await?;
+        }
+
+        let _: i64 = redis::cmd("EVAL")
+            .arg(INSERT_SCRIPT)
+            .arg(1)
+            .arg(&key)
+            .arg(player_id)
+            .arg(time_score(time))
+            .arg(time_score(time + 1))
+            .arg(MAX_SCORE_TIE)
+            .query_async(&mut self.db.redis_conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rank(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<Option<i32>> {
+        let key = map_key(map_id, event);
+        let (min, max) = (time_score(time), time_score(time + 1));
+
+        let player_id: Vec<u32> = self
+            .db
+            .redis_conn
+            .zrangebyscore_limit(&key, min, format!("({max}"), 0, 1)
+            .await?;
+
+        if player_id.is_empty() {
+            return Ok(None);
+        }
+
+        // The amount of players with a strictly better time
+        let better_count: i32 = self
+            .db
+            .redis_conn
+            .zcount(&key, "-inf", format!("({min}"))
+            .await?;
+        Ok(Some(better_count + 1))
+    }
+
+    async fn better_count(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<i32> {
+        let count = self
+            .db
+            .redis_conn
+            .zcount(
+                map_key(map_id, event),
+                "-inf",
+                format!("({}", time_score(time)),
+            )
+            .await?;
+        Ok(count)
+    }
+
+    async fn position(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+    ) -> RecordsResult<Option<u32>> {
+        let position: Option<i64> = self
+            .db
+            .redis_conn
+            .zrank(map_key(map_id, event), player_id)
+            .await?;
+        Ok(position.map(|p| p as _))
+    }
+
+    async fn range(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        start: u32,
+        end: u32,
+        reversed: bool,
+    ) -> RecordsResult<Vec<u32>> {
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let key = map_key(map_id, event);
+        // transforms exclusive to inclusive range
+        let (start, end) = (start as isize, end as isize - 1);
+        let ids = if reversed {
+            self.db.redis_conn.zrevrange(&key, start, end).await?
+        } else {
+            self.db.redis_conn.zrange(&key, start, end).await?
+        };
+        Ok(ids)
+    }
+
+    async fn count(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<i64> {
+        let count = self
+            .db
+            .redis_conn
+            .zcount(map_key(map_id, event), "-inf", "+inf")
+            .await?;
+        Ok(count)
+    }
+
+    async fn rebuild(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<()> {
+        let scores = expected_scores(&mut self.db.mysql_conn, map_id, event).await?;
+        write_scores(&mut self.db.redis_conn, map_id, event, &scores).await?;
+        Ok(())
+    }
+}
+
+/// Returns the expected scores of the players in the Redis leaderboard of the map,
+/// from the records saved in the MySQL/MariaDB database.
+///
+/// The scores are returned sorted, in the same order as the leaderboard.
+pub(crate) async fn expected_scores(
+    mysql_conn: &mut MySqlConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<Vec<(u32, i64)>> {
+    let (view_name, and_event) = event.get_view();
+
+    let query = format!(
+        "SELECT record_player_id, time, record_date
+        FROM {view_name} r
+            WHERE map_id = ?
+                {and_event}
+            ORDER BY time, record_date, record_player_id",
+    );
+
+    let mut query = sqlx::query_as(&query).bind(map_id);
+
+    if let Some((event, edition)) = event.0 {
+        query = query.bind(event.id).bind(edition.id);
+    }
+
+    let all_map_records: Vec<(u32, i32, NaiveDateTime)> = query.fetch_all(mysql_conn).await?;
+
+    let mut scores = Vec::with_capacity(all_map_records.len());
+    let mut previous_time = None;
+    let mut tie = 0;
+    for (player_id, time, _) in all_map_records {
+        tie = if previous_time == Some(time) {
+            tie + 1
+        } else {
+            0
+        };
+        previous_time = Some(time);
+        scores.push((player_id, record_score(time, tie)));
+    }
+
+    Ok(scores)
+}
+
+/// Replaces the content of the Redis leaderboard of the map with the provided scores.
+///
+/// The replacement is atomic, so the leaderboard is never seen empty or partially filled.
+///
+/// It returns `false` if the replacement was aborted, because a key watched on the connection
+/// was modified.
+pub(crate) async fn write_scores(
+    redis_conn: &mut RedisConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+    scores: &[(u32, i64)],
+) -> RecordsResult<bool> {
+    let key = map_key(map_id, event);
+
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(&key).ignore();
+    if !scores.is_empty() {
+        let items = scores
+            .iter()
+            .map(|(player_id, score)| (*score, *player_id))
+            .collect::<Vec<_>>();
+        pipe.zadd_multiple(&key, &items).ignore();
+    }
+    let written: Option<()> = pipe.query_async(redis_conn).await?;
+
+    Ok(written.is_some())
+}
+
+/// The leaderboards computed from the records saved in the MySQL/MariaDB database.
+///
+/// The records are the source of truth, so inserting a time or rebuilding a leaderboard
+/// doesn't do anything.
+pub struct MySqlLeaderboard<'a> {
+    mysql_conn: &'a mut MySqlConnection,
+}
+
+impl<'a> MySqlLeaderboard<'a> {
+    /// Returns the MySQL/MariaDB leaderboards using the provided connection.
+    pub fn new(mysql_conn: &'a mut MySqlConnection) -> Self {
+        Self { mysql_conn }
+    }
+}
+
+impl Leaderboard for MySqlLeaderboard<'_> {
+    async fn insert(
+        &mut self,
+        _map_id: u32,
+        _event: OptEvent<'_, '_>,
+        _player_id: u32,
+        _time: i32,
+    ) -> RecordsResult<()> {
+        Ok(())
+    }
+
+    async fn rank(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<Option<i32>> {
+        let (view_name, and_event) = event.get_view();
+
+        let query = format!(
+            "SELECT CAST(rnk AS INT) FROM (
+                SELECT time, RANK() OVER (ORDER BY time) AS rnk
+                FROM {view_name} r
+                WHERE map_id = ? {and_event}
+            ) t
+            WHERE time = ?
+            LIMIT 1"
+        );
+
+        let mut query = sqlx::query_scalar(&query).bind(map_id);
+        if let Some((event, edition)) = event.0 {
+            query = query.bind(event.id).bind(edition.id);
+        }
+
+        let rank: Option<i64> = query
+            .bind(time)
+            .fetch_optional(&mut *self.mysql_conn)
+            .await?;
+        Ok(rank.map(|r| r as _))
+    }
+
+    async fn better_count(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<i32> {
+        let (view_name, and_event) = event.get_view();
+
+        let query =
+            format!("SELECT COUNT(*) FROM {view_name} r WHERE map_id = ? {and_event} AND time < ?");
+
+        let mut query = sqlx::query_scalar(&query).bind(map_id);
+        if let Some((event, edition)) = event.0 {
+            query = query.bind(event.id).bind(edition.id);
+        }
+
+        let count: i64 = query.bind(time).fetch_one(&mut *self.mysql_conn).await?;
+        Ok(count as _)
+    }
+
+    async fn position(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+    ) -> RecordsResult<Option<u32>> {
+        let (view_name, and_event) = event.get_view();
+
+        let query = format!(
+            "SELECT CAST(pos AS INT) FROM (
+                SELECT record_player_id,
+                    ROW_NUMBER() OVER (ORDER BY time, record_date, record_player_id) - 1 AS pos
+                FROM {view_name} r
+                WHERE map_id = ? {and_event}
+            ) t
+            WHERE record_player_id = ?"
+        );
+
+        let mut query = sqlx::query_scalar(&query).bind(map_id);
+        if let Some((event, edition)) = event.0 {
+            query = query.bind(event.id).bind(edition.id);
+        }
+
+        let position: Option<i64> = query
+            .bind(player_id)
+            .fetch_optional(&mut *self.mysql_conn)
+            .await?;
+        Ok(position.map(|p| p as _))
+    }
+
+    async fn range(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        start: u32,
+        end: u32,
+        reversed: bool,
+    ) -> RecordsResult<Vec<u32>> {
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let (view_name, and_event) = event.get_view();
+        let order = if reversed { "DESC" } else { "ASC" };
+
+        let query = format!(
+            "SELECT record_player_id FROM (
+                SELECT record_player_id,
+                    ROW_NUMBER() OVER (
+                        ORDER BY time {order}, record_date {order}, record_player_id {order}
+                    ) - 1 AS pos
+                FROM {view_name} r
+                WHERE map_id = ? {and_event}
+            ) t
+            WHERE pos >= ? AND pos < ?
+            ORDER BY pos"
+        );
+
+        let mut query = sqlx::query_scalar(&query).bind(map_id);
+        if let Some((event, edition)) = event.0 {
+            query = query.bind(event.id).bind(edition.id);
+        }
+
+        let ids = query
+            .bind(start)
+            .bind(end)
+            .fetch_all(&mut *self.mysql_conn)
+            .await?;
+        Ok(ids)
+    }
+
+    async fn count(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<i64> {
+        let (view_name, and_event) = event.get_view();
+
+        let query = format!("SELECT COUNT(*) FROM {view_name} r WHERE map_id = ? {and_event}");
+
+        let mut query = sqlx::query_scalar(&query).bind(map_id);
+        if let Some((event, edition)) = event.0 {
+            query = query.bind(event.id).bind(edition.id);
+        }
+
+        let count = query.fetch_one(&mut *self.mysql_conn).await?;
+        Ok(count)
+    }
+
+    async fn rebuild(&mut self, _map_id: u32, _event: OptEvent<'_, '_>) -> RecordsResult<()> {
+        Ok(())
+    }
+}
+
+/// Represents any implementation of the leaderboards.
+///
+/// This is returned by the [`leaderboard`] function.
+pub enum AnyLeaderboard<'a> {
+    /// The Redis leaderboards.
+    Redis(RedisLeaderboard<'a>),
+    /// The MySQL/MariaDB leaderboards.
+    MySql(MySqlLeaderboard<'a>),
+}
+
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),* $(,)?)) => {
+        match $self {
+            AnyLeaderboard::Redis(lb) => lb.$method($($arg),*).await,
+            AnyLeaderboard::MySql(lb) => lb.$method($($arg),*).await,
+        }
+    };
+}
+
+impl Leaderboard for AnyLeaderboard<'_> {
+    async fn insert(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+        time: i32,
+    ) -> RecordsResult<()> {
+        dispatch!(self.insert(map_id, event, player_id, time))
+    }
+
+    async fn rank(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<Option<i32>> {
+        dispatch!(self.rank(map_id, event, time))
+    }
+
+    async fn better_count(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        time: i32,
+    ) -> RecordsResult<i32> {
+        dispatch!(self.better_count(map_id, event, time))
+    }
+
+    async fn position(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        player_id: u32,
+    ) -> RecordsResult<Option<u32>> {
+        dispatch!(self.position(map_id, event, player_id))
+    }
+
+    async fn range(
+        &mut self,
+        map_id: u32,
+        event: OptEvent<'_, '_>,
+        start: u32,
+        end: u32,
+        reversed: bool,
+    ) -> RecordsResult<Vec<u32>> {
+        dispatch!(self.range(map_id, event, start, end, reversed))
+    }
+
+    async fn count(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<i64> {
+        dispatch!(self.count(map_id, event))
+    }
+
+    async fn rebuild(&mut self, map_id: u32, event: OptEvent<'_, '_>) -> RecordsResult<()> {
+        dispatch!(self.rebuild(map_id, event))
+    }
+}
+
+/// Returns the leaderboards implementation configured in the
+/// [library environment](crate::LibEnv), using the provided database connection.
+pub fn leaderboard(db: &mut DatabaseConnection) -> AnyLeaderboard<'_> {
+    match crate::env().leaderboard_backend {
+        LeaderboardBackend::Redis => AnyLeaderboard::Redis(RedisLeaderboard::new(db)),
+        LeaderboardBackend::MySql => {
+            AnyLeaderboard::MySql(MySqlLeaderboard::new(&mut db.mysql_conn))
+        }
+    }
+}
@@ -16,10 +16,16 @@ use std::time::Duration;
 mod mpdefault;
 
 pub mod error;
+pub mod leaderboard;
 pub mod mappack;
+pub mod migrations;
 pub mod models;
+pub mod moderation;
 pub mod must;
+pub mod mx;
+pub mod reconcile;
 pub mod redis_key;
+pub mod scoring;
 pub mod update_ranks;
 
 pub mod event;
@@ -35,7 +41,7 @@ pub type RedisConnection = deadpool_redis::Connection;
 
 pub use mpdefault::*;
 
-use self::error::RecordsResult;
+use self::{error::RecordsResult, leaderboard::LeaderboardBackend};
 
 /// Represents a connection to the API database, both MariaDB and Redis.
 pub struct DatabaseConnection {
@@ -108,6 +114,11 @@ mkenv::make_env! {
     ]:
 }
 
+const DEFAULT_LEADERBOARD_BACKEND: LeaderboardBackend = LeaderboardBackend::Redis;
+const DEFAULT_MX_BASE_URL: &str = "https://sm.mania.exchange";
+const DEFAULT_MX_FIXTURES_DIR: &str = "";
+const DEFAULT_MAPPACK_SNAPSHOT_RETENTION: i64 = 90;
+
 mkenv::make_env! {
 /// The environment used by this crate.
 pub LibEnv:
@@ -117,6 +128,38 @@ pub LibEnv:
         kind: parse,
         var: "RECORDS_API_MAPPACK_TTL",
         desc: "The TTL (time-to-live) of the mappacks stored in Redis",
+    },
+    /// The storage used for the leaderboards of the maps.
+    leaderboard_backend: {
+        id: LeaderboardBackendVar(LeaderboardBackend),
+        kind: parse,
+        var: "RECORDS_API_LEADERBOARD_BACKEND",
+        desc: "The storage used for the maps leaderboards (`redis` or `mysql`)",
+        default: DEFAULT_LEADERBOARD_BACKEND,
+    },
+    /// The amount of days the snapshots of the mappack standings are kept.
+    mappack_snapshot_retention: {
+        id: MappackSnapshotRetention(i64),
+        kind: parse,
+        var: "RECORDS_MAPPACK_SNAPSHOT_RETENTION",
+        desc: "The amount of days the snapshots of the mappack standings are kept (0 to keep them forever)",
+        default: DEFAULT_MAPPACK_SNAPSHOT_RETENTION,
+    },
+    /// The base URL of the ManiaExchange API.
+    mx_base_url: {
+        id: MxBaseUrl(String),
+        kind: normal,
+        var: "RECORDS_MX_BASE_URL",
+        desc: "The base URL of the ManiaExchange API (e.g. https://sm.mania.exchange)",
+        default: DEFAULT_MX_BASE_URL,
+    },
+    /// The directory of the fixtures used instead of the ManiaExchange API.
+    mx_fixtures_dir: {
+        id: MxFixturesDir(String),
+        kind: normal,
+        var: "RECORDS_MX_FIXTURES_DIR",
+        desc: "The path to a directory of ManiaExchange responses used instead of the API, for offline tests (empty to disable)",
+        default: DEFAULT_MX_FIXTURES_DIR,
     }
 }
 
@@ -1,25 +1,36 @@
 //! This module contains anything related to mappacks in this library.
 
-use std::{fmt, time::SystemTime};
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
 
-use deadpool_redis::redis::{AsyncCommands, SetExpiry, SetOptions, ToRedisArgs};
+use deadpool_redis::redis::{
+    self, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions, ToRedisArgs,
+};
+use sqlx::{Connection as _, MySqlConnection};
 
 use crate::{
-    error::RecordsResult,
-    event::OptEvent,
+    error::{RecordsError, RecordsResult},
+    event::{self, MedalTimes, OptEvent},
     models, must,
     redis_key::{
-        mappack_key, mappack_lb_key, mappack_map_last_rank, mappack_nb_map_key,
-        mappack_player_map_finished_key, mappack_player_rank_avg_key, mappack_player_ranks_key,
-        mappack_player_worst_rank_key, mappack_time_key, mappacks_key,
+        map_mappacks_key, mappack_key, mappack_lb_key, mappack_lock_key, mappack_map_last_rank,
+        mappack_nb_map_key, mappack_pending_maps_key, mappack_player_map_finished_key,
+        mappack_player_points_key, mappack_player_rank_avg_key, mappack_player_ranks_key,
+        mappack_player_worst_rank_key, mappack_scoring_key, mappack_time_key, mappacks_key,
+        staging_key,
     },
-    update_ranks::get_rank,
+    scoring::{Evaluation, MapResult, ScoringStrategy},
     DatabaseConnection, RedisConnection,
 };
 
 #[derive(Default, Clone, Debug)]
 struct Rank {
     rank: i32,
+    finished: bool,
+    time: Option<i32>,
     map_idx: usize,
 }
 
@@ -27,17 +38,36 @@ struct Rank {
 struct PlayerScore {
     player_id: u32,
     ranks: Vec<Rank>,
-    score: f64,
+    eval: Evaluation,
     maps_finished: usize,
     rank: u32,
     worst: Rank,
 }
 
+impl PlayerScore {
+    fn new(player_id: u32, ranks: Vec<Rank>) -> Self {
+        Self {
+            player_id,
+            maps_finished: ranks.iter().filter(|rank| rank.finished).count(),
+            ranks,
+            eval: Default::default(),
+            rank: 0,
+            worst: Default::default(),
+        }
+    }
+
+    /// Returns the rank average of the player, rounded to 2 decimals.
+    fn rounded_rank_avg(&self) -> f64 {
+        ((self.eval.rank_avg + f64::EPSILON) * 100.).round() / 100.
+    }
+}
+
 #[derive(Debug)]
 struct MappackMap {
     map_id: String,
     last_rank: i32,
-    records: Option<Vec<RankedRecordRow>>,
+    medal_times: Option<MedalTimes>,
+    records: Vec<RankedRecordRow>,
 }
 
 #[derive(Debug)]
@@ -48,9 +78,9 @@ struct MappackScores {
 
 #[derive(sqlx::FromRow, Debug)]
 struct RecordRow {
-    #[sqlx(flatten)]
-    record: models::Record,
-    player_id2: u32,
+    map_id: u32,
+    record_player_id: u32,
+    time: i32,
 }
 
 #[derive(Debug)]
@@ -135,11 +165,168 @@ impl AnyMappackId<'_> {
     fn get_ttl(&self) -> Option<i64> {
         self.has_ttl().then_some(crate::env().mappack_ttl)
     }
+
+    /// Returns the scoring strategy of the mappack.
+    ///
+    /// The strategy of an event is stored in its edition, and the one of a regular MX mappack
+    /// is stored in the Redis database. It falls back to the default strategy if it is missing
+    /// or invalid.
+    pub async fn get_scoring(
+        &self,
+        redis_conn: &mut RedisConnection,
+    ) -> RecordsResult<ScoringStrategy> {
+        match self {
+            Self::Event(_, edition) => Ok(edition.scoring.clone()),
+            Self::Id(_) => {
+                let scoring: Option<String> = redis_conn.get(mappack_scoring_key(*self)).await?;
+                Ok(scoring
+                    .and_then(|scoring| scoring.parse().ok())
+                    .unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// The time after which the lock of a mappack expires during the update of its pending maps,
+/// if its holder couldn't release it.
+const LOCK_TTL: Duration = Duration::from_secs(60);
+
+/// The time after which the lock of a mappack expires during a full update. The lock is renewed
+/// after each step of the update.
+const FULL_UPDATE_LOCK_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// The maximum time spent waiting for the lock of a mappack before a full update.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The delay between two attempts to acquire the lock of a mappack.
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Releases a lock, only if it is still held with the provided token.
+const UNLOCK_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// Renews a lock, only if it is still held with the provided token.
+///
+/// It returns 1 if the lock was renewed, 0 otherwise.
+const RENEW_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+";
+
+/// Tries to acquire the lock of the mappack for the provided time, and returns its token,
+/// or `None` if it is already held.
+///
+/// The updates of a mappack are serialized with this lock, so an update based on the results
+/// of the previous one doesn't overwrite the results of a concurrent update.
+async fn try_lock(
+    redis_conn: &mut RedisConnection,
+    mappack: AnyMappackId<'_>,
+    ttl: Duration,
+) -> RecordsResult<Option<String>> {
+    let token = format!(
+        "{}:{}",
+        std::process::id(),
+        SystemTime::UNIX_EPOCH
+            .elapsed()
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    let options = SetOptions::default()
+        .conditional_set(ExistenceCheck::NX)
+        .with_expiration(SetExpiry::PX(ttl.as_millis() as _));
+
+    let acquired: Option<String> = redis_conn
+        .set_options(mappack_lock_key(mappack), &token, options)
+        .await?;
+    Ok(acquired.map(|_| token))
+}
+
+/// Acquires the lock of the mappack for the provided time, and returns its token.
+///
+/// It waits for the current holder of the lock, if any, up to [`LOCK_TIMEOUT`].
+async fn lock(
+    redis_conn: &mut RedisConnection,
+    mappack: AnyMappackId<'_>,
+    ttl: Duration,
+) -> RecordsResult<String> {
+    let start = Instant::now();
+    loop {
+        if let Some(token) = try_lock(redis_conn, mappack, ttl).await? {
+            return Ok(token);
+        }
+        if start.elapsed() >= LOCK_TIMEOUT {
+            return Err(RecordsError::MappackLocked(
+                mappack.mappack_id().to_string(),
+            ));
+        }
+        tokio::time::sleep(LOCK_RETRY_DELAY).await;
+    }
+}
+
+/// Renews the lock of the mappack for the provided time, or returns an error if it was lost.
+async fn renew(
+    redis_conn: &mut RedisConnection,
+    mappack: AnyMappackId<'_>,
+    token: &str,
+    ttl: Duration,
+) -> RecordsResult<()> {
+    let renewed: i32 = redis::cmd("EVAL")
+        .arg(RENEW_SCRIPT)
+        .arg(1)
+        .arg(mappack_lock_key(mappack))
+        .arg(token)
+        .arg(ttl.as_millis() as u64)
+        .query_async(redis_conn)
+        .await?;
+
+    if renewed == 0 {
+        return Err(RecordsError::MappackLockLost(
+            mappack.mappack_id().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Releases the lock of the mappack acquired with [`lock`] or [`try_lock`].
+async fn unlock(
+    redis_conn: &mut RedisConnection,
+    mappack: AnyMappackId<'_>,
+    token: &str,
+) -> RecordsResult<()> {
+    redis::cmd("EVAL")
+        .arg(UNLOCK_SCRIPT)
+        .arg(1)
+        .arg(mappack_lock_key(mappack))
+        .arg(token)
+        .query_async::<_, ()>(redis_conn)
+        .await?;
+    Ok(())
+}
+
+/// Returns the IDs of the regular MX mappacks which contain the map.
+///
+/// Some of them may have expired since, in which case updating them does nothing.
+pub async fn mappacks_of_map(
+    redis_conn: &mut RedisConnection,
+    map_uid: &str,
+) -> RecordsResult<Vec<String>> {
+    Ok(redis_conn.smembers(map_mappacks_key(map_uid)).await?)
 }
 
 /// Calculates the scores of the players on the provided mappack, and save the results
 /// on the Redis database.
 ///
+/// The updates of the same mappack are serialized, so this waits for the running one, if any.
+/// The maps with new records queued in the meantime (see [`queue_map_update`]) are updated
+/// afterwards.
+///
 /// ## Parameters
 ///
 /// * `mappack`: the mappack.
@@ -149,6 +336,26 @@ impl AnyMappackId<'_> {
 pub async fn update_mappack(
     mappack: AnyMappackId<'_>,
     db: &mut DatabaseConnection,
+) -> RecordsResult<usize> {
+    let token = lock(&mut db.redis_conn, mappack, FULL_UPDATE_LOCK_TTL).await?;
+    let result = update_mappack_locked(mappack, db, &token).await;
+    let unlocked = unlock(&mut db.redis_conn, mappack, &token).await;
+    let total_scores = result?;
+    unlocked?;
+
+    update_pending_maps(mappack, db).await?;
+
+    Ok(total_scores)
+}
+
+/// Updates the mappack like [`update_mappack`], while its lock is already held with
+/// the provided token.
+///
+/// The lock is renewed for a full update after each step.
+async fn update_mappack_locked(
+    mappack: AnyMappackId<'_>,
+    db: &mut DatabaseConnection,
+    token: &str,
 ) -> RecordsResult<usize> {
     // Calculate the scores
     let scores = calc_scores(mappack, db).await?;
@@ -160,169 +367,729 @@ pub async fn update_mappack(
 
     let total_scores = scores.scores.len();
 
+    renew(&mut db.redis_conn, mappack, token, FULL_UPDATE_LOCK_TTL).await?;
+
     // Then save them to the Redis database for cache-handling
-    save(mappack, scores, &mut db.redis_conn).await?;
+    save(mappack, &scores, &mut db.redis_conn, token).await?;
+
+    renew(&mut db.redis_conn, mappack, token, FULL_UPDATE_LOCK_TTL).await?;
+
+    // And keep a snapshot of the standings in the MySQL/MariaDB database
+    save_snapshot(mappack, &scores, &mut db.mysql_conn).await?;
 
     // And we save it to the registered mappacks set.
     if mappack.has_ttl() {
         // The mappack has a TTL, so its member will be removed from the set when
         // attempting to retrieve its maps.
         db.redis_conn
-            .sadd(mappacks_key(), mappack.mappack_id())
+            .sadd::<_, _, ()>(mappacks_key(), mappack.mappack_id())
             .await?;
     }
 
     Ok(total_scores)
 }
 
-#[cfg_attr(feature = "tracing", tracing::instrument(skip(scores, redis_conn)))]
+/// Queues the update of the scores of the provided mappack, after a new record was saved
+/// on the provided map.
+///
+/// The update is done by [`update_pending_maps`], or by the running update of the mappack, if any.
+pub async fn queue_map_update(
+    redis_conn: &mut RedisConnection,
+    mappack: AnyMappackId<'_>,
+    map_uid: &str,
+) -> RecordsResult<()> {
+    redis_conn
+        .sadd::<_, _, ()>(mappack_pending_maps_key(mappack), map_uid)
+        .await?;
+    Ok(())
+}
+
+/// Updates the scores of the players on the provided mappack, for the maps with new records
+/// queued with [`queue_map_update`].
+///
+/// Unlike [`update_mappack`], this function only retrieves the records of the queued maps, and
+/// reuses the results of the other maps saved in the Redis database by the last update. It doesn't
+/// save any snapshot of the standings either, so [`update_mappack`] should still be called
+/// periodically.
+///
+/// If the mappack doesn't have any saved results yet, it falls back to a full update. If its
+/// scoring strategy needs the times of the players on every map, nothing is done, and the scores
+/// are only updated by [`update_mappack`].
+///
+/// This doesn't wait for the running update of the mappack, if any: the holder of its lock updates
+/// the queued maps once it releases it.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(db), err))]
+pub async fn update_pending_maps(
+    mappack: AnyMappackId<'_>,
+    db: &mut DatabaseConnection,
+) -> RecordsResult<()> {
+    // The queue is checked again after releasing the lock, because the maps queued while it was
+    // held may have failed to acquire it.
+    loop {
+        let has_pending: bool = db
+            .redis_conn
+            .exists(mappack_pending_maps_key(mappack))
+            .await?;
+        if !has_pending {
+            return Ok(());
+        }
+
+        let Some(token) = try_lock(&mut db.redis_conn, mappack, LOCK_TTL).await? else {
+            return Ok(());
+        };
+        let result = update_pending_maps_locked(mappack, db, &token).await;
+        let unlocked = unlock(&mut db.redis_conn, mappack, &token).await;
+        result?;
+        unlocked?;
+    }
+}
+
+/// Updates the mappack like [`update_pending_maps`], while its lock is already held with
+/// the provided token.
+async fn update_pending_maps_locked(
+    mappack: AnyMappackId<'_>,
+    db: &mut DatabaseConnection,
+    token: &str,
+) -> RecordsResult<()> {
+    let key = mappack_pending_maps_key(mappack);
+    let (map_uids,): (Vec<String>,) = redis::pipe()
+        .atomic()
+        .smembers(&key)
+        .del(&key)
+        .ignore()
+        .query_async(&mut db.redis_conn)
+        .await?;
+
+    for map_uid in map_uids {
+        let map = must::have_map(&mut db.mysql_conn, &map_uid).await?;
+        update_mappack_map_locked(mappack, &map, db, token).await?;
+        renew(&mut db.redis_conn, mappack, token, LOCK_TTL).await?;
+    }
+
+    Ok(())
+}
+
+/// Updates the scores of the mappack after a new record on the provided map, while its lock
+/// is already held with the provided token.
+async fn update_mappack_map_locked(
+    mappack: AnyMappackId<'_>,
+    map: &models::Map,
+    db: &mut DatabaseConnection,
+    token: &str,
+) -> RecordsResult<()> {
+    let mappack_uids: Vec<String> = db.redis_conn.smembers(mappack_key(mappack)).await?;
+
+    // The mappack may have expired, or may not contain the map
+    let Some(updated_idx) = mappack_uids.iter().position(|uid| *uid == map.game_id) else {
+        if mappack.has_ttl() {
+            db.redis_conn
+                .srem::<_, _, ()>(map_mappacks_key(&map.game_id), mappack.mappack_id())
+                .await?;
+        }
+        return Ok(());
+    };
+
+    let strategy = mappack.get_scoring(&mut db.redis_conn).await?;
+    let nb_map: Option<usize> = db.redis_conn.get(mappack_nb_map_key(mappack)).await?;
+
+    // The medal times of every map would be needed, so the scores are only updated
+    // by the full update
+    if strategy.needs_medal_times() {
+        return Ok(());
+    }
+
+    if nb_map != Some(mappack_uids.len()) {
+        update_mappack_locked(mappack, db, token).await?;
+        return Ok(());
+    }
+
+    // --- Retrieve the previous results of the other maps
+
+    let mut pipe = redis::pipe();
+    for map_uid in &mappack_uids {
+        pipe.get(mappack_map_last_rank(mappack, map_uid));
+    }
+    let last_ranks: Vec<Option<i32>> = pipe.query_async(&mut db.redis_conn).await?;
+
+    let mut maps = mappack_uids
+        .iter()
+        .zip(last_ranks)
+        .map(|(map_uid, last_rank)| MappackMap {
+            map_id: map_uid.clone(),
+            last_rank: last_rank.unwrap_or(0),
+            medal_times: None,
+            records: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    let map_indexes = mappack_uids
+        .iter()
+        .enumerate()
+        .map(|(i, map_uid)| (map_uid.as_str(), i))
+        .collect::<HashMap<_, _>>();
+
+    let player_ids: Vec<u32> = db.redis_conn.zrange(mappack_lb_key(mappack), 0, -1).await?;
+
+    let mut pipe = redis::pipe();
+    for player_id in &player_ids {
+        pipe.zrange_withscores(mappack_player_ranks_key(mappack, *player_id), 0, -1);
+    }
+    let players_ranks: Vec<Vec<(String, i32)>> = pipe.query_async(&mut db.redis_conn).await?;
+
+    let mut scores = Vec::with_capacity(player_ids.len());
+    let mut player_indexes = HashMap::with_capacity(player_ids.len());
+
+    for (player_id, ranks) in player_ids.into_iter().zip(players_ranks) {
+        let ranks = ranks
+            .into_iter()
+            .filter_map(|(map_uid, rank)| {
+                let map_idx = *map_indexes.get(map_uid.as_str())?;
+                (map_idx != updated_idx).then(|| Rank {
+                    rank,
+                    finished: rank <= maps[map_idx].last_rank,
+                    time: None,
+                    map_idx,
+                })
+            })
+            .collect();
+
+        player_indexes.insert(player_id, scores.len());
+        scores.push(PlayerScore::new(player_id, ranks));
+    }
+
+    // --- Retrieve the records of the updated map
+
+    let event = mappack.get_event();
+    let (view_name, and_event) = event.get_view();
+
+    let query = format!(
+        "SELECT r.map_id, r.record_player_id, r.time
+        FROM {view_name} r
+        INNER JOIN players p ON p.id = r.record_player_id
+        WHERE map_id = ?
+        {and_event}
+        ORDER BY time ASC",
+    );
+
+    let mut query = sqlx::query_as::<_, RecordRow>(&query).bind(map.id);
+    if let Some((event, edition)) = event.0 {
+        query = query.bind(event.id).bind(edition.id);
+    }
+
+    let records = rank_records(query.fetch_all(&mut *db.mysql_conn).await?);
+
+    let last_rank = records.iter().map(|r| r.rank).max().unwrap_or(0);
+    maps[updated_idx].last_rank = last_rank;
+
+    for record in records {
+        let player_idx = *player_indexes
+            .entry(record.record.record_player_id)
+            .or_insert_with(|| {
+                // The player didn't finish any other map of the mappack
+                let ranks = maps
+                    .iter()
+                    .enumerate()
+                    .filter(|(map_idx, _)| *map_idx != updated_idx)
+                    .map(|(map_idx, map)| Rank {
+                        rank: map.last_rank + 1,
+                        finished: false,
+                        time: None,
+                        map_idx,
+                    })
+                    .collect();
+                scores.push(PlayerScore::new(record.record.record_player_id, ranks));
+                scores.len() - 1
+            });
+
+        let player = &mut scores[player_idx];
+        player.ranks.push(Rank {
+            rank: record.rank,
+            finished: true,
+            time: Some(record.record.time),
+            map_idx: updated_idx,
+        });
+        player.maps_finished += 1;
+    }
+
+    for player in &mut scores {
+        if !player.ranks.iter().any(|rank| rank.map_idx == updated_idx) {
+            player.ranks.push(Rank {
+                rank: last_rank + 1,
+                finished: false,
+                time: None,
+                map_idx: updated_idx,
+            });
+        }
+    }
+
+    rank_players(&strategy, &maps, &mut scores);
+
+    save(
+        mappack,
+        &MappackScores { maps, scores },
+        &mut db.redis_conn,
+        token,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The expiration time (in seconds) of the staging keys of a mappack without TTL, so they don't
+/// stay in the Redis database if the save fails before they're renamed.
+const STAGING_TTL: i64 = 60 * 60;
+
+/// Saves the results of the mappack in the Redis database.
+///
+/// The results are first written to staging keys (see [`staging_key`]), then they replace
+/// the current ones atomically, with `RENAME` commands inside a `MULTI`/`EXEC` block. This way,
+/// a reader always sees either the previous complete results, or the new ones.
+///
+/// The staging keys always expire, and the keys of a mappack without TTL are persisted
+/// once renamed.
+///
+/// The current results are only replaced if the lock of the mappack is still held with
+/// the provided token, so an update whose lock expired doesn't overwrite the results of a more
+/// recent one.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(scores, redis_conn, token))
+)]
 async fn save(
     mappack: AnyMappackId<'_>,
-    scores: MappackScores,
+    scores: &MappackScores,
     redis_conn: &mut RedisConnection,
+    token: &str,
 ) -> RecordsResult<()> {
     // Here, we control all the Redis keys related to mappacks except `mappack_key`,
     // because this one is set only once.
 
+    let staging_id = SystemTime::UNIX_EPOCH
+        .elapsed()
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+
     let set_options = SetOptions::default();
     let set_options = match mappack.get_ttl() {
-        Some(ex) => {
-            // Update expiration time of the mappack's maps set by the way
-            redis_conn.expire(mappack_key(mappack), ex).await?;
-
-            set_options.with_expiration(SetExpiry::EX(ex as _))
-        }
-        None => {
-            // Persist the mappack's maps set by the way
-            redis_conn.persist(mappack_key(mappack)).await?;
-
-            set_options
-        }
+        Some(ex) => set_options.with_expiration(SetExpiry::EX(ex as _)),
+        None => set_options,
+    };
+    let staging_ttl = mappack.get_ttl().unwrap_or(STAGING_TTL);
+    let staging_options = SetOptions::default().with_expiration(SetExpiry::EX(staging_ttl as _));
+
+    // The pairs of (staging key, key) to rename at the end
+    let mut renames = Vec::new();
+    let mut stage = |key: String| {
+        let staging = staging_key(&staging_id, &key).to_string();
+        renames.push((staging.clone(), key));
+        staging
     };
 
+    let mut pipe = redis::pipe();
+
     // --- Save the number of maps of the campaign
 
-    redis_conn
-        .set_options(mappack_nb_map_key(mappack), scores.maps.len(), set_options)
-        .await?;
+    pipe.set_options(
+        stage(mappack_nb_map_key(mappack).to_string()),
+        scores.maps.len(),
+        staging_options,
+    )
+    .ignore();
 
     for map in &scores.maps {
         // --- Save the last rank on each map
 
-        redis_conn
-            .set_options(
-                mappack_map_last_rank(mappack, &map.map_id),
-                map.last_rank,
-                set_options,
-            )
-            .await?;
-
-        if !mappack.has_ttl() {
-            redis_conn
-                .persist(mappack_map_last_rank(mappack, &map.map_id))
-                .await?;
-        }
+        pipe.set_options(
+            stage(mappack_map_last_rank(mappack, &map.map_id).to_string()),
+            map.last_rank,
+            staging_options,
+        )
+        .ignore();
     }
 
-    redis_conn.del(mappack_lb_key(mappack)).await?;
-
-    for score in scores.scores {
-        redis_conn
-            .zadd(mappack_lb_key(mappack), score.player_id, score.rank)
-            .await?;
+    let lb = scores
+        .scores
+        .iter()
+        .map(|score| (score.rank, score.player_id))
+        .collect::<Vec<_>>();
+
+    let has_lb = !lb.is_empty();
+    if has_lb {
+        let staging = stage(mappack_lb_key(mappack).to_string());
+        pipe.zadd_multiple(&staging, &lb)
+            .ignore()
+            .expire(&staging, staging_ttl)
+            .ignore();
+    }
 
+    for score in &scores.scores {
         // --- Save the rank average
 
-        let rank_avg = ((score.score + f64::EPSILON) * 100.).round() / 100.;
+        pipe.set_options(
+            stage(mappack_player_rank_avg_key(mappack, score.player_id).to_string()),
+            score.rounded_rank_avg(),
+            staging_options,
+        )
+        .ignore();
+
+        // --- Save the points, if the scoring strategy gives any
 
-        redis_conn
-            .set_options(
-                mappack_player_rank_avg_key(mappack, score.player_id),
-                rank_avg,
-                set_options,
+        if let Some(points) = score.eval.points {
+            pipe.set_options(
+                stage(mappack_player_points_key(mappack, score.player_id).to_string()),
+                points,
+                staging_options,
             )
-            .await?;
+            .ignore();
+        }
 
         // --- Save the amount of finished map
 
-        redis_conn
-            .set_options(
-                mappack_player_map_finished_key(mappack, score.player_id),
-                score.maps_finished,
-                set_options,
-            )
-            .await?;
+        pipe.set_options(
+            stage(mappack_player_map_finished_key(mappack, score.player_id).to_string()),
+            score.maps_finished,
+            staging_options,
+        )
+        .ignore();
 
         // --- Save their worst rank
 
-        redis_conn
-            .set_options(
-                mappack_player_worst_rank_key(mappack, score.player_id),
-                score.worst.rank,
-                set_options,
-            )
-            .await?;
+        pipe.set_options(
+            stage(mappack_player_worst_rank_key(mappack, score.player_id).to_string()),
+            score.worst.rank,
+            staging_options,
+        )
+        .ignore();
 
-        redis_conn
-            .del(mappack_player_ranks_key(mappack, score.player_id))
-            .await?;
+        // --- Save their rank on each map
 
-        for (game_id, rank) in score
+        let ranks = score
             .ranks
-            .into_iter()
-            .map(|rank| (&scores.maps[rank.map_idx].map_id, rank.rank))
-        {
-            // --- Save their rank on each map
+            .iter()
+            .map(|rank| (rank.rank, &scores.maps[rank.map_idx].map_id))
+            .collect::<Vec<_>>();
+
+        let staging = stage(mappack_player_ranks_key(mappack, score.player_id).to_string());
+        pipe.zadd_multiple(&staging, &ranks)
+            .ignore()
+            .expire(&staging, staging_ttl)
+            .ignore();
+    }
 
-            redis_conn
-                .zadd(
-                    mappack_player_ranks_key(mappack, score.player_id),
-                    game_id,
-                    rank,
-                )
-                .await?;
+    pipe.query_async::<_, ()>(redis_conn).await?;
+
+    // Then we swap the staging keys with the current ones. The TTL of the staging keys
+    // is transferred by the rename, so it is removed if the mappack doesn't have any.
+    // The lock is watched, so the swap is aborted if it is taken in the meantime.
+
+    let lock_key = mappack_lock_key(mappack);
+    redis::cmd("WATCH")
+        .arg(&lock_key)
+        .query_async::<_, ()>(redis_conn)
+        .await?;
+    let holder: Option<String> = redis_conn.get(&lock_key).await?;
+    if holder.as_deref() != Some(token) {
+        redis::cmd("UNWATCH")
+            .query_async::<_, ()>(redis_conn)
+            .await?;
+        return Err(RecordsError::MappackLockLost(
+            mappack.mappack_id().to_string(),
+        ));
+    }
+
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    for (staging, key) in &renames {
+        pipe.rename(staging, key).ignore();
+        if mappack.get_ttl().is_none() {
+            pipe.persist(key).ignore();
         }
+    }
 
-        if let Some(ttl) = mappack.get_ttl() {
-            redis_conn
-                .expire(mappack_player_ranks_key(mappack, score.player_id), ttl)
-                .await?;
-        } else {
-            redis_conn
-                .persist(mappack_player_ranks_key(mappack, score.player_id))
-                .await?;
-            redis_conn
-                .persist(mappack_player_rank_avg_key(mappack, score.player_id))
-                .await?;
-            redis_conn
-                .persist(mappack_player_map_finished_key(mappack, score.player_id))
-                .await?;
-            redis_conn
-                .persist(mappack_player_worst_rank_key(mappack, score.player_id))
-                .await?;
+    if !has_lb {
+        pipe.del(mappack_lb_key(mappack)).ignore();
+    }
+
+    // Remove the points left by a previous scoring strategy
+    for score in scores
+        .scores
+        .iter()
+        .filter(|score| score.eval.points.is_none())
+    {
+        pipe.del(mappack_player_points_key(mappack, score.player_id))
+            .ignore();
+    }
+
+    match mappack.get_ttl() {
+        // Update expiration time of the mappack's maps set by the way
+        Some(ttl) => pipe.expire(mappack_key(mappack), ttl).ignore(),
+        // Persist the mappack's maps set by the way
+        None => pipe.persist(mappack_key(mappack)).ignore(),
+    };
+
+    // Index the regular MX mappacks by their maps, to find them after a new record.
+    // The index of a map expires with the last mappack updated with it.
+    if let Some(ttl) = mappack.get_ttl() {
+        for map in &scores.maps {
+            pipe.sadd(map_mappacks_key(&map.map_id), mappack.mappack_id())
+                .ignore()
+                .expire(map_mappacks_key(&map.map_id), ttl)
+                .ignore();
         }
     }
 
     // Set the time of the update
     if let Ok(time) = SystemTime::UNIX_EPOCH.elapsed() {
-        redis_conn
-            .set(mappack_time_key(mappack), time.as_secs())
-            .await?;
+        pipe.set_options(mappack_time_key(mappack), time.as_secs(), set_options)
+            .ignore();
     }
 
-    // Update the expiration time of the global keys
-    if let Some(ttl) = mappack.get_ttl() {
-        redis_conn.expire(mappack_time_key(mappack), ttl).await?;
-        redis_conn.expire(mappack_lb_key(mappack), ttl).await?;
-    } else {
-        redis_conn.persist(mappack_time_key(mappack)).await?;
-        redis_conn.persist(mappack_lb_key(mappack)).await?;
-        redis_conn.persist(mappack_nb_map_key(mappack)).await?;
+    // The transaction is aborted if the lock was taken since it was checked
+    let swapped: Option<()> = pipe.query_async(redis_conn).await?;
+    if swapped.is_none() {
+        return Err(RecordsError::MappackLockLost(
+            mappack.mappack_id().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// The maximum amount of players inserted in a single query when saving a snapshot.
+const SNAPSHOT_CHUNK_SIZE: usize = 1000;
+
+/// Returns whether the standings are the same as the provided ones of a snapshot,
+/// sorted by player ID.
+fn is_same_standings(snapshot: &[models::MappackSnapshotPlayer], scores: &MappackScores) -> bool {
+    let mut standings = scores
+        .scores
+        .iter()
+        .map(|score| {
+            (
+                score.player_id,
+                score.rank,
+                score.rounded_rank_avg(),
+                score.eval.points,
+                score.maps_finished as u32,
+            )
+        })
+        .collect::<Vec<_>>();
+    standings.sort_by_key(|(player_id, ..)| *player_id);
+
+    snapshot.len() == standings.len()
+        && snapshot.iter().zip(standings).all(|(saved, standing)| {
+            (
+                saved.player_id,
+                saved.rank,
+                saved.rank_avg,
+                saved.points,
+                saved.maps_finished,
+            ) == standing
+        })
+}
+
+/// Saves a snapshot of the standings of the mappack in the MySQL/MariaDB database.
+///
+/// Nothing is saved if the standings didn't change since the latest snapshot, which then
+/// still holds. The snapshots older than the retention configured in the environment
+/// are removed, except the latest one.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(scores, mysql_conn)))]
+async fn save_snapshot(
+    mappack: AnyMappackId<'_>,
+    scores: &MappackScores,
+    mysql_conn: &mut MySqlConnection,
+) -> RecordsResult<()> {
+    let mappack_id = mappack.mappack_id().to_string();
+    let mut tx = mysql_conn.begin().await?;
+
+    let latest_id: Option<u32> = sqlx::query_scalar(
+        "SELECT id FROM mappack_snapshot
+        WHERE mappack_id = ?
+        ORDER BY snapshot_date DESC, id DESC
+        LIMIT 1
+        FOR UPDATE",
+    )
+    .bind(&mappack_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let unchanged = match latest_id {
+        Some(latest_id) => {
+            let latest: Vec<models::MappackSnapshotPlayer> = sqlx::query_as(
+                "SELECT * FROM mappack_snapshot_player WHERE snapshot_id = ? ORDER BY player_id",
+            )
+            .bind(latest_id)
+            .fetch_all(&mut *tx)
+            .await?;
+            is_same_standings(&latest, scores)
+        }
+        None => false,
+    };
+
+    let snapshot_id = match latest_id {
+        Some(latest_id) if unchanged => latest_id,
+        _ => insert_snapshot(&mut tx, &mappack_id, scores).await?,
+    };
+
+    let retention = crate::env().mappack_snapshot_retention;
+    if retention > 0 {
+        sqlx::query(
+            "DELETE FROM mappack_snapshot
+            WHERE mappack_id = ? AND id <> ? AND snapshot_date < SYSDATE() - INTERVAL ? DAY",
+        )
+        .bind(&mappack_id)
+        .bind(snapshot_id)
+        .bind(retention)
+        .execute(&mut *tx)
+        .await?;
     }
 
+    tx.commit().await?;
+
     Ok(())
 }
 
+/// Inserts a new snapshot of the standings of the mappack, and returns its ID.
+async fn insert_snapshot(
+    mysql_conn: &mut MySqlConnection,
+    mappack_id: &str,
+    scores: &MappackScores,
+) -> RecordsResult<u32> {
+    let snapshot_id: u32 = sqlx::query_scalar(
+        "INSERT INTO mappack_snapshot (mappack_id, snapshot_date)
+        VALUES (?, SYSDATE()) RETURNING id",
+    )
+    .bind(mappack_id)
+    .fetch_one(&mut *mysql_conn)
+    .await?;
+
+    for chunk in scores.scores.chunks(SNAPSHOT_CHUNK_SIZE) {
+        let query = format!(
+            "INSERT INTO mappack_snapshot_player
+            (snapshot_id, player_id, `rank`, rank_avg, points, maps_finished)
+            VALUES {}",
+            chunk
+                .iter()
+                .map(|_| "(?, ?, ?, ?, ?, ?)")
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+
+        let mut query = sqlx::query(&query);
+        for score in chunk {
+            query = query
+                .bind(snapshot_id)
+                .bind(score.player_id)
+                .bind(score.rank)
+                .bind(score.rounded_rank_avg())
+                .bind(score.eval.points)
+                .bind(score.maps_finished as u32);
+        }
+
+        query.execute(&mut *mysql_conn).await?;
+    }
+
+    Ok(snapshot_id)
+}
+
+/// The standing of a player in a snapshot of a mappack, with the date of the snapshot.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct MappackStanding {
+    /// The UTC date of the snapshot.
+    pub snapshot_date: chrono::NaiveDateTime,
+    /// The standing itself.
+    #[sqlx(flatten)]
+    pub inner: models::MappackSnapshotPlayer,
+}
+
+/// Returns the standings of the player in all the snapshots of the mappack,
+/// from the oldest to the newest.
+pub async fn player_history(
+    mysql_conn: &mut MySqlConnection,
+    mappack: AnyMappackId<'_>,
+    player_id: u32,
+) -> RecordsResult<Vec<MappackStanding>> {
+    let history = sqlx::query_as(
+        "SELECT ms.snapshot_date, msp.*
+        FROM mappack_snapshot_player msp
+        INNER JOIN mappack_snapshot ms ON ms.id = msp.snapshot_id
+        WHERE ms.mappack_id = ? AND msp.player_id = ?
+        ORDER BY ms.snapshot_date ASC, ms.id ASC",
+    )
+    .bind(mappack.mappack_id().to_string())
+    .bind(player_id)
+    .fetch_all(mysql_conn)
+    .await?;
+
+    Ok(history)
+}
+
+/// Returns the standings of the mappack at the provided date, sorted by rank.
+///
+/// The standings are those of the latest snapshot made before or at the date. If there is no
+/// such snapshot, it returns an empty list.
+pub async fn standings_at(
+    mysql_conn: &mut MySqlConnection,
+    mappack: AnyMappackId<'_>,
+    date: chrono::NaiveDateTime,
+) -> RecordsResult<Vec<MappackStanding>> {
+    let standings = sqlx::query_as(
+        "SELECT ms.snapshot_date, msp.*
+        FROM mappack_snapshot_player msp
+        INNER JOIN mappack_snapshot ms ON ms.id = msp.snapshot_id
+        WHERE ms.id = (
+            SELECT id FROM mappack_snapshot
+            WHERE mappack_id = ? AND snapshot_date <= ?
+            ORDER BY snapshot_date DESC, id DESC
+            LIMIT 1
+        )
+        ORDER BY msp.`rank` ASC, msp.player_id ASC",
+    )
+    .bind(mappack.mappack_id().to_string())
+    .bind(date)
+    .fetch_all(mysql_conn)
+    .await?;
+
+    Ok(standings)
+}
+
+/// Returns the maps of the mappack from their UIDs, in the same order.
+async fn get_mappack_maps(
+    mysql_conn: &mut MySqlConnection,
+    mappack_uids: &[String],
+) -> RecordsResult<Vec<models::Map>> {
+    let query = format!(
+        "SELECT * FROM maps WHERE game_id IN ({})",
+        mappack_uids
+            .iter()
+            .map(|_| "?")
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+
+    let mut query = sqlx::query_as::<_, models::Map>(&query);
+    for map_uid in mappack_uids {
+        query = query.bind(map_uid);
+    }
+
+    let mut maps = query
+        .fetch_all(mysql_conn)
+        .await?
+        .into_iter()
+        .map(|map| (map.game_id.clone(), map))
+        .collect::<HashMap<_, _>>();
+
+    mappack_uids
+        .iter()
+        .map(|map_uid| {
+            maps.remove(map_uid)
+                .ok_or_else(|| RecordsError::MapNotFound(map_uid.clone()))
+        })
+        .collect()
+}
+
 /// Returns an `Option` because the mappack may have expired.
 #[cfg_attr(feature = "tracing", tracing::instrument(skip(db)))]
 async fn calc_scores(
@@ -332,12 +1099,11 @@ async fn calc_scores(
     let mappack_key = mappack_key(mappack);
     let mappack_uids: Vec<String> = db.redis_conn.smembers(&mappack_key).await?;
 
-    let mut maps = Vec::with_capacity(mappack_uids.len().max(5));
-
     let event = mappack.get_event();
     let (view_name, and_event) = event.get_view();
+    let strategy = mappack.get_scoring(&mut db.redis_conn).await?;
 
-    let mappack = if mappack_uids.is_empty() {
+    if mappack_uids.is_empty() {
         // If the mappack is empty, it means either that it's an invalid/unknown mappack ID,
         // or that its TTL has expired. So we remove its entry in the registered mappacks set.
         // The other keys related to this mappack were set with a TTL so they should
@@ -347,101 +1113,159 @@ async fn calc_scores(
             .srem(mappacks_key(), mappack.mappack_id())
             .await?;
         return Ok(None);
-    } else {
-        let mut out = Vec::with_capacity(mappack_uids.len());
-        for map_uid in &mappack_uids {
-            let map = must::have_map(&mut db.mysql_conn, map_uid).await?;
-            maps.push(MappackMap {
-                map_id: map.game_id.clone(),
-                last_rank: 0,
-                records: None,
-            });
-            out.push(map);
+    }
+
+    let mappack = get_mappack_maps(&mut db.mysql_conn, &mappack_uids).await?;
+
+    let mut maps = mappack
+        .iter()
+        .map(|map| MappackMap {
+            map_id: map.game_id.clone(),
+            last_rank: 0,
+            medal_times: None,
+            records: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    let map_indexes = mappack
+        .iter()
+        .enumerate()
+        .map(|(i, map)| (map.id, i))
+        .collect::<HashMap<_, _>>();
+
+    if let Some((event, edition)) = event.0.filter(|_| strategy.needs_medal_times()) {
+        for (map_id, medal_times) in
+            event::get_medal_times_of_edition(&mut db.mysql_conn, event.id, edition.id).await?
+        {
+            if let Some(&map_idx) = map_indexes.get(&map_id) {
+                maps[map_idx].medal_times = Some(medal_times);
+            }
         }
-        out
-    };
+    }
 
-    let mut scores = Vec::<PlayerScore>::with_capacity(mappack.len());
+    // We retrieve the records of all the maps at once, sorted by time on each map
+    let query = format!(
+        "SELECT r.map_id, r.record_player_id, r.time
+        FROM {view_name} r
+        INNER JOIN players p ON p.id = r.record_player_id
+        WHERE map_id IN ({params})
+        {and_event}
+        ORDER BY map_id, time ASC",
+        params = mappack.iter().map(|_| "?").collect::<Vec<_>>().join(","),
+    );
+
+    let mut query = sqlx::query_as::<_, RecordRow>(&query);
+    for map in &mappack {
+        query = query.bind(map.id);
+    }
+    if let Some((event, edition)) = event.0 {
+        query = query.bind(event.id).bind(edition.id);
+    }
 
-    for (i, map) in mappack.iter().enumerate() {
-        let query = format!(
-            "SELECT r.*, p.id as player_id2, p.login as player_login, p.name as player_name
-            FROM {view_name} r
-            INNER JOIN players p ON p.id = r.record_player_id
-            WHERE map_id = ?
-            {and_event}
-            ORDER BY time ASC",
-        );
+    let res = query.fetch_all(&mut *db.mysql_conn).await?;
 
-        let query = sqlx::query_as::<_, RecordRow>(&query).bind(map.id);
-        let query = if let Some((event, edition)) = event.0 {
-            query.bind(event.id).bind(edition.id)
-        } else {
-            query
-        };
+    let mut scores = collect_scores(&mut maps, &map_indexes, rank_records(res));
 
-        let res = query.fetch_all(&mut *db.mysql_conn).await?;
+    rank_players(&strategy, &maps, &mut scores);
 
-        let mut records = Vec::with_capacity(res.len());
+    Ok(Some(MappackScores { maps, scores }))
+}
 
-        for record in res {
-            if !scores.iter().any(|p| p.player_id == record.player_id2) {
-                scores.push(PlayerScore {
-                    player_id: record.player_id2,
-                    ranks: Vec::new(),
-                    score: 0.,
-                    maps_finished: 0,
-                    rank: 0,
-                    worst: Default::default(),
-                });
-            }
+/// Distributes the ranked records on the maps of the mappack, and returns the ranks of each player
+/// on every map, in the order of the maps.
+///
+/// The players are listed in the order of their first record when iterating the maps in
+/// the mappack order, so the players with the same score are saved in the same order whatever
+/// the order of the records. A player without a record on a map is ranked after the last player
+/// of the map.
+fn collect_scores(
+    maps: &mut [MappackMap],
+    map_indexes: &HashMap<u32, usize>,
+    records: Vec<RankedRecordRow>,
+) -> Vec<PlayerScore> {
+    for record in records {
+        maps[map_indexes[&record.record.map_id]]
+            .records
+            .push(record);
+    }
 
-            let record = RankedRecordRow {
-                rank: get_rank(db, map.id, record.record.time, event).await?,
-                record,
-            };
-            records.push(record);
-        }
+    let mut scores = Vec::<PlayerScore>::new();
+    let mut player_indexes = HashMap::<u32, usize>::new();
 
-        maps[i].records = Some(records);
+    for record in maps.iter().flat_map(|map| &map.records) {
+        player_indexes
+            .entry(record.record.record_player_id)
+            .or_insert_with(|| {
+                scores.push(PlayerScore::new(record.record.record_player_id, Vec::new()));
+                scores.len() - 1
+            });
     }
 
-    let mut map_number = 1;
-
     for (map_idx, map) in maps.iter_mut().enumerate() {
-        let records = map.records.take().unwrap();
+        let records = std::mem::take(&mut map.records);
 
         let last_rank = records.iter().map(|p| p.rank).max().unwrap_or(0);
+        map.last_rank = last_rank;
 
         for record in records {
-            let player = scores
-                .iter_mut()
-                .find(|p| p.player_id == record.record.player_id2)
-                .unwrap();
+            let player = &mut scores[player_indexes[&record.record.record_player_id]];
 
             player.ranks.push(Rank {
                 rank: record.rank,
+                finished: true,
+                time: Some(record.record.time),
                 map_idx,
             });
-            map.last_rank = last_rank;
 
             player.maps_finished += 1;
         }
 
         for player in &mut scores {
-            if player.ranks.len() < map_number {
+            if player.ranks.len() < map_idx + 1 {
                 player.ranks.push(Rank {
                     rank: last_rank + 1,
+                    finished: false,
+                    time: None,
                     map_idx,
                 });
-                map.last_rank = last_rank;
             }
         }
+    }
+
+    scores
+}
+
+/// Ranks the records, which must be sorted by map then by time, with the standard
+/// competition ranking (1224).
+fn rank_records(records: Vec<RecordRow>) -> Vec<RankedRecordRow> {
+    let mut out = Vec::with_capacity(records.len());
+
+    let mut previous: Option<(u32, i32, i32)> = None;
+    let mut position = 0;
+
+    for record in records {
+        position = match previous {
+            Some((map_id, ..)) if map_id == record.map_id => position + 1,
+            _ => 1,
+        };
+
+        let rank = match previous {
+            Some((map_id, time, rank)) if map_id == record.map_id && time == record.time => rank,
+            _ => position,
+        };
+        previous = Some((record.map_id, record.time, rank));
 
-        map_number += 1;
+        out.push(RankedRecordRow { rank, record });
     }
 
-    for player in &mut scores {
+    out
+}
+
+/// Evaluates the results of the players with the scoring strategy, then sorts and ranks them.
+///
+/// The players must have a rank on every map of the mappack.
+fn rank_players(strategy: &ScoringStrategy, maps: &[MappackMap], scores: &mut [PlayerScore]) {
+    for player in &mut *scores {
         player.ranks.sort_by(|a, b| {
             ((a.rank / maps[a.map_idx].last_rank.max(1)
                 - b.rank / maps[b.map_idx].last_rank.max(1))
@@ -456,36 +1280,189 @@ async fn calc_scores(
             .unwrap()
             .clone();
 
-        player.score = player
+        let results = player
             .ranks
             .iter()
-            .fold(0., |acc, rank| acc + rank.rank as f64)
-            / player.ranks.len() as f64;
+            .map(|rank| MapResult {
+                rank: rank.rank,
+                finished: rank.finished,
+                time: rank.time,
+                medal_times: maps[rank.map_idx].medal_times,
+            })
+            .collect::<Vec<_>>();
+
+        player.eval = strategy.evaluate(&results);
     }
 
-    scores.sort_by(|a, b| {
-        if a.maps_finished != b.maps_finished {
-            b.maps_finished.cmp(&a.maps_finished)
-        } else {
-            a.score.partial_cmp(&b.score).unwrap()
-        }
-    });
+    scores.sort_by(|a, b| strategy.compare(&a.eval, &b.eval));
 
-    let mut old_score = 0.;
-    let mut old_finishes = 0;
-    let mut old_rank = 0;
+    let mut old: Option<(Evaluation, u32)> = None;
 
     for (rank, player) in scores.iter_mut().enumerate() {
-        player.rank = if old_score.eq(&player.score) && old_finishes == player.maps_finished {
-            old_rank
-        } else {
-            rank as u32 + 1
+        player.rank = match old {
+            Some((eval, old_rank)) if strategy.compare(&eval, &player.eval).is_eq() => old_rank,
+            _ => rank as u32 + 1,
         };
 
-        old_score = player.score;
-        old_finishes = player.maps_finished;
-        old_rank = player.rank;
+        old = Some((player.eval, player.rank));
     }
+}
 
-    Ok(Some(MappackScores { maps, scores }))
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{collect_scores, rank_players, rank_records, MappackMap, PlayerScore, RecordRow};
+    use crate::scoring::ScoringStrategy;
+
+    fn row(map_id: u32, record_player_id: u32, time: i32) -> RecordRow {
+        RecordRow {
+            map_id,
+            record_player_id,
+            time,
+        }
+    }
+
+    /// Computes the scores of the mappack made of the provided map IDs, in this order,
+    /// from the records sorted by map ID then by time, like the records query.
+    fn scores(
+        strategy: &ScoringStrategy,
+        map_ids: &[u32],
+        records: Vec<RecordRow>,
+    ) -> (Vec<MappackMap>, Vec<PlayerScore>) {
+        let mut maps = map_ids
+            .iter()
+            .map(|map_id| MappackMap {
+                map_id: map_id.to_string(),
+                last_rank: 0,
+                medal_times: None,
+                records: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let map_indexes = map_ids
+            .iter()
+            .enumerate()
+            .map(|(i, map_id)| (*map_id, i))
+            .collect::<HashMap<_, _>>();
+
+        let mut scores = collect_scores(&mut maps, &map_indexes, rank_records(records));
+        rank_players(strategy, &maps, &mut scores);
+        (maps, scores)
+    }
+
+    fn ranks_of(scores: &[PlayerScore]) -> Vec<(u32, u32)> {
+        scores.iter().map(|p| (p.player_id, p.rank)).collect()
+    }
+
+    #[test]
+    fn rank_records_ties() {
+        let ranks = rank_records(vec![
+            row(1, 1, 10),
+            row(1, 2, 20),
+            row(1, 3, 20),
+            row(1, 4, 30),
+            row(1, 5, 30),
+            row(1, 6, 40),
+        ])
+        .into_iter()
+        .map(|r| (r.record.record_player_id, r.rank))
+        .collect::<Vec<_>>();
+
+        assert_eq!(ranks, [(1, 1), (2, 2), (3, 2), (4, 4), (5, 4), (6, 6)]);
+    }
+
+    #[test]
+    fn rank_records_restart_on_each_map() {
+        // The first time of the second map is the same as the last one of the first map,
+        // but they aren't tied
+        let ranks = rank_records(vec![
+            row(1, 1, 10),
+            row(1, 2, 20),
+            row(2, 3, 20),
+            row(2, 1, 20),
+            row(2, 2, 30),
+        ])
+        .into_iter()
+        .map(|r| (r.record.map_id, r.record.record_player_id, r.rank))
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            ranks,
+            [(1, 1, 1), (1, 2, 2), (2, 3, 1), (2, 1, 1), (2, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn players_in_mappack_order() {
+        // The records are sorted by map ID, but the map 20 comes first in the mappack,
+        // so its players come first
+        let (_, scores) = scores(
+            &ScoringStrategy::AverageRank,
+            &[20, 10],
+            vec![
+                row(10, 2, 10),
+                row(10, 1, 10),
+                row(20, 1, 10),
+                row(20, 2, 10),
+            ],
+        );
+
+        assert_eq!(ranks_of(&scores), [(1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn equal_scores_are_tied() {
+        let (_, scores) = scores(
+            &ScoringStrategy::AverageRank,
+            &[1, 2],
+            vec![
+                row(1, 1, 10),
+                row(1, 2, 10),
+                row(1, 3, 20),
+                row(2, 3, 5),
+                row(2, 1, 10),
+                row(2, 2, 10),
+            ],
+        );
+
+        // The players 1 and 2 have the ranks 1 and 2, the player 3 has the ranks 3 and 1
+        assert_eq!(ranks_of(&scores), [(1, 1), (2, 1), (3, 3)]);
+        assert_eq!(scores[0].rounded_rank_avg(), 1.5);
+        assert_eq!(scores[2].rounded_rank_avg(), 2.);
+        assert_eq!(scores[2].worst.rank, 3);
+    }
+
+    #[test]
+    fn missing_maps() {
+        let (maps, scores) = scores(
+            &ScoringStrategy::AverageRank,
+            &[1, 2, 3],
+            vec![
+                row(1, 1, 10),
+                row(1, 2, 20),
+                row(2, 2, 10),
+                row(2, 3, 20),
+                row(2, 4, 20),
+            ],
+        );
+
+        let last_ranks = maps.iter().map(|map| map.last_rank).collect::<Vec<_>>();
+        assert_eq!(last_ranks, [2, 2, 0]);
+
+        // The players with the most finished maps come first, whatever their rank average
+        assert_eq!(ranks_of(&scores), [(2, 1), (1, 2), (3, 3), (4, 3)]);
+
+        let player_1 = scores.iter().find(|p| p.player_id == 1).unwrap();
+        assert_eq!(player_1.maps_finished, 1);
+        let mut ranks = player_1
+            .ranks
+            .iter()
+            .map(|rank| (rank.map_idx, rank.rank, rank.finished))
+            .collect::<Vec<_>>();
+        ranks.sort_unstable();
+        // Unfinished maps count as the rank after the last player of the map
+        assert_eq!(ranks, [(0, 1, true), (1, 3, false), (2, 1, false)]);
+        assert_eq!(player_1.worst.rank, 3);
+        assert_eq!(player_1.rounded_rank_avg(), 1.67);
+    }
 }
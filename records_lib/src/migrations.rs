@@ -0,0 +1,56 @@
+//! This module contains the migrations of the MariaDB database schema.
+//!
+//! The migrations are the SQL files in the `migrations/` folder of this crate, and are embedded
+//! in the binaries at compile time. They're applied in order, and each applied migration is saved
+//! in the `_sqlx_migrations` table.
+//!
+//! See the [`migrate`] and [`status`] functions for more information.
+
+use sqlx::migrate::{Migrate as _, Migrator};
+
+use crate::{error::RecordsResult, MySqlPool};
+
+/// The embedded migrations of the database.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies all the pending migrations to the database.
+pub async fn migrate(db: &MySqlPool) -> RecordsResult<()> {
+    MIGRATOR.run(db).await?;
+    Ok(())
+}
+
+/// The status of a migration in the database.
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    /// The version of the migration, which is the timestamp prefix of its file.
+    pub version: i64,
+    /// The description of the migration.
+    pub description: String,
+    /// Whether the migration has been applied or not.
+    pub applied: bool,
+    /// Whether the migration has been modified since it has been applied.
+    pub checksum_mismatch: bool,
+}
+
+/// Returns the status of each embedded migration in the database, ordered by version.
+pub async fn status(db: &MySqlPool) -> RecordsResult<Vec<MigrationStatus>> {
+    let mut conn = db.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    let out = MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|m| {
+            let applied = applied.iter().find(|a| a.version == m.version);
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: applied.is_some(),
+                checksum_mismatch: applied.is_some_and(|a| a.checksum != m.checksum),
+            }
+        })
+        .collect();
+
+    Ok(out)
+}
@@ -11,6 +11,8 @@ use async_graphql::{Enum, SimpleObject};
 use serde::Serialize;
 use sqlx::{mysql::MySqlRow, FromRow, Row};
 
+use crate::scoring::ScoringStrategy;
+
 /// A player in the database.
 #[derive(Serialize, FromRow, Clone, Debug)]
 pub struct Player {
@@ -107,8 +109,8 @@ pub struct Role {
     pub role_name: String,
     /// The privileges of the role, coded on 8 bits.
     ///
-    /// Each bit represents a privilege. The privileges are not yet well defined, but
-    /// the players have it with all zeros and the admins have it with all ones.
+    /// This is deprecated, the permissions of each role are now saved by their name in the
+    /// `role_permissions` table.
     pub privileges: u8,
 }
 
@@ -131,6 +133,10 @@ pub struct Banishment {
     pub banished_by: Option<u32>,
     /// Equals true if the player was already banned before.
     pub was_reprieved: bool,
+    /// Equals true if it's a shadow ban.
+    ///
+    /// A shadow-banned player isn't blocked, but their records are hidden from the other players.
+    pub shadow: bool,
 }
 
 impl fmt::Display for Banishment {
@@ -324,6 +330,9 @@ pub struct EventEdition {
     /// This is used by the `global_records` view to retrieve the records of the events that don't
     /// have original maps.
     pub non_original_maps: bool,
+    /// The scoring strategy of the edition, used to calculate the scores of its mappack.
+    #[sqlx(try_from = "String")]
+    pub scoring: ScoringStrategy,
 }
 
 impl EventEdition {
@@ -430,3 +439,158 @@ pub struct ResourcesContent {
     /// The UTC date of the last edit of the page.
     pub last_modified: chrono::NaiveDateTime,
 }
+
+/// A snapshot of the standings of a mappack.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct MappackSnapshot {
+    /// The ID of the snapshot.
+    pub id: u32,
+    /// The mappack ID, as displayed by [`AnyMappackId::mappack_id`][1].
+    ///
+    /// [1]: crate::mappack::AnyMappackId::mappack_id
+    pub mappack_id: String,
+    /// The UTC date of the snapshot.
+    pub snapshot_date: chrono::NaiveDateTime,
+}
+
+/// The standing of a player in a snapshot of a mappack.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct MappackSnapshotPlayer {
+    /// The ID of the snapshot.
+    pub snapshot_id: u32,
+    /// The ID of the player.
+    pub player_id: u32,
+    /// The rank of the player in the mappack.
+    pub rank: u32,
+    /// The rank average of the player.
+    pub rank_avg: f64,
+    /// The total points of the player, if the scoring strategy of the mappack gave points.
+    pub points: Option<f64>,
+    /// The amount of maps finished by the player.
+    pub maps_finished: u32,
+}
+
+/// A world record in the history of the world records of a map.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct WorldRecord {
+    /// The ID of the world record in the history.
+    pub id: u32,
+    /// The ID of the map.
+    pub map_id: u32,
+    /// The ID of the event, if the world record was made in an event context.
+    pub event_id: Option<u32>,
+    /// The ID of the event edition, if the world record was made in an event context.
+    pub edition_id: Option<u32>,
+    /// The ID of the record.
+    pub record_id: u32,
+    /// The ID of the player who made the world record.
+    pub player_id: u32,
+    /// The time in milliseconds of the world record.
+    pub time: i32,
+    /// The UTC date of the world record.
+    pub record_date: chrono::NaiveDateTime,
+}
+
+/// A report sent by the anticheat of the Obstacle gamemode.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct AcReport {
+    /// The ID of the report.
+    pub id: u32,
+    /// The ID of the reported player, if the report was authenticated.
+    pub player_id: Option<u32>,
+    /// The ID of the map.
+    pub map_id: u32,
+    /// The ID of the record of the run, if it could be resolved.
+    pub record_id: Option<u32>,
+    /// The UTC date of the report.
+    pub report_date: chrono::NaiveDateTime,
+    /// The time of the run, as sent by the anticheat.
+    pub run_time: String,
+    /// The time of the run in milliseconds, if it could be parsed.
+    pub time: Option<i32>,
+    /// The checkpoint times of the run.
+    pub cp_times: String,
+    /// The player field, as displayed in-game.
+    pub player_field: String,
+    /// The server on which the run was made.
+    pub server_text: String,
+    /// The real time elapsed during the run.
+    pub irl_time_passed: String,
+    /// The discrepancy between the run time and the real time elapsed.
+    pub discrepancy: String,
+    /// The ratio of the discrepancy.
+    pub discrepancy_ratio: String,
+    /// The version of the anticheat.
+    pub ac_version: String,
+    /// The UTC date of the review, if the report was reviewed.
+    pub reviewed_at: Option<chrono::NaiveDateTime>,
+    /// The ID of the moderator who reviewed the report.
+    pub reviewed_by: Option<u32>,
+    /// Whether the run was confirmed as cheated, if the report was reviewed.
+    pub is_cheat: Option<bool>,
+}
+
+/// A record flagged by the plausibility checks of the API.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct SuspiciousRecord {
+    /// The ID of the flag.
+    pub id: u32,
+    /// The ID of the record.
+    pub record_id: u32,
+    /// The reason of the flag.
+    pub reason: String,
+    /// The UTC date of the flag.
+    pub flagged_at: chrono::NaiveDateTime,
+    /// The UTC date of the review, if the record was reviewed.
+    pub reviewed_at: Option<chrono::NaiveDateTime>,
+    /// The ID of the moderator who reviewed the record.
+    pub reviewed_by: Option<u32>,
+    /// Whether the record was approved, if it was reviewed.
+    pub approved: Option<bool>,
+}
+
+/// An entry of the audit log, saved when a privileged user changes something through the API.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct AuditLogEntry {
+    /// The ID of the entry.
+    pub id: u32,
+    /// The ID of the player who made the change.
+    pub actor_id: u32,
+    /// The name of the action, in snake case.
+    pub action: String,
+    /// The target of the action, e.g. `player:<login>` or `record:<id>`.
+    pub target: String,
+    /// The JSON state of the target before the change, if any.
+    pub before_state: Option<String>,
+    /// The JSON state of the target after the change, if any.
+    pub after_state: Option<String>,
+    /// The ID of the request that made the change.
+    pub request_id: String,
+    /// The UTC date of the change.
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// An API key issued by an admin to a third-party consumer of the API.
+///
+/// Only the SHA-256 hash of the key is saved. The scopes of the key are saved
+/// in the `api_key_scopes` table.
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct ApiKey {
+    /// The ID of the key.
+    pub id: u32,
+    /// The name of the key, describing its consumer.
+    pub name: String,
+    /// The SHA-256 hash of the key, in hexadecimal.
+    #[serde(skip)]
+    pub key_hash: String,
+    /// The ID of the admin who issued the key.
+    pub created_by: u32,
+    /// The UTC date when the key was issued.
+    pub created_at: chrono::NaiveDateTime,
+    /// The UTC date when the key was revoked, if it was.
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    /// The UTC date when the key was last used.
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+    /// The amount of requests made with the key.
+    pub usage_count: u64,
+}
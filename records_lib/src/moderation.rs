@@ -0,0 +1,365 @@
+//! This module contains the moderation of the records.
+//!
+//! A record can be invalidated by a moderator, e.g. when it is confirmed as cheated. An invalidated
+//! record is kept in the database (with its `invalidated_at` column set), but it doesn't count in
+//! the leaderboards anymore.
+//!
+//! The records flagged as suspicious when they're saved are pending (with their `pending` column
+//! set) until a moderator approves them, or invalidates them.
+//!
+//! The moderation of a record only changes the records in the MySQL/MariaDB database, so it can
+//! be done in a transaction, e.g. with its entry in the audit log. Once it is committed,
+//! [`recompute`] must be called to update the leaderboards of its maps, alone and in the event
+//! editions it was saved for, their history of world records, and the scores of the mappacks
+//! containing these maps.
+//!
+//! The records of the shadow-banned players are hidden from the other players, and don't count
+//! in the leaderboards either (see [`recompute_player`]).
+//!
+//! When a record is saved for an event, it may be cloned for the original map of the event map
+//! (see [`Record::event_record_id`][1]). The clones represent the same run, so they're always
+//! moderated together.
+//!
+//! [1]: crate::models::Record::event_record_id
+
+use std::collections::BTreeSet;
+
+use crate::{
+    error::{RecordsError, RecordsResult},
+    event::OptEvent,
+    mappack::{mappacks_of_map, update_mappack, AnyMappackId},
+    models, must,
+    reconcile::reconcile_map,
+    DatabaseConnection,
+};
+
+/// Returns the IDs of the records representing the same run as the provided record,
+/// including itself.
+///
+/// These are the record it was cloned from, if any, and all its clones, transitively.
+pub async fn linked_records(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    record_id: u32,
+) -> RecordsResult<Vec<u32>> {
+    // We first retrieve the source record of the run...
+    let source_id: Option<u32> = sqlx::query_scalar(
+        "WITH RECURSIVE sources AS (
+            SELECT record_id, event_record_id FROM records WHERE record_id = ?
+            UNION ALL
+            SELECT r.record_id, r.event_record_id FROM records r
+            INNER JOIN sources s ON r.record_id = s.event_record_id
+        )
+        SELECT record_id FROM sources WHERE event_record_id IS NULL",
+    )
+    .bind(record_id)
+    .fetch_optional(&mut *mysql_conn)
+    .await?;
+
+    let Some(source_id) = source_id else {
+        return Err(RecordsError::RecordNotFound(record_id));
+    };
+
+    // ...then all of its clones
+    let ids = sqlx::query_scalar(
+        "WITH RECURSIVE clones AS (
+            SELECT record_id FROM records WHERE record_id = ?
+            UNION ALL
+            SELECT r.record_id FROM records r
+            INNER JOIN clones c ON r.event_record_id = c.record_id
+        )
+        SELECT record_id FROM clones",
+    )
+    .bind(source_id)
+    .fetch_all(mysql_conn)
+    .await?;
+
+    Ok(ids)
+}
+
+fn params_list(ids: &[u32]) -> String {
+    ids.iter().map(|_| "?").collect::<Vec<_>>().join(",")
+}
+
+/// Links the anticheat reports of the player on the map to the records of the same runs.
+///
+/// The report and the record of a run are sent by different requests, in any order,
+/// so this is called after saving either of them. A report is linked to the latest record
+/// with the same time, saved within a few minutes of the report.
+pub async fn link_ac_reports(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    player_id: u32,
+    map_id: u32,
+) -> RecordsResult<()> {
+    sqlx::query(
+        "UPDATE ac_reports a
+        SET a.record_id = (
+            SELECT r.record_id FROM records r
+            WHERE r.record_player_id = a.player_id AND r.map_id = a.map_id AND r.time = a.time
+                AND r.event_record_id IS NULL
+                AND r.record_date BETWEEN a.report_date - INTERVAL 5 MINUTE
+                    AND a.report_date + INTERVAL 5 MINUTE
+            ORDER BY r.record_date DESC
+            LIMIT 1
+        )
+        WHERE a.player_id = ? AND a.map_id = ? AND a.record_id IS NULL AND a.time IS NOT NULL",
+    )
+    .bind(player_id)
+    .bind(map_id)
+    .execute(mysql_conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Repairs the leaderboards of the maps of the provided records, alone and in each event
+/// edition they were saved for, then updates the scores of the mappacks containing these maps.
+///
+/// This must be called with the IDs returned by the moderation of records, once it is committed.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(db)))]
+pub async fn recompute(db: &mut DatabaseConnection, record_ids: &[u32]) -> RecordsResult<()> {
+    if record_ids.is_empty() {
+        return Ok(());
+    }
+
+    let params = params_list(record_ids);
+
+    let query = format!(
+        "SELECT DISTINCT m.* FROM maps m
+        INNER JOIN records r ON r.map_id = m.id
+        WHERE r.record_id IN ({params})"
+    );
+    let mut query = sqlx::query_as(&query);
+    for id in record_ids {
+        query = query.bind(id);
+    }
+    let maps = query.fetch_all(&mut *db.mysql_conn).await?;
+
+    let query = format!(
+        "SELECT DISTINCT r.map_id, eer.event_id, eer.edition_id
+        FROM records r
+        INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+        WHERE r.record_id IN ({params})"
+    );
+    let mut query = sqlx::query_as(&query);
+    for id in record_ids {
+        query = query.bind(id);
+    }
+    let event_maps = query.fetch_all(&mut *db.mysql_conn).await?;
+
+    recompute_maps(db, &maps, event_maps).await
+}
+
+/// Repairs the leaderboards of the maps where the provided player has records, alone and in each
+/// event edition they were saved for, then updates the scores of the mappacks containing
+/// these maps.
+///
+/// This must be called when the player is shadow-banned or when their shadow ban is lifted.
+/// The expired shadow bans are handled when the leaderboards are checked
+/// (see [`update_leaderboard`](crate::update_ranks::update_leaderboard)).
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(db)))]
+pub async fn recompute_player(db: &mut DatabaseConnection, player_id: u32) -> RecordsResult<()> {
+    let maps = sqlx::query_as(
+        "SELECT DISTINCT m.* FROM maps m
+        INNER JOIN records r ON r.map_id = m.id
+        WHERE r.record_player_id = ?",
+    )
+    .bind(player_id)
+    .fetch_all(&mut *db.mysql_conn)
+    .await?;
+
+    let event_maps = sqlx::query_as(
+        "SELECT DISTINCT r.map_id, eer.event_id, eer.edition_id
+        FROM records r
+        INNER JOIN event_edition_records eer ON eer.record_id = r.record_id
+        WHERE r.record_player_id = ?",
+    )
+    .bind(player_id)
+    .fetch_all(&mut *db.mysql_conn)
+    .await?;
+
+    recompute_maps(db, &maps, event_maps).await
+}
+
+/// Repairs the leaderboards of the provided maps, then updates the scores of the mappacks
+/// containing them.
+///
+/// The scores of the mappacks are fully recalculated, because a record may have been removed
+/// from the leaderboards.
+async fn recompute_maps(
+    db: &mut DatabaseConnection,
+    maps: &[models::Map],
+    event_maps: Vec<(u32, u32, u32)>,
+) -> RecordsResult<()> {
+    let mut mappacks = BTreeSet::new();
+
+    for map in maps {
+        reconcile_map(db, map.id, Default::default()).await?;
+        repair_world_records(&mut db.mysql_conn, map.id, Default::default()).await?;
+        mappacks.extend(mappacks_of_map(&mut db.redis_conn, &map.game_id).await?);
+    }
+
+    for mappack_id in &mappacks {
+        update_mappack(AnyMappackId::Id(mappack_id), db).await?;
+    }
+
+    let mut editions = BTreeSet::new();
+
+    for (map_id, event_id, edition_id) in event_maps {
+        let (event, edition) =
+            must::have_event_edition_from_ids(&mut db.mysql_conn, event_id, edition_id).await?;
+        reconcile_map(db, map_id, OptEvent::new(&event, &edition)).await?;
+        repair_world_records(&mut db.mysql_conn, map_id, OptEvent::new(&event, &edition)).await?;
+        editions.insert((event_id, edition_id));
+    }
+
+    for (event_id, edition_id) in editions {
+        let (event, edition) =
+            must::have_event_edition_from_ids(&mut db.mysql_conn, event_id, edition_id).await?;
+        update_mappack(AnyMappackId::Event(&event, &edition), db).await?;
+    }
+
+    Ok(())
+}
+
+/// Repairs the history of the world records of the map, alone or in an event edition.
+///
+/// The world records which don't count in the leaderboard anymore (e.g. invalidated) are removed
+/// from the history, and the best record is added back if it beats the last world record left,
+/// e.g. when it was pending until now.
+async fn repair_world_records(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<()> {
+    let (event_id, edition_id) = event
+        .0
+        .map(|(event, edition)| (event.id, edition.id))
+        .unzip();
+
+    sqlx::query(
+        "DELETE FROM world_records
+        WHERE map_id = ? AND event_id <=> ? AND edition_id <=> ?
+            AND record_id NOT IN (SELECT record_id FROM ranked_records)",
+    )
+    .bind(map_id)
+    .bind(event_id)
+    .bind(edition_id)
+    .execute(&mut *mysql_conn)
+    .await?;
+
+    let last_time: Option<i32> = sqlx::query_scalar(
+        "SELECT time FROM world_records
+        WHERE map_id = ? AND event_id <=> ? AND edition_id <=> ?
+        ORDER BY id DESC LIMIT 1",
+    )
+    .bind(map_id)
+    .bind(event_id)
+    .bind(edition_id)
+    .fetch_optional(&mut *mysql_conn)
+    .await?;
+
+    let (join_event, and_event) = event.get_join();
+    let query = format!(
+        "SELECT r.* FROM ranked_records r
+        {join_event}
+        WHERE r.map_id = ?
+        {and_event}
+        ORDER BY r.time, r.record_date LIMIT 1"
+    );
+    let mut query = sqlx::query_as::<_, models::Record>(&query).bind(map_id);
+    if let Some((event, edition)) = event.0 {
+        query = query.bind(event.id).bind(edition.id);
+    }
+    let best = query.fetch_optional(&mut *mysql_conn).await?;
+
+    let Some(best) = best.filter(|best| last_time.is_none_or(|time| best.time < time)) else {
+        return Ok(());
+    };
+
+    sqlx::query(
+        "INSERT INTO world_records
+        (map_id, event_id, edition_id, record_id, player_id, time, record_date)
+        VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(map_id)
+    .bind(event_id)
+    .bind(edition_id)
+    .bind(best.record_id)
+    .bind(best.record_player_id)
+    .bind(best.time)
+    .bind(best.record_date)
+    .execute(mysql_conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Sets the moderation columns of the provided records.
+async fn set_records(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    set: &str,
+    record_ids: &[u32],
+) -> RecordsResult<()> {
+    let query = format!(
+        "UPDATE records SET {set} WHERE record_id IN ({})",
+        params_list(record_ids)
+    );
+    let mut query = sqlx::query(&query);
+    for id in record_ids {
+        query = query.bind(id);
+    }
+    query.execute(mysql_conn).await?;
+    Ok(())
+}
+
+/// Invalidates the record with the provided ID, with all its linked records
+/// (see [`linked_records`]).
+///
+/// It returns the IDs of the invalidated records, to [`recompute`] the affected leaderboards
+/// and mappacks.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(mysql_conn)))]
+pub async fn invalidate_record(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    record_id: u32,
+) -> RecordsResult<Vec<u32>> {
+    let ids = linked_records(mysql_conn, record_id).await?;
+    set_records(
+        mysql_conn,
+        "invalidated_at = COALESCE(invalidated_at, SYSDATE())",
+        &ids,
+    )
+    .await?;
+    Ok(ids)
+}
+
+/// Restores the invalidated record with the provided ID, with all its linked records
+/// (see [`linked_records`]).
+///
+/// If the record was pending before being invalidated, it is still pending after.
+///
+/// It returns the IDs of the restored records, to [`recompute`] the affected leaderboards
+/// and mappacks.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(mysql_conn)))]
+pub async fn restore_record(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    record_id: u32,
+) -> RecordsResult<Vec<u32>> {
+    let ids = linked_records(mysql_conn, record_id).await?;
+    set_records(mysql_conn, "invalidated_at = NULL", &ids).await?;
+    Ok(ids)
+}
+
+/// Approves the pending record with the provided ID, with all its linked records
+/// (see [`linked_records`]).
+///
+/// It returns the IDs of the approved records, to [`recompute`] the affected leaderboards
+/// and mappacks.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(mysql_conn)))]
+pub async fn approve_record(
+    mysql_conn: &mut sqlx::MySqlConnection,
+    record_id: u32,
+) -> RecordsResult<Vec<u32>> {
+    let ids = linked_records(mysql_conn, record_id).await?;
+    set_records(mysql_conn, "pending = FALSE", &ids).await?;
+    Ok(ids)
+}
@@ -0,0 +1,333 @@
+//! This module contains the client of the ManiaExchange (MX) API.
+//!
+//! Every request to MX goes through the [`MxClient`] type. It handles:
+//!
+//! * The base URL of the API, configurable with the [library environment](crate::LibEnv).
+//! * The cache of the responses in the Redis database (see [`MxClient::with_cache`]).
+//! * The rate limit of the requests (see [`MIN_REQUEST_INTERVAL`]).
+//! * The retries of the requests which failed because of the network or of MX
+//!   (see [`MAX_RETRIES`]).
+//!
+//! For offline tests, the client can also read the responses from fixture files instead of
+//! sending requests (see [`MxClient::from_fixtures`]).
+//! The fixtures used by the tests of this crate are in the `tests/fixtures/mx` folder.
+
+use std::{io, path::PathBuf, sync::Arc, time::Duration};
+
+use deadpool_redis::redis::AsyncCommands as _;
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{
+    error::{RecordsError, RecordsResult},
+    redis_key::mx_cache_key,
+    RedisPool,
+};
+
+/// The user agent sent with the requests, to identify us to MX.
+const USER_AGENT: &str = "obstacle (discord @ahmadbky)";
+
+/// The minimum interval between 2 requests sent by a client and its clones.
+pub const MIN_REQUEST_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The maximum amount of times a failed request is retried.
+///
+/// A request is retried if it couldn't be sent, or if MX returned a `429 Too Many Requests`
+/// or a server error.
+pub const MAX_RETRIES: u32 = 3;
+
+/// The delay before the first retry of a request, doubled at each retry.
+///
+/// It is overridden by the `Retry-After` header of the response, if any.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// The time-to-live of the responses cached in the Redis database (in seconds).
+pub const CACHE_TTL: u64 = 60 * 10;
+
+/// The maximum amount of maps that can be requested at once with [`MxClient::maps_info`].
+pub const MAX_MAPS_INFO: usize = 25;
+
+/// A map returned by the MX API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MxMap {
+    /// The MX ID of the map.
+    #[serde(rename = "TrackID")]
+    pub mx_id: i64,
+    /// The UID of the map.
+    #[serde(rename = "TrackUID")]
+    pub map_uid: String,
+    /// The name of the map.
+    #[serde(rename = "GbxMapName")]
+    pub name: String,
+    /// The login of the author.
+    #[serde(rename = "AuthorLogin")]
+    pub author_login: String,
+}
+
+/// A map of a mappack returned by the MX API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MxMappackMap {
+    /// The MX ID of the map.
+    #[serde(rename = "MapID")]
+    pub mx_id: i64,
+    /// The UID of the map.
+    #[serde(rename = "TrackUID")]
+    pub map_uid: String,
+    /// The name of the map.
+    #[serde(rename = "GbxMapName")]
+    pub name: String,
+    /// The login of the author.
+    #[serde(rename = "AuthorLogin")]
+    pub author_login: String,
+}
+
+/// The information about a mappack returned by the MX API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MxMappackInfo {
+    /// The username of the creator of the mappack.
+    #[serde(rename = "Username")]
+    pub username: String,
+    /// The name of the mappack.
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// The creation date of the mappack, as returned by MX.
+    #[serde(rename = "Created")]
+    pub created: String,
+}
+
+enum Backend {
+    Http {
+        client: reqwest::Client,
+        base_url: String,
+        /// The instant when the next request can be sent.
+        next_request: Mutex<Instant>,
+    },
+    Fixtures {
+        dir: PathBuf,
+    },
+}
+
+/// The client of the MX API.
+///
+/// The clones of a client share the same rate limit.
+#[derive(Clone)]
+pub struct MxClient {
+    backend: Arc<Backend>,
+    cache: Option<RedisPool>,
+}
+
+fn decode<T: DeserializeOwned>(body: &str) -> RecordsResult<T> {
+    serde_json::from_str(body).map_err(RecordsError::InvalidMxResponse)
+}
+
+fn retry_after(res: &reqwest::Response) -> Option<Duration> {
+    res.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse().ok())
+        .map(Duration::from_secs)
+}
+
+impl MxClient {
+    /// Creates a client sending its requests to the MX API at the provided base URL
+    /// (e.g. `https://sm.mania.exchange`).
+    pub fn new(client: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Self {
+            backend: Arc::new(Backend::Http {
+                client,
+                base_url: base_url.into().trim_end_matches('/').to_owned(),
+                next_request: Mutex::new(Instant::now()),
+            }),
+            cache: None,
+        }
+    }
+
+    /// Creates a fake client, reading the responses from the JSON fixtures of the provided
+    /// directory.
+    ///
+    /// The fixtures are laid out as follows:
+    ///
+    /// * `mappacks/{mappack_id}/maps.json` for [`Self::mappack_maps`].
+    /// * `mappacks/{mappack_id}/info.json` for [`Self::mappack_info`].
+    /// * `maps/{mx_id}.json` for each map returned by [`Self::maps_info`]. Like with MX,
+    ///   the missing maps are skipped.
+    pub fn from_fixtures(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            backend: Arc::new(Backend::Fixtures { dir: dir.into() }),
+            cache: None,
+        }
+    }
+
+    /// Creates the client configured by the [library environment](crate::LibEnv).
+    ///
+    /// If a fixtures directory is set, it returns a fake client (see [`Self::from_fixtures`]).
+    pub fn from_env(client: reqwest::Client) -> Self {
+        let env = crate::env();
+        if env.mx_fixtures_dir.is_empty() {
+            Self::new(client, &env.mx_base_url)
+        } else {
+            Self::from_fixtures(&env.mx_fixtures_dir)
+        }
+    }
+
+    /// Caches the responses of the client in the provided Redis database,
+    /// for [`CACHE_TTL`] seconds.
+    ///
+    /// The requests of the secret mappacks aren't cached.
+    pub fn with_cache(mut self, redis_pool: RedisPool) -> Self {
+        self.cache = Some(redis_pool);
+        self
+    }
+
+    /// Returns the maps of the mappack with the provided MX ID.
+    ///
+    /// The secret of the mappack must be provided if it is private.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, secret)))]
+    pub async fn mappack_maps(
+        &self,
+        mappack_id: u32,
+        secret: Option<&str>,
+    ) -> RecordsResult<Vec<MxMappackMap>> {
+        let path = format!("/api/mappack/get_mappack_tracks/{mappack_id}");
+        let fixture = format!("mappacks/{mappack_id}/maps.json");
+        self.get(&path, secret, &fixture).await
+    }
+
+    /// Returns the information about the mappack with the provided MX ID.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn mappack_info(&self, mappack_id: u32) -> RecordsResult<MxMappackInfo> {
+        let path = format!("/api/mappack/get_info/{mappack_id}");
+        let fixture = format!("mappacks/{mappack_id}/info.json");
+        self.get(&path, None, &fixture).await
+    }
+
+    /// Returns the maps with the provided MX IDs.
+    ///
+    /// At most [`MAX_MAPS_INFO`] maps can be requested at once. The unknown maps are skipped.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn maps_info(&self, mx_ids: &[i64]) -> RecordsResult<Vec<MxMap>> {
+        if let Backend::Fixtures { dir } = &*self.backend {
+            let mut maps = Vec::with_capacity(mx_ids.len());
+            for mx_id in mx_ids {
+                match read_fixture(dir, &format!("maps/{mx_id}.json")).await {
+                    Ok(body) => maps.push(decode(&body)?),
+                    Err(RecordsError::MxFixture(_, e)) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            return Ok(maps);
+        }
+
+        let ids = mx_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let path = format!("/api/maps/get_map_info/multi/{ids}");
+        self.get(&path, None, "").await
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        secret: Option<&str>,
+        fixture: &str,
+    ) -> RecordsResult<T> {
+        let (client, base_url, next_request) = match &*self.backend {
+            Backend::Http {
+                client,
+                base_url,
+                next_request,
+            } => (client, base_url, next_request),
+            Backend::Fixtures { dir } => return decode(&read_fixture(dir, fixture).await?),
+        };
+
+        let cache = match &self.cache {
+            Some(pool) if secret.is_none() => Some(pool),
+            _ => None,
+        };
+
+        if let Some(pool) = cache {
+            let mut redis_conn = pool.get().await?;
+            let cached: Option<String> = redis_conn.get(mx_cache_key(path)).await?;
+            if let Some(body) = cached {
+                return decode(&body);
+            }
+        }
+
+        let url = format!("{base_url}{path}");
+        let body = send(client, next_request, &url, secret).await?;
+        // The response is decoded before being cached, to avoid caching an invalid response
+        let out = decode(&body)?;
+
+        if let Some(pool) = cache {
+            let mut redis_conn = pool.get().await?;
+            redis_conn
+                .set_ex::<_, _, ()>(mx_cache_key(path), body, CACHE_TTL)
+                .await?;
+        }
+
+        Ok(out)
+    }
+}
+
+async fn read_fixture(dir: &std::path::Path, fixture: &str) -> RecordsResult<String> {
+    let path = dir.join(fixture);
+    tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| RecordsError::MxFixture(path.display().to_string(), e))
+}
+
+/// Waits for the rate limit to allow a new request.
+async fn wait_turn(next_request: &Mutex<Instant>) {
+    let at = {
+        let mut next_request = next_request.lock().await;
+        let at = (*next_request).max(Instant::now());
+        *next_request = at + MIN_REQUEST_INTERVAL;
+        at
+    };
+    tokio::time::sleep_until(at).await;
+}
+
+/// Sends a GET request to MX and returns the body of the response, retrying it on failure.
+async fn send(
+    client: &reqwest::Client,
+    next_request: &Mutex<Instant>,
+    url: &str,
+    secret: Option<&str>,
+) -> RecordsResult<String> {
+    let mut attempt = 0;
+
+    loop {
+        wait_turn(next_request).await;
+
+        let mut req = client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, USER_AGENT);
+        if let Some(secret) = secret {
+            req = req.query(&[("secret", secret)]);
+        }
+
+        let delay = match req.send().await {
+            Ok(res)
+                if attempt < MAX_RETRIES
+                    && (res.status() == StatusCode::TOO_MANY_REQUESTS
+                        || res.status().is_server_error()) =>
+            {
+                retry_after(&res)
+            }
+            Ok(res) => return Ok(res.error_for_status()?.text().await?),
+            Err(e) if attempt < MAX_RETRIES && (e.is_connect() || e.is_timeout()) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let delay = delay.unwrap_or(RETRY_BASE_DELAY * 2u32.pow(attempt));
+        attempt += 1;
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Request to MX failed, retrying in {delay:?} ({attempt}/{MAX_RETRIES})");
+
+        tokio::time::sleep(delay).await;
+    }
+}
@@ -28,3 +28,19 @@ pub async fn get_player_from_id<'c, E: sqlx::Executor<'c, Database = sqlx::MySql
         .await?;
     Ok(r)
 }
+
+/// Returns true if the player with the provided ID is shadow-banned.
+///
+/// A shadow-banned player can still play, but their records are hidden from the other players,
+/// and don't count in the leaderboards.
+pub async fn is_shadow_banned<'c, E: sqlx::Executor<'c, Database = sqlx::MySql>>(
+    db: E,
+    player_id: u32,
+) -> RecordsResult<bool> {
+    let r =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM current_shadow_bans WHERE player_id = ?)")
+            .bind(player_id)
+            .fetch_one(db)
+            .await?;
+    Ok(r)
+}
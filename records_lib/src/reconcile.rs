@@ -0,0 +1,208 @@
+//! This module contains the reconciliation of the Redis leaderboards with the MySQL/MariaDB
+//! database.
+//!
+//! The Redis leaderboards may drift from the records saved in the database (missing players,
+//! players that shouldn't be there, or outdated times). The reconciliation compares both versions
+//! of a leaderboard, and replaces the Redis one atomically if they differ.
+//!
+//! See the [`reconcile_map`] and [`reconcile_edition`] functions for more information.
+
+use std::collections::HashMap;
+
+use deadpool_redis::redis::{self, AsyncCommands as _};
+use serde::Serialize;
+
+use crate::{
+    error::RecordsResult,
+    event::{self, OptEvent},
+    leaderboard::{expected_scores, score_time, write_scores, LeaderboardBackend},
+    models,
+    redis_key::map_key,
+    DatabaseConnection,
+};
+
+/// The report of the reconciliation of a leaderboard.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileReport {
+    /// The ID of the map.
+    pub map_id: u32,
+    /// The handle of the event, if the leaderboard is bound to an event.
+    pub event_handle: Option<String>,
+    /// The ID of the event edition, if the leaderboard is bound to an event.
+    pub edition_id: Option<u32>,
+    /// The amount of players in the leaderboard, according to the database.
+    pub expected_count: usize,
+    /// The IDs of the players who were missing in the Redis leaderboard.
+    pub missing: Vec<u32>,
+    /// The IDs of the players who shouldn't be in the Redis leaderboard.
+    pub unexpected: Vec<u32>,
+    /// The IDs of the players who had a wrong time or position in the Redis leaderboard.
+    pub mismatched: Vec<u32>,
+    /// Whether the leaderboard kept being modified during the reconciliation, so it wasn't
+    /// checked. It is checked again by the next reconciliation.
+    pub skipped: bool,
+}
+
+impl ReconcileReport {
+    fn new(map_id: u32, event: OptEvent<'_, '_>, expected_count: usize) -> Self {
+        Self {
+            map_id,
+            event_handle: event.0.map(|(event, _)| event.handle.clone()),
+            edition_id: event.0.map(|(_, edition)| edition.id),
+            expected_count,
+            missing: Vec::new(),
+            unexpected: Vec::new(),
+            mismatched: Vec::new(),
+            skipped: false,
+        }
+    }
+
+    /// Returns whether the leaderboard was already consistent, meaning nothing was repaired.
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// The maximum amount of attempts to repair a leaderboard modified during its reconciliation.
+const RECONCILE_ATTEMPTS: usize = 3;
+
+/// Compares the Redis leaderboard of the map with the records saved in the database,
+/// and repairs it if they differ.
+///
+/// The repair replaces the whole leaderboard atomically. It is aborted if the leaderboard is
+/// modified in the meantime (e.g. by a new personal best), so it never erases a more recent
+/// time, and the reconciliation is retried. If the leaderboard keeps being modified, it is left
+/// as is, and the report is marked as [skipped](ReconcileReport::skipped).
+///
+/// If the leaderboards are stored in the MySQL/MariaDB database (see [`LeaderboardBackend`]),
+/// there is nothing to reconcile.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(db, event)))]
+pub async fn reconcile_map(
+    db: &mut DatabaseConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<ReconcileReport> {
+    if crate::env().leaderboard_backend == LeaderboardBackend::MySql {
+        let expected = expected_scores(&mut db.mysql_conn, map_id, event).await?;
+        return Ok(ReconcileReport::new(map_id, event, expected.len()));
+    }
+
+    let key = map_key(map_id, event);
+
+    for _ in 0..RECONCILE_ATTEMPTS {
+        // The leaderboard is watched before reading the records, so any time inserted after
+        // the read aborts the repair.
+        redis::cmd("WATCH")
+            .arg(&key)
+            .query_async::<_, ()>(&mut db.redis_conn)
+            .await?;
+
+        let result = reconcile_watched(db, map_id, event).await;
+
+        // The EXEC of the repair already unwatches the key, but not the other paths
+        redis::cmd("UNWATCH")
+            .query_async::<_, ()>(&mut db.redis_conn)
+            .await?;
+
+        if let Some(report) = result? {
+            return Ok(report);
+        }
+    }
+
+    let expected = expected_scores(&mut db.mysql_conn, map_id, event).await?;
+    let mut report = ReconcileReport::new(map_id, event, expected.len());
+    report.skipped = true;
+
+    #[cfg(feature = "tracing")]
+    tracing::warn!(report = ?report, "Skipped the repair of a leaderboard modified concurrently");
+
+    Ok(report)
+}
+
+/// Reconciles the leaderboard of the map, while it is watched on the Redis connection.
+///
+/// It returns `None` if the repair was aborted because the leaderboard was modified.
+async fn reconcile_watched(
+    db: &mut DatabaseConnection,
+    map_id: u32,
+    event: OptEvent<'_, '_>,
+) -> RecordsResult<Option<ReconcileReport>> {
+    let expected = expected_scores(&mut db.mysql_conn, map_id, event).await?;
+    let mut report = ReconcileReport::new(map_id, event, expected.len());
+
+    let actual: Vec<(u32, f64)> = db
+        .redis_conn
+        .zrange_withscores(map_key(map_id, event), 0, -1)
+        .await?;
+
+    let expected_map = expected
+        .iter()
+        .map(|(player_id, score)| (*player_id, score_time(*score)))
+        .collect::<HashMap<_, _>>();
+    let actual_map = actual
+        .iter()
+        .map(|(player_id, score)| (*player_id, score_time(*score as i64)))
+        .collect::<HashMap<_, _>>();
+
+    for (player_id, time) in &actual_map {
+        match expected_map.get(player_id) {
+            Some(expected) if expected != time => report.mismatched.push(*player_id),
+            Some(_) => (),
+            None => report.unexpected.push(*player_id),
+        }
+    }
+
+    report.missing.extend(
+        expected
+            .iter()
+            .filter(|(player_id, _)| !actual_map.contains_key(player_id))
+            .map(|(player_id, _)| *player_id),
+    );
+
+    // The tie-breaks of the scores may differ, as long as the players with the same time
+    // are in the same order
+    if report.is_consistent() {
+        report.mismatched.extend(
+            actual
+                .iter()
+                .zip(&expected)
+                .filter(|((actual, _), (expected, _))| actual != expected)
+                .map(|(_, (expected, _))| *expected),
+        );
+    }
+
+    if report.is_consistent() {
+        return Ok(Some(report));
+    }
+
+    report.unexpected.sort_unstable();
+    report.mismatched.sort_unstable();
+
+    if !write_scores(&mut db.redis_conn, map_id, event, &expected).await? {
+        return Ok(None);
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::warn!(report = ?report, "Repaired leaderboard");
+
+    Ok(Some(report))
+}
+
+/// Reconciles the Redis leaderboards of all the maps of the provided event edition.
+///
+/// It returns the report of each map.
+pub async fn reconcile_edition(
+    db: &mut DatabaseConnection,
+    event: &models::Event,
+    edition: &models::EventEdition,
+) -> RecordsResult<Vec<ReconcileReport>> {
+    let maps = event::event_edition_maps(&mut db.mysql_conn, event.id, edition.id).await?;
+    let event = OptEvent::new(event, edition);
+
+    let mut reports = Vec::with_capacity(maps.len());
+    for map in maps {
+        reports.push(reconcile_map(db, map.id, event).await?);
+    }
+
+    Ok(reports)
+}
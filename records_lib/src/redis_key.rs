@@ -4,7 +4,7 @@
 //! value we might want during a computation.
 //!
 //! For example, we store the ranks of the players in the Redis database as ZSETs. The key
-//! to the ZSET of a map with an ID `X` is `v3:lb:X`. To get it, we must use the [`alone_map_key`]
+//! to the ZSET of a map with an ID `X` is `v3:lb2:X`. To get it, we must use the [`alone_map_key`]
 //! function by providing the map ID.
 
 use core::fmt;
@@ -17,25 +17,62 @@ const V3_KEY_PREFIX: &str = "v3";
 
 const V3_MAPPACK_KEY_PREFIX: &str = "mappack";
 
-const V3_MAP_KEY_PREFIX: &str = "lb";
+/// The prefix of the leaderboards keys.
+///
+/// It was changed from `lb` when the scores started to include a tie-break (see
+/// [`Leaderboard`](crate::leaderboard::Leaderboard)), so the leaderboards with the old scores
+/// are ignored, and rebuilt from the MySQL/MariaDB database when accessed.
+const V3_MAP_KEY_PREFIX: &str = "lb2";
+
+/// The pattern of the keys of the leaderboards saved before the scores included a tie-break
+/// (see [`V3_MAP_KEY_PREFIX`]).
+///
+/// These leaderboards aren't used anymore, and should be removed once the API is updated.
+pub const LEGACY_MAP_KEYS_PATTERN: &str = "v3:lb:*";
 
 const V3_EVENT_KEY_PREFIX: &str = "event";
 
+const V3_LB_INTEGRITY_KEY_PREFIX: &str = "lb_integrity";
+
+const V3_STAGING_KEY_PREFIX: &str = "staging";
+
+const V3_RECORD_EVENTS_KEY_PREFIX: &str = "record_events";
+
+const V3_WEBHOOKS_KEY_PREFIX: &str = "webhooks";
+const V3_WEBHOOKS_RETRY: &str = "retry";
+const V3_WEBHOOKS_DEAD: &str = "dead";
+
+const V3_MX_CACHE_KEY_PREFIX: &str = "mx_cache";
+
+const V3_RATE_LIMIT_KEY_PREFIX: &str = "rate_limit";
+
 const V3_TOKEN_KEY_PREFIX: &str = "token";
 const V3_TOKEN_WEB_KEY_PREFIX: &str = "web";
 const V3_TOKEN_MP_KEY_PREFIX: &str = "mp";
+const V3_TOKEN_MPS_KEY_PREFIX: &str = "mps";
+
+const V3_OAUTH_STATE_KEY_PREFIX: &str = "oauth_state";
+const V3_OAUTH_STATE_CODE: &str = "code";
+const V3_OAUTH_STATE_RESULT: &str = "result";
 
 const V3_MAPPACK_TIME: &str = "time";
 const V3_MAPPACK_NB_MAP: &str = "nb_map";
 const V3_MAPPACK_MX_USERNAME: &str = "mx_username";
 const V3_MAPPACK_MX_NAME: &str = "mx_name";
 const V3_MAPPACK_MX_CREATED: &str = "mx_created";
+const V3_MAPPACK_SCORING: &str = "scoring";
 const V3_MAPPACK_LB: &str = "lb";
 const V3_MAPPACK_LB_RANK_AVG: &str = "rank_avg";
+const V3_MAPPACK_LB_POINTS: &str = "points";
 const V3_MAPPACK_LB_MAP_FINISHED: &str = "map_finished";
 const V3_MAPPACK_LB_WORST_RANK: &str = "worst_rank";
 const V3_MAPPACK_LB_RANKS: &str = "ranks";
 
+const V3_MAPPACK_LOCK: &str = "lock";
+const V3_MAPPACK_PENDING_MAPS: &str = "pending_maps";
+
+const V3_MAP_MAPPACKS_KEY_PREFIX: &str = "map_mappacks";
+
 const V3_MAPPACK_MAP_KEY_PREFIX: &str = "map";
 const V3_MAPPACK_MAP_LAST_RANK: &str = "last_rank";
 
@@ -103,6 +140,47 @@ create_key! {
     )
 }
 
+create_key! {
+    ///
+    /// This key holds the lock of a mappack while its scores are updated,
+    /// with the token of its holder.
+    struct MappackLockKey<'a => '_> = mappack_lock_key {
+        /// The mappack.
+        mappack: AnyMappackId<'a>,
+    }
+    |self, f| write!(
+        f,
+        "{V3_KEY_PREFIX}:{V3_MAPPACK_KEY_PREFIX}:{}:{V3_MAPPACK_LOCK}",
+        self.mappack.mappack_id()
+    )
+}
+
+create_key! {
+    ///
+    /// This key points to a SET containing the UIDs of the maps of a mappack with new records,
+    /// whose scores are waiting to be updated.
+    struct MappackPendingMapsKey<'a => '_> = mappack_pending_maps_key {
+        /// The mappack.
+        mappack: AnyMappackId<'a>,
+    }
+    |self, f| write!(
+        f,
+        "{V3_KEY_PREFIX}:{V3_MAPPACK_KEY_PREFIX}:{}:{V3_MAPPACK_PENDING_MAPS}",
+        self.mappack.mappack_id()
+    )
+}
+
+create_key! {
+    ///
+    /// This key points to a SET containing the IDs of the regular MX mappacks which contain
+    /// the map. It is the reverse of the [`mappack_key`] of these mappacks.
+    struct MapMappacksKey<'a => '_> = map_mappacks_key {
+        /// The UID of the map.
+        map_uid: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_MAP_MAPPACKS_KEY_PREFIX}:{}", self.map_uid)
+}
+
 create_key! {
     ///
     /// The map key (or alone map key, as it isn't bound to an event) returns a ZSET containing
@@ -196,6 +274,108 @@ pub fn map_key<'a>(map_id: u32, event: OptEvent<'a, 'a>) -> MapKey<'a> {
     }
 }
 
+create_key! {
+    ///
+    /// This key points to a hash containing the statistics of the last run of the leaderboards
+    /// integrity check: the amount of checked, repaired, skipped and failed leaderboards,
+    /// the duration of the run, and its date.
+    struct LbIntegrityKey = lb_integrity_key;;
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_LB_INTEGRITY_KEY_PREFIX}")
+}
+
+create_key! {
+    ///
+    /// This key is the name of the Pub/Sub channel on which the new records are published,
+    /// to be shared between the instances of the API.
+    struct RecordEventsChannel = record_events_channel;;
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_RECORD_EVENTS_KEY_PREFIX}")
+}
+
+create_key! {
+    ///
+    /// This key points to the stream of the webhook deliveries waiting to be sent.
+    struct WebhooksKey = webhooks_key;;
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_WEBHOOKS_KEY_PREFIX}")
+}
+
+create_key! {
+    ///
+    /// This key points to a ZSET containing the failed webhook deliveries,
+    /// scored by the timestamp (in milliseconds) of their next attempt.
+    struct WebhooksRetryKey = webhooks_retry_key;;
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_WEBHOOKS_KEY_PREFIX}:{V3_WEBHOOKS_RETRY}")
+}
+
+create_key! {
+    ///
+    /// This key points to the stream of the webhook deliveries which exhausted their attempts.
+    struct WebhooksDeadKey = webhooks_dead_key;;
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_WEBHOOKS_KEY_PREFIX}:{V3_WEBHOOKS_DEAD}")
+}
+
+create_key! {
+    ///
+    /// This key points to the cached response of a request to the ManiaExchange API
+    /// (see [`MxClient`](crate::mx::MxClient)).
+    struct MxCacheKey<'a => '_> = mx_cache_key {
+        /// The path of the request.
+        path: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_MX_CACHE_KEY_PREFIX}:{}", self.path)
+}
+
+create_key! {
+    ///
+    /// This key points to a hash containing the token bucket of a client of the API
+    /// on a group of routes: the amount of remaining tokens, and the timestamp (in milliseconds)
+    /// of its last update.
+    struct RateLimitKey<'a => '_> = rate_limit_key {
+        /// The name of the group of routes.
+        scope: &'a str,
+        /// The identifier of the client (its login, API key or IP address).
+        client: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_RATE_LIMIT_KEY_PREFIX}:{}:{}", self.scope, self.client)
+}
+
+/// The `StagingKey` Redis key.
+///
+/// This key wraps another key, to write its new content before replacing it atomically
+/// with a `RENAME`. The staging ID isolates the concurrent writes of the same key.
+#[derive(Debug)]
+pub struct StagingKey<'a, K> {
+    /// The ID of the staging namespace.
+    pub staging_id: &'a str,
+    /// The wrapped key.
+    pub key: K,
+}
+
+/// The constructor of the `StagingKey` Redis key.
+#[inline(always)]
+pub fn staging_key<K>(staging_id: &str, key: K) -> StagingKey<'_, K> {
+    StagingKey { staging_id, key }
+}
+
+impl<K: fmt::Display> ToRedisArgs for StagingKey<'_, K> {
+    #[inline(always)]
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        out.write_arg_fmt(self)
+    }
+}
+
+impl<K: fmt::Display> fmt::Display for StagingKey<'_, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{V3_KEY_PREFIX}:{V3_STAGING_KEY_PREFIX}:{}:{}",
+            self.staging_id, self.key
+        )
+    }
+}
+
 /// Tiny module used to help the specialization of the [`TokenKey`] Redis key.
 pub mod token_kind {
     /// The type of the constants.
@@ -222,6 +402,9 @@ pub fn web_token_key(login: &str) -> TokenKey<'_, { token_kind::WEB }> {
 }
 
 /// The constructor of the `TokenKey` Redis key in its "gamemode" version.
+///
+/// This key only contains the ManiaPlanet tokens generated before a player could have several
+/// of them. The new ones are stored in the [`MpTokensKey`] Redis key.
 #[inline(always)]
 pub fn mp_token_key(login: &str) -> TokenKey<'_, { token_kind::MP }> {
     TokenKey { login }
@@ -259,6 +442,54 @@ impl fmt::Display for TokenKey<'_, { token_kind::MP }> {
     }
 }
 
+create_key! {
+    ///
+    /// This key points to a Redis HASH containing the ManiaPlanet tokens of the player.
+    /// A player may have several ManiaPlanet tokens at the same time, one for each game server
+    /// they logged in with.
+    ///
+    /// The fields of the hash are the hashes of the tokens, and the values their metadata,
+    /// in JSON.
+    struct MpTokensKey<'a => '_> = mp_tokens_key {
+        /// The login of the player.
+        login: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_TOKEN_KEY_PREFIX}:{V3_TOKEN_MPS_KEY_PREFIX}:{}", self.login)
+}
+
+create_key! {
+    ///
+    /// This key points to the date when the `/player/get_token` endpoint received the OAuth
+    /// state string. It expires with the state.
+    struct OAuthStateKey<'a => '_> = oauth_state_key {
+        /// The state string.
+        state: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_OAUTH_STATE_KEY_PREFIX}:{}", self.state)
+}
+
+create_key! {
+    ///
+    /// This key points to a Redis LIST containing the messages sent by the `/player/give_token`
+    /// endpoint to the `/player/get_token` endpoint, for the provided OAuth state string.
+    struct OAuthCodeKey<'a => '_> = oauth_code_key {
+        /// The state string.
+        state: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_OAUTH_STATE_KEY_PREFIX}:{}:{V3_OAUTH_STATE_CODE}", self.state)
+}
+
+create_key! {
+    ///
+    /// This key points to a Redis LIST containing the messages sent by the `/player/get_token`
+    /// endpoint to the `/player/give_token` endpoint, for the provided OAuth state string.
+    struct OAuthResultKey<'a => '_> = oauth_result_key {
+        /// The state string.
+        state: &'a str,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_OAUTH_STATE_KEY_PREFIX}:{}:{V3_OAUTH_STATE_RESULT}", self.state)
+}
+
 create_key! {
     ///
     /// This key points to the timestamp of the last time the mappack content was updated.
@@ -322,6 +553,21 @@ create_key! {
     )
 }
 
+create_key! {
+    ///
+    /// This key points to the scoring strategy of the MX mappack. It is not used if the mappack
+    /// is bound to an event, because the strategy is then stored in the event edition.
+    ///
+    /// Unlike the other keys of the mappack, it doesn't have any time-to-live.
+    struct MappackScoringKey<'a => '_> = mappack_scoring_key {
+        /// The mappack.
+        mappack: AnyMappackId<'a>,
+    }
+    |self, f| write!(f, "{V3_KEY_PREFIX}:{V3_MAPPACK_KEY_PREFIX}:{}:{V3_MAPPACK_SCORING}",
+        self.mappack.mappack_id()
+    )
+}
+
 create_key! {
     ///
     /// This key points to a ZSET containing the IDs of the players sorted by their score.
@@ -352,6 +598,24 @@ create_key! {
     )
 }
 
+create_key! {
+    ///
+    /// This key points to the total points of the provided player in the provided mappack.
+    ///
+    /// It is only set if the scoring strategy of the mappack gives points.
+    struct MappackPlayerPoints<'a => '_> = mappack_player_points_key {
+        /// The mappack.
+        mappack: AnyMappackId<'a>,
+        /// The player ID.
+        player_id: u32,
+    }
+    |self, f| write!(
+        f,
+        "{V3_KEY_PREFIX}:{V3_MAPPACK_KEY_PREFIX}:{}:{V3_MAPPACK_LB}:{}:{V3_MAPPACK_LB_POINTS}",
+        self.mappack.mappack_id(), self.player_id
+    )
+}
+
 create_key! {
     ///
     /// This key points to the amount of finished maps of the provided player
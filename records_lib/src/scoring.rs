@@ -0,0 +1,429 @@
+//! This module contains the scoring strategies of the mappacks.
+//!
+//! A scoring strategy decides how the results of a player on each map of a mappack are turned
+//! into a score, and how the players are sorted in the mappack leaderboard. It is selected per
+//! event edition (stored in the `event_edition.scoring` column), or per regular MX mappack
+//! (stored in the Redis database, see [`mappack_scoring_key`][1]).
+//!
+//! The strategies are written as strings in the database, see the [`ScoringStrategy`] type
+//! for their format.
+//!
+//! [1]: crate::redis_key::mappack_scoring_key
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::event::MedalTimes;
+
+/// The default points table, used by the [`ScoringStrategy::PointsTable`] strategy when
+/// no table is provided.
+///
+/// The first player of a map gets the first amount of points, the second one the second amount,
+/// and so on. The players ranked beyond the table don't get any point.
+pub const DEFAULT_POINTS_TABLE: [u32; 10] = [25, 18, 15, 12, 10, 8, 6, 4, 2, 1];
+
+/// The amount of points given by the [`ScoringStrategy::MedalPoints`] strategy,
+/// for the champion, gold, silver and bronze medals respectively.
+pub const MEDAL_POINTS: [u32; 4] = [4, 3, 2, 1];
+
+/// The strategy used to calculate the scores of the players on a mappack.
+///
+/// In every strategy, the unfinished maps count as a rank of `last_rank + 1`
+/// in the rank average of the player.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ScoringStrategy {
+    /// The players are sorted by their amount of finished maps, then by their rank average.
+    ///
+    /// Written as `average_rank`.
+    #[default]
+    AverageRank,
+    /// The players get points depending on their rank on each map, from the provided table.
+    /// They are sorted by their total points.
+    ///
+    /// Written as `points_table`, with the default table ([`DEFAULT_POINTS_TABLE`]), or
+    /// `points_table:25,18,15,...` with a custom table.
+    PointsTable(Vec<u32>),
+    /// The players get points depending on the medal they got on each map (see [`MEDAL_POINTS`]).
+    /// They are sorted by their total points.
+    ///
+    /// The medal times are those of the event edition maps, so a regular MX mappack doesn't
+    /// give any point with this strategy.
+    ///
+    /// Written as `medal_points`.
+    MedalPoints,
+    /// Only the best `n` ranks of the players are taken into account. The players are sorted by
+    /// their amount of finished maps among them, then by their rank average on them.
+    ///
+    /// Written as `best_of:N`.
+    BestOf(usize),
+}
+
+/// The error returned when parsing an invalid scoring strategy.
+#[derive(thiserror::Error, Debug)]
+#[error("invalid scoring strategy: `{0}`")]
+pub struct InvalidScoringStrategy(pub String);
+
+impl FromStr for ScoringStrategy {
+    type Err = InvalidScoringStrategy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || InvalidScoringStrategy(s.to_owned());
+
+        match s.split_once(':') {
+            None => match s {
+                "average_rank" => Ok(Self::AverageRank),
+                "points_table" => Ok(Self::PointsTable(DEFAULT_POINTS_TABLE.to_vec())),
+                "medal_points" => Ok(Self::MedalPoints),
+                _ => Err(err()),
+            },
+            Some(("points_table", table)) => {
+                let table = table
+                    .split(',')
+                    .map(|points| points.trim().parse())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| err())?;
+                Ok(Self::PointsTable(table))
+            }
+            Some(("best_of", n)) => match n.trim().parse() {
+                Ok(n) if n > 0 => Ok(Self::BestOf(n)),
+                _ => Err(err()),
+            },
+            Some(_) => Err(err()),
+        }
+    }
+}
+
+impl TryFrom<String> for ScoringStrategy {
+    type Error = InvalidScoringStrategy;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ScoringStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AverageRank => f.write_str("average_rank"),
+            Self::PointsTable(table) if table[..] == DEFAULT_POINTS_TABLE => {
+                f.write_str("points_table")
+            }
+            Self::PointsTable(table) => {
+                f.write_str("points_table:")?;
+                for (i, points) in table.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{points}")?;
+                }
+                Ok(())
+            }
+            Self::MedalPoints => f.write_str("medal_points"),
+            Self::BestOf(n) => write!(f, "best_of:{n}"),
+        }
+    }
+}
+
+impl Serialize for ScoringStrategy {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// The result of a player on a map of a mappack.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MapResult {
+    /// The rank of the player, or `last_rank + 1` if they didn't finish the map.
+    pub rank: i32,
+    /// Whether the player finished the map.
+    pub finished: bool,
+    /// The time of the player, if they finished the map and it is known.
+    pub time: Option<i32>,
+    /// The medal times of the map, if any.
+    pub medal_times: Option<MedalTimes>,
+}
+
+/// The evaluation of the results of a player with a scoring strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Evaluation {
+    /// The rank average of the player, on the maps taken into account.
+    pub rank_avg: f64,
+    /// The amount of finished maps taken into account.
+    pub finished: usize,
+    /// The total points of the player, if the strategy gives points.
+    pub points: Option<f64>,
+}
+
+fn medal_points(time: i32, medal_times: &MedalTimes) -> u32 {
+    [
+        medal_times.champion_time,
+        medal_times.gold_time,
+        medal_times.silver_time,
+        medal_times.bronze_time,
+    ]
+    .into_iter()
+    .zip(MEDAL_POINTS)
+    .find_map(|(medal_time, points)| (time <= medal_time).then_some(points))
+    .unwrap_or(0)
+}
+
+fn rank_avg<'a, I>(results: I) -> f64
+where
+    I: IntoIterator<Item = &'a MapResult>,
+{
+    let (sum, count) = results.into_iter().fold((0., 0), |(sum, count), result| {
+        (sum + result.rank as f64, count + 1)
+    });
+    if count == 0 {
+        0.
+    } else {
+        sum / count as f64
+    }
+}
+
+impl ScoringStrategy {
+    /// Returns whether the strategy needs the medal times of the maps.
+    pub(crate) fn needs_medal_times(&self) -> bool {
+        matches!(self, Self::MedalPoints)
+    }
+
+    /// Evaluates the results of a player on each map of the mappack.
+    pub(crate) fn evaluate(&self, results: &[MapResult]) -> Evaluation {
+        let finished = results.iter().filter(|r| r.finished).count();
+
+        match self {
+            Self::AverageRank => Evaluation {
+                rank_avg: rank_avg(results),
+                finished,
+                points: None,
+            },
+            Self::PointsTable(table) => Evaluation {
+                rank_avg: rank_avg(results),
+                finished,
+                points: Some(
+                    results
+                        .iter()
+                        .filter(|r| r.finished)
+                        .filter_map(|r| table.get(r.rank as usize - 1))
+                        .map(|points| *points as f64)
+                        .sum(),
+                ),
+            },
+            Self::MedalPoints => Evaluation {
+                rank_avg: rank_avg(results),
+                finished,
+                points: Some(
+                    results
+                        .iter()
+                        .filter_map(|r| Some(medal_points(r.time?, r.medal_times.as_ref()?)))
+                        .map(|points| points as f64)
+                        .sum(),
+                ),
+            },
+            Self::BestOf(n) => {
+                let mut best = results.iter().collect::<Vec<_>>();
+                best.sort_by_key(|r| r.rank);
+                best.truncate(*n);
+                Evaluation {
+                    rank_avg: rank_avg(best.iter().copied()),
+                    finished: best.iter().filter(|r| r.finished).count(),
+                    points: None,
+                }
+            }
+        }
+    }
+
+    /// Compares two evaluations, the best one being the lowest.
+    pub(crate) fn compare(&self, a: &Evaluation, b: &Evaluation) -> Ordering {
+        let by_rank = b
+            .finished
+            .cmp(&a.finished)
+            .then_with(|| a.rank_avg.total_cmp(&b.rank_avg));
+
+        match (a.points, b.points) {
+            (Some(a), Some(b)) => b.total_cmp(&a).then(by_rank),
+            _ => by_rank,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{MapResult, ScoringStrategy, DEFAULT_POINTS_TABLE};
+    use crate::event::MedalTimes;
+
+    const MEDAL_TIMES: MedalTimes = MedalTimes {
+        bronze_time: 40_000,
+        silver_time: 30_000,
+        gold_time: 20_000,
+        champion_time: 10_000,
+    };
+
+    fn finished(rank: i32, time: i32) -> MapResult {
+        MapResult {
+            rank,
+            finished: true,
+            time: Some(time),
+            medal_times: Some(MEDAL_TIMES),
+        }
+    }
+
+    fn unfinished(last_rank: i32) -> MapResult {
+        MapResult {
+            rank: last_rank + 1,
+            finished: false,
+            time: None,
+            medal_times: Some(MEDAL_TIMES),
+        }
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for (s, strategy) in [
+            ("average_rank", ScoringStrategy::AverageRank),
+            (
+                "points_table",
+                ScoringStrategy::PointsTable(DEFAULT_POINTS_TABLE.to_vec()),
+            ),
+            (
+                "points_table:3,2,1",
+                ScoringStrategy::PointsTable(vec![3, 2, 1]),
+            ),
+            ("medal_points", ScoringStrategy::MedalPoints),
+            ("best_of:3", ScoringStrategy::BestOf(3)),
+        ] {
+            assert_eq!(s.parse::<ScoringStrategy>().unwrap(), strategy);
+            assert_eq!(strategy.to_string(), s);
+        }
+
+        for s in [
+            "",
+            "unknown",
+            "best_of:0",
+            "best_of:x",
+            "points_table:1,x",
+            "foo:1",
+        ] {
+            assert!(
+                s.parse::<ScoringStrategy>().is_err(),
+                "{s} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn average_rank() {
+        let strategy = ScoringStrategy::AverageRank;
+
+        // The unfinished map counts as the rank after the last player
+        let eval = strategy.evaluate(&[finished(1, 10_000), finished(2, 20_000), unfinished(5)]);
+        assert_eq!(eval.rank_avg, 3.);
+        assert_eq!(eval.finished, 2);
+        assert_eq!(eval.points, None);
+
+        // More finished maps is better, whatever the rank average
+        let more_finished = strategy.evaluate(&[finished(10, 10_000), finished(10, 10_000)]);
+        let better_avg = strategy.evaluate(&[finished(1, 10_000), unfinished(2)]);
+        assert_eq!(
+            strategy.compare(&more_finished, &better_avg),
+            Ordering::Less
+        );
+
+        // Then the lowest rank average is better
+        let worse_avg = strategy.evaluate(&[finished(2, 10_000), unfinished(2)]);
+        assert_eq!(strategy.compare(&better_avg, &worse_avg), Ordering::Less);
+        assert_eq!(strategy.compare(&better_avg, &better_avg), Ordering::Equal);
+    }
+
+    #[test]
+    fn points_table() {
+        let strategy = ScoringStrategy::PointsTable(vec![3, 2, 1]);
+
+        // The ranks beyond the table and the unfinished maps don't give any point
+        let eval = strategy.evaluate(&[
+            finished(1, 10_000),
+            finished(3, 10_000),
+            finished(4, 10_000),
+            unfinished(1),
+        ]);
+        assert_eq!(eval.points, Some(4.));
+        assert_eq!(eval.finished, 3);
+
+        // The most points is better, even with less finished maps
+        let more_points = strategy.evaluate(&[finished(1, 10_000), unfinished(1)]);
+        let more_finished = strategy.evaluate(&[finished(2, 10_000), finished(3, 10_000)]);
+        assert_eq!(more_points.points, more_finished.points);
+        // With the same points, the amount of finished maps decides
+        assert_eq!(
+            strategy.compare(&more_finished, &more_points),
+            Ordering::Less
+        );
+
+        let fewer_points = strategy.evaluate(&[finished(2, 10_000), unfinished(2)]);
+        assert_eq!(
+            strategy.compare(&more_points, &fewer_points),
+            Ordering::Less
+        );
+
+        let default = ScoringStrategy::PointsTable(DEFAULT_POINTS_TABLE.to_vec());
+        assert_eq!(default.evaluate(&[finished(1, 10_000)]).points, Some(25.));
+        assert_eq!(default.evaluate(&[finished(11, 10_000)]).points, Some(0.));
+    }
+
+    #[test]
+    fn medal_points() {
+        let strategy = ScoringStrategy::MedalPoints;
+        assert!(strategy.needs_medal_times());
+
+        // The medal times are inclusive
+        let points = |time| strategy.evaluate(&[finished(1, time)]).points;
+        assert_eq!(points(9_999), Some(4.));
+        assert_eq!(points(10_000), Some(4.));
+        assert_eq!(points(10_001), Some(3.));
+        assert_eq!(points(20_000), Some(3.));
+        assert_eq!(points(30_000), Some(2.));
+        assert_eq!(points(40_000), Some(1.));
+        assert_eq!(points(40_001), Some(0.));
+
+        // The maps without medal times and the unfinished maps don't give any point
+        let without_medals = MapResult {
+            medal_times: None,
+            ..finished(1, 1_000)
+        };
+        let eval = strategy.evaluate(&[finished(2, 15_000), without_medals, unfinished(3)]);
+        assert_eq!(eval.points, Some(3.));
+        assert_eq!(eval.finished, 2);
+    }
+
+    #[test]
+    fn best_of() {
+        let strategy = ScoringStrategy::BestOf(2);
+        assert!(!strategy.needs_medal_times());
+
+        // Only the 2 best ranks are taken into account
+        let eval = strategy.evaluate(&[
+            finished(5, 10_000),
+            finished(1, 10_000),
+            finished(3, 10_000),
+        ]);
+        assert_eq!(eval.rank_avg, 2.);
+        assert_eq!(eval.finished, 2);
+        assert_eq!(eval.points, None);
+
+        // An unfinished map can be among the best ranks
+        let eval = strategy.evaluate(&[finished(9, 10_000), unfinished(1), finished(1, 10_000)]);
+        assert_eq!(eval.rank_avg, 1.5);
+        assert_eq!(eval.finished, 1);
+
+        // With less maps than `n`, all of them are taken into account
+        let eval = ScoringStrategy::BestOf(5).evaluate(&[finished(2, 10_000), finished(4, 10_000)]);
+        assert_eq!(eval.rank_avg, 3.);
+        assert_eq!(eval.finished, 2);
+    }
+}
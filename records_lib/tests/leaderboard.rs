@@ -0,0 +1,211 @@
+//! Tests of the leaderboards, checking that the Redis and MySQL/MariaDB implementations
+//! give the same results after the same operations.
+//!
+//! They need a database, whose URLs are read from the `DATABASE_URL` and `REDIS_URL` environment
+//! variables, so they're ignored by default. To run them:
+//!
+//! ```sh
+//! DATABASE_URL=mysql://... REDIS_URL=redis://... cargo test -p records-lib --test leaderboard -- --ignored
+//! ```
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{NaiveDateTime, TimeDelta};
+use deadpool_redis::redis::AsyncCommands as _;
+use records_lib::{
+    event::OptEvent,
+    leaderboard::{Leaderboard, MySqlLeaderboard, RedisLeaderboard},
+    migrations,
+    redis_key::map_key,
+    Database,
+};
+
+/// The players of the test, with their records, in chronological order:
+/// (player index, time, seconds since the first record).
+const RECORDS: &[(usize, i32, i64)] = &[
+    (0, 30_000, 0),
+    (1, 25_000, 1),
+    // Same time as the second player, ranked after them
+    (2, 25_000, 2),
+    // Doesn't improve the record of the first player
+    (0, 31_000, 3),
+    (3, 40_000, 4),
+    // Improves to the same time as the others
+    (0, 25_000, 5),
+    (4, 20_000, 6),
+    (3, 25_000, 7),
+];
+
+async fn database() -> Database {
+    let mysql_url = std::env::var("DATABASE_URL").expect("DATABASE_URL should be set");
+    let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL should be set");
+
+    let mysql_pool = records_lib::get_mysql_pool(mysql_url)
+        .await
+        .expect("DATABASE_URL should be a valid MySQL/MariaDB URL");
+    let redis_pool =
+        records_lib::get_redis_pool(redis_url).expect("REDIS_URL should be a valid Redis URL");
+
+    migrations::migrate(&mysql_pool).await.unwrap();
+
+    Database {
+        mysql_pool,
+        redis_pool,
+    }
+}
+
+/// Checks that both leaderboards of the map give the same results.
+async fn assert_same(
+    redis: &mut RedisLeaderboard<'_>,
+    mysql: &mut MySqlLeaderboard<'_>,
+    map_id: u32,
+    player_ids: &[u32],
+    step: usize,
+) {
+    let event = OptEvent::default();
+
+    let count = redis.count(map_id, event).await.unwrap();
+    assert_eq!(
+        count,
+        mysql.count(map_id, event).await.unwrap(),
+        "count after step {step}"
+    );
+
+    for time in [19_000, 20_000, 25_000, 30_000, 31_000, 40_000, 50_000] {
+        assert_eq!(
+            redis.rank(map_id, event, time).await.unwrap(),
+            mysql.rank(map_id, event, time).await.unwrap(),
+            "rank of {time} after step {step}"
+        );
+        assert_eq!(
+            redis.better_count(map_id, event, time).await.unwrap(),
+            mysql.better_count(map_id, event, time).await.unwrap(),
+            "better count of {time} after step {step}"
+        );
+    }
+
+    for &player_id in player_ids {
+        assert_eq!(
+            redis.position(map_id, event, player_id).await.unwrap(),
+            mysql.position(map_id, event, player_id).await.unwrap(),
+            "position of player {player_id} after step {step}"
+        );
+    }
+
+    let len = count as u32;
+    for (start, end) in [(0, len), (0, 2), (1, 3), (2, len + 1), (len, len + 2)] {
+        for reversed in [false, true] {
+            assert_eq!(
+                redis
+                    .range(map_id, event, start, end, reversed)
+                    .await
+                    .unwrap(),
+                mysql
+                    .range(map_id, event, start, end, reversed)
+                    .await
+                    .unwrap(),
+                "range {start}..{end} (reversed: {reversed}) after step {step}"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a MySQL/MariaDB and a Redis database"]
+async fn redis_and_mysql_are_the_same() {
+    let db = database().await;
+
+    let suffix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let mut mysql_conn = db.mysql_pool.acquire().await.unwrap();
+
+    let mut player_ids = Vec::new();
+    for i in 0..5 {
+        let login = format!("lb_test_{suffix}_{i}");
+        let id = sqlx::query("INSERT INTO players (login, name) VALUES (?, ?)")
+            .bind(&login)
+            .bind(&login)
+            .execute(&mut *mysql_conn)
+            .await
+            .unwrap()
+            .last_insert_id();
+        player_ids.push(id as u32);
+    }
+
+    let map_id = sqlx::query("INSERT INTO maps (game_id, player_id, name) VALUES (?, ?, ?)")
+        .bind(format!("lb_test_{suffix}"))
+        .bind(player_ids[0])
+        .bind("Leaderboard test")
+        .execute(&mut *mysql_conn)
+        .await
+        .unwrap()
+        .last_insert_id() as u32;
+
+    let start = NaiveDateTime::parse_from_str("2024-08-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+    let mut conn = db.acquire().await.unwrap();
+    let mut redis = RedisLeaderboard::new(&mut conn);
+    let mut mysql = MySqlLeaderboard::new(&mut mysql_conn);
+
+    // Also compares the empty leaderboards
+    assert_same(&mut redis, &mut mysql, map_id, &player_ids, 0).await;
+
+    for (step, &(player, time, secs)) in RECORDS.iter().enumerate() {
+        let player_id = player_ids[player];
+        let record_date = start + TimeDelta::seconds(secs);
+
+        sqlx::query(
+            "INSERT INTO records (record_player_id, map_id, time, respawn_count, record_date)
+            VALUES (?, ?, ?, 0, ?)",
+        )
+        .bind(player_id)
+        .bind(map_id)
+        .bind(time)
+        .bind(record_date)
+        .execute(&db.mysql_pool)
+        .await
+        .unwrap();
+
+        redis
+            .insert(map_id, OptEvent::default(), player_id, time)
+            .await
+            .unwrap();
+
+        assert_same(&mut redis, &mut mysql, map_id, &player_ids, step + 1).await;
+    }
+
+    // The rebuilt leaderboard is the same as the one built incrementally
+    redis.rebuild(map_id, OptEvent::default()).await.unwrap();
+    assert_same(
+        &mut redis,
+        &mut mysql,
+        map_id,
+        &player_ids,
+        RECORDS.len() + 1,
+    )
+    .await;
+
+    conn.redis_conn
+        .del::<_, ()>(map_key(map_id, OptEvent::default()))
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM records WHERE map_id = ?")
+        .bind(map_id)
+        .execute(&db.mysql_pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM maps WHERE id = ?")
+        .bind(map_id)
+        .execute(&db.mysql_pool)
+        .await
+        .unwrap();
+    for id in player_ids {
+        sqlx::query("DELETE FROM players WHERE id = ?")
+            .bind(id)
+            .execute(&db.mysql_pool)
+            .await
+            .unwrap();
+    }
+}
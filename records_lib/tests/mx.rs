@@ -0,0 +1,41 @@
+//! Tests of the ManiaExchange client, with the fixtures of the `fixtures/mx` folder.
+
+use records_lib::{error::RecordsError, mx::MxClient};
+
+fn client() -> MxClient {
+    MxClient::from_fixtures(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mx"))
+}
+
+#[tokio::test]
+async fn mappack_maps() {
+    let maps = client().mappack_maps(1234, None).await.unwrap();
+
+    let uids: Vec<_> = maps.iter().map(|m| m.map_uid.as_str()).collect();
+    assert_eq!(uids, ["uid_first_map", "uid_second_map"]);
+    assert_eq!(maps[0].mx_id, 100);
+    assert_eq!(maps[1].author_login, "author_two");
+}
+
+#[tokio::test]
+async fn mappack_info() {
+    let info = client().mappack_info(1234).await.unwrap();
+
+    assert_eq!(info.username, "mappack_creator");
+    assert_eq!(info.name, "Fixture mappack");
+}
+
+#[tokio::test]
+async fn maps_info_skips_unknown_maps() {
+    let maps = client().maps_info(&[101, 999, 100]).await.unwrap();
+
+    let ids: Vec<_> = maps.iter().map(|m| m.mx_id).collect();
+    assert_eq!(ids, [101, 100]);
+    assert_eq!(maps[0].map_uid, "uid_second_map");
+}
+
+#[tokio::test]
+async fn unknown_mappack() {
+    let err = client().mappack_maps(999, None).await.unwrap_err();
+
+    assert!(matches!(err, RecordsError::MxFixture(..)));
+}
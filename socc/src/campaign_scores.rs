@@ -1,6 +1,9 @@
 use std::time::Duration;
 
-use deadpool_redis::{redis::AsyncCommands, Connection};
+use deadpool_redis::{
+    redis::{self, AsyncCommands},
+    Connection,
+};
 use records_lib::{
     event,
     mappack::{self, AnyMappackId},
@@ -34,15 +37,22 @@ async fn update_event_mappacks(conn: &mut DatabaseConnection) -> anyhow::Result<
 
             let mappack = AnyMappackId::Event(&event.event, &edition);
 
-            conn.redis_conn.del(mappack_key(mappack)).await?;
-
-            for map in event::event_edition_maps(&mut conn.mysql_conn, edition.event_id, edition.id)
-                .await?
-            {
-                conn.redis_conn
-                    .sadd(mappack_key(mappack), map.game_id)
+            let maps =
+                event::event_edition_maps(&mut conn.mysql_conn, edition.event_id, edition.id)
                     .await?;
+
+            // The set is replaced atomically, so the concurrent updates of the mappack
+            // never see it partially filled.
+            let mut pipe = redis::pipe();
+            pipe.atomic().del(mappack_key(mappack)).ignore();
+            if !maps.is_empty() {
+                pipe.sadd(
+                    mappack_key(mappack),
+                    maps.into_iter().map(|map| map.game_id).collect::<Vec<_>>(),
+                )
+                .ignore();
             }
+            pipe.query_async::<_, ()>(&mut conn.redis_conn).await?;
 
             update_mappack(conn, mappack).await?;
         }
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use deadpool_redis::{redis::AsyncCommands, Connection};
+use records_lib::{
+    error::RecordsResult,
+    event::{self, OptEvent},
+    reconcile::{self, ReconcileReport},
+    redis_key::lb_integrity_key,
+    DatabaseConnection,
+};
+use sqlx::{pool::PoolConnection, MySql};
+
+const PROCESS_DURATION_SECS: u64 = 3600; // Every hour
+pub const PROCESS_DURATION: Duration = Duration::from_secs(PROCESS_DURATION_SECS);
+
+#[derive(Default, Debug)]
+struct RunStats {
+    maps_checked: u64,
+    maps_repaired: u64,
+    maps_skipped: u64,
+    maps_failed: u64,
+}
+
+impl RunStats {
+    /// Counts the result of the reconciliation of a leaderboard.
+    ///
+    /// A failure is only logged, so the other leaderboards are still checked.
+    fn add(&mut self, map_id: u32, result: RecordsResult<ReconcileReport>) {
+        self.maps_checked += 1;
+        match result {
+            Ok(report) if report.skipped => self.maps_skipped += 1,
+            Ok(report) if !report.is_consistent() => self.maps_repaired += 1,
+            Ok(_) => (),
+            Err(e) => {
+                self.maps_failed += 1;
+                tracing::error!("Couldn't check the leaderboard of the map {map_id}: {e}");
+            }
+        }
+    }
+}
+
+async fn check_maps(conn: &mut DatabaseConnection, stats: &mut RunStats) -> anyhow::Result<()> {
+    let map_ids: Vec<u32> = sqlx::query_scalar("SELECT id FROM maps")
+        .fetch_all(&mut *conn.mysql_conn)
+        .await?;
+
+    for map_id in map_ids {
+        let result = reconcile::reconcile_map(conn, map_id, Default::default()).await;
+        stats.add(map_id, result);
+    }
+
+    Ok(())
+}
+
+async fn check_event_editions(
+    conn: &mut DatabaseConnection,
+    stats: &mut RunStats,
+) -> anyhow::Result<()> {
+    for event in event::event_list(&mut conn.mysql_conn).await? {
+        for edition in event::event_editions_list(&mut conn.mysql_conn, &event.handle).await? {
+            let maps =
+                event::event_edition_maps(&mut conn.mysql_conn, edition.event_id, edition.id)
+                    .await?;
+            let opt_event = OptEvent::new(&event.event, &edition);
+
+            for map in maps {
+                let result = reconcile::reconcile_map(conn, map.id, opt_event).await;
+                stats.add(map.id, result);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn check(
+    mysql_conn: PoolConnection<MySql>,
+    redis_conn: Connection,
+) -> anyhow::Result<()> {
+    let mut conn = DatabaseConnection {
+        mysql_conn,
+        redis_conn,
+    };
+
+    let start = Instant::now();
+    let mut stats = RunStats::default();
+
+    check_maps(&mut conn, &mut stats).await?;
+    check_event_editions(&mut conn, &mut stats).await?;
+
+    let duration = start.elapsed();
+    tracing::info!(
+        "Checked {} leaderboard(s), repaired {}, skipped {}, failed {} in {duration:?}",
+        stats.maps_checked,
+        stats.maps_repaired,
+        stats.maps_skipped,
+        stats.maps_failed
+    );
+
+    let date = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+
+    conn.redis_conn
+        .hset_multiple::<_, _, _, ()>(
+            lb_integrity_key(),
+            &[
+                ("maps_checked", stats.maps_checked),
+                ("maps_repaired", stats.maps_repaired),
+                ("maps_skipped", stats.maps_skipped),
+                ("maps_failed", stats.maps_failed),
+                ("duration_ms", duration.as_millis() as u64),
+                ("date", date),
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
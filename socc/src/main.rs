@@ -2,7 +2,8 @@
 //!
 //! The cache manager is a service that runs alongside the API. It fetches periodically the records
 //! in the database to calculate the scores in certain contexts, then store the results in the Redis
-//! database.
+//! database. It also checks periodically that the leaderboards stored in Redis match the records
+//! in the database, and repairs them if needed.
 
 use std::{future::Future, time::Duration};
 
@@ -15,6 +16,7 @@ use tokio::{task::JoinHandle, time};
 use tracing::info;
 
 mod campaign_scores;
+mod leaderboards_integrity;
 
 async fn handle<F, Fut>(
     mysql_pool: MySqlPool,
@@ -32,7 +34,10 @@ where
         interval.tick().await;
         let mysql_conn = mysql_pool.acquire().await?;
         let redis_conn = redis_pool.get().await?;
-        f(mysql_conn, redis_conn).await?;
+        // A failed run is retried on the next tick, instead of stopping the whole service
+        if let Err(e) = f(mysql_conn, redis_conn).await {
+            tracing::error!("{e:?}");
+        }
     }
 }
 
@@ -67,21 +72,34 @@ async fn main() -> anyhow::Result<()> {
     let redis_pool = records_lib::get_redis_pool(env.db_env.redis_url.redis_url)
         .context("When creating Redis pool")?;
 
-    let res = tokio::spawn(handle(
+    let campaign_scores = tokio::spawn(handle(
         mysql_pool.clone(),
         redis_pool.clone(),
         campaign_scores::PROCESS_DURATION,
         campaign_scores::update,
     ));
 
+    let leaderboards_integrity = tokio::spawn(handle(
+        mysql_pool.clone(),
+        redis_pool.clone(),
+        leaderboards_integrity::PROCESS_DURATION,
+        leaderboards_integrity::check,
+    ));
+
     info!("Spawned all tasks");
 
-    join(
-        res,
-        "When joining the campaign_scores::update task",
-        "When updating campaign scores",
-    )
-    .await?;
+    tokio::try_join!(
+        join(
+            campaign_scores,
+            "When joining the campaign_scores::update task",
+            "When updating campaign scores",
+        ),
+        join(
+            leaderboards_integrity,
+            "When joining the leaderboards_integrity::check task",
+            "When checking the leaderboards integrity",
+        ),
+    )?;
 
     Ok(())
 }